@@ -0,0 +1,23 @@
+#include <stdio.h>
+
+#include "export_fn.h"
+
+int main(void) {
+    printf("%d\n", square(7));
+    printf("%d %d\n", is_positive(3), is_positive(-3));
+    printf("%s\n", describe(1));
+    fflush(stdout);
+    report(4);
+
+    // the function works on a copy of the string
+    char word[] = "echo";
+    const char *echoed = echo(word);
+    word[0] = 'E';
+    printf("%s %s\n", echoed, word);
+
+    // lists are only borrowed by the functions they are passed to
+    void *numbers = countdown(4);
+    printf("%d %d\n", total(numbers), total(numbers));
+    viv_list_release(numbers);
+    return 0;
+}
@@ -0,0 +1,15 @@
+#include <stdbool.h>
+
+static int counter = 0;
+
+int add_ints(int a, int b) { return a + b; }
+
+bool is_even(int x) { return x % 2 == 0; }
+
+bool both(bool a, bool b) { return a && b; }
+
+const char *greeting(void) { return "hello from C"; }
+
+void set_counter(int value) { counter = value; }
+
+int get_counter(void) { return counter; }
@@ -0,0 +1,8 @@
+#include <stdio.h>
+
+#include "export_shared_lib.h"
+
+int main(void) {
+    printf("%d %d %d\n", triple(14), is_big(triple(40)), is_big(triple(20)));
+    return 0;
+}
@@ -1,7 +1,29 @@
 use std::collections::HashMap;
 
 use crate::{ir, CompilerOptions};
-use inkwell::{context::Context, IntPredicate};
+use inkwell::{context::Context, types::BasicType, IntPredicate};
+
+// fields of the header every list points to
+const LIST_LENGTH: u32 = 0;
+const LIST_CAPACITY: u32 = 1;
+const LIST_DATA: u32 = 2;
+const LIST_REFERENCES: u32 = 3;
+const LIST_HOLDS_STRINGS: u32 = 4;
+
+/// Bytes in front of the characters of every string, the first four hold its reference count
+const STRING_HEADER_SIZE: u64 = 8;
+
+/// Everything `compile_libc_definitions` declares in every object
+const LIBC_NAMES: &[&str] = &[
+    "printf", "abort", "fflush", "exit", "scanf", "getline", "atoi", "malloc", "realloc", "free",
+    "dprintf", "stdin", "strlen", "memcpy",
+];
+
+/// Whether an exported function with this name would clash with the entry point, libc or the runtime,
+/// which prefixes everything it defines with `viv_`
+pub fn is_reserved_name(name: &str) -> bool {
+    name == "main" || name.starts_with("viv_") || LIBC_NAMES.contains(&name)
+}
 
 pub struct CodeGen<'ctx> {
     context: &'ctx Context,
@@ -9,11 +31,23 @@ pub struct CodeGen<'ctx> {
     builder: inkwell::builder::Builder<'ctx>,
     fpm: inkwell::passes::PassManager<inkwell::module::Module<'ctx>>,
     local_vars: HashMap<ir::VariableIdentifier, inkwell::values::PointerValue<'ctx>>,
+    /// Globals are visible in every function, they are added to the locals when compiling one
+    globals: HashMap<ir::VariableIdentifier, inkwell::values::PointerValue<'ctx>>,
+    in_test: bool,
+    in_main: bool,
+    /// Variables of the current function holding a list or a string, released whenever the function returns
+    counted_locals: Vec<(inkwell::values::PointerValue<'ctx>, ir::VarType)>,
+    /// Report the lists and strings that are still allocated when main returns
+    leak_check: bool,
+    /// Symbols holding the body of exported functions that are called through a C wrapper
+    implementations: HashMap<String, String>,
+    /// Return types of the extern functions that aren't defined by the module, which only borrow the values passed to them
+    externs: HashMap<String, Option<ir::VarType>>,
 }
 
 impl<'ctx> CodeGen<'ctx> {
-    pub fn new(context: &'ctx Context) -> Self {
-        let module = context.create_module("main");
+    pub fn new(context: &'ctx Context, module_name: &str, leak_check: bool) -> Self {
+        let module = context.create_module(module_name);
         let builder = context.create_builder();
         let fpm = inkwell::passes::PassManager::create(());
 
@@ -41,18 +75,25 @@ impl<'ctx> CodeGen<'ctx> {
         fpm.add_loop_deletion_pass();
 
         Self {
-            context: &context,
+            context,
             module,
             builder,
             fpm,
             local_vars: HashMap::new(),
+            globals: HashMap::new(),
+            in_test: false,
+            in_main: false,
+            counted_locals: Vec::new(),
+            leak_check,
+            implementations: HashMap::new(),
+            externs: HashMap::new(),
         }
     }
 
     fn int_type(&self) -> inkwell::types::IntType<'ctx> {
-        use crate::IntWidth;
+        use crate::INT_WIDTH;
 
-        match IntWidth {
+        match INT_WIDTH {
             8 => self.context.i8_type(),
             16 => self.context.i16_type(),
             32 => self.context.i32_type(),
@@ -62,17 +103,1598 @@ impl<'ctx> CodeGen<'ctx> {
         }
     }
 
-    fn compile_libc_definitions(&mut self) {
-        let i32_type = self.context.i32_type();
-        let i8_type = self.context.i8_type();
-        let i8_ptr_type = i8_type.ptr_type(inkwell::AddressSpace::default());
-        let void_type = self.context.void_type();
+    fn compile_libc_definitions(&mut self) {
+        let i32_type = self.context.i32_type();
+        let i8_type = self.context.i8_type();
+        let i8_ptr_type = i8_type.ptr_type(inkwell::AddressSpace::default());
+        let void_type = self.context.void_type();
+
+        let printf_type = i32_type.fn_type(&[i8_ptr_type.into()], true);
+        self.module.add_function("printf", printf_type, None);
+
+        let abort_type = void_type.fn_type(&[], false);
+        self.module.add_function("abort", abort_type, None);
+
+        let fflush_type = i32_type.fn_type(&[i8_ptr_type.into()], false);
+        self.module.add_function("fflush", fflush_type, None);
+
+        let exit_type = void_type.fn_type(&[i32_type.into()], false);
+        self.module.add_function("exit", exit_type, None);
+
+        let scanf_type = i32_type.fn_type(&[i8_ptr_type.into()], true);
+        self.module.add_function("scanf", scanf_type, None);
+
+        let i64_type = self.context.i64_type();
+        let getline_type = i64_type.fn_type(
+            &[
+                i8_ptr_type
+                    .ptr_type(inkwell::AddressSpace::default())
+                    .into(),
+                i64_type.ptr_type(inkwell::AddressSpace::default()).into(),
+                i8_ptr_type.into(),
+            ],
+            false,
+        );
+        self.module.add_function("getline", getline_type, None);
+
+        let atoi_type = i32_type.fn_type(&[i8_ptr_type.into()], false);
+        self.module.add_function("atoi", atoi_type, None);
+
+        let malloc_type = i8_ptr_type.fn_type(&[i64_type.into()], false);
+        self.module.add_function("malloc", malloc_type, None);
+
+        let realloc_type = i8_ptr_type.fn_type(&[i8_ptr_type.into(), i64_type.into()], false);
+        self.module.add_function("realloc", realloc_type, None);
+
+        let free_type = void_type.fn_type(&[i8_ptr_type.into()], false);
+        self.module.add_function("free", free_type, None);
+
+        let dprintf_type = i32_type.fn_type(&[i32_type.into(), i8_ptr_type.into()], true);
+        self.module.add_function("dprintf", dprintf_type, None);
+
+        let strlen_type = i64_type.fn_type(&[i8_ptr_type.into()], false);
+        self.module.add_function("strlen", strlen_type, None);
+
+        let memcpy_type = i8_ptr_type.fn_type(
+            &[i8_ptr_type.into(), i8_ptr_type.into(), i64_type.into()],
+            false,
+        );
+        self.module.add_function("memcpy", memcpy_type, None);
+
+        // FILE* is opaque to us, so stdin is treated as a plain pointer
+        self.module
+            .add_global(i8_ptr_type, None, "stdin")
+            .set_linkage(inkwell::module::Linkage::External);
+    }
+
+    fn string_type(&self) -> inkwell::types::PointerType<'ctx> {
+        self.context
+            .i8_type()
+            .ptr_type(inkwell::AddressSpace::default())
+    }
+
+    /// A list is a pointer to its header
+    fn list_type(&self) -> inkwell::types::PointerType<'ctx> {
+        self.string_type()
+    }
+
+    /// Length, capacity, a pointer to the elements, the number of references to the list and whether
+    /// the elements are strings, which are released with the list. Every element takes 8 bytes
+    fn list_header_type(&self) -> inkwell::types::StructType<'ctx> {
+        let i32_type = self.context.i32_type();
+        self.context.struct_type(
+            &[
+                i32_type.into(),
+                i32_type.into(),
+                self.string_type().into(),
+                i32_type.into(),
+                self.context.bool_type().into(),
+            ],
+            false,
+        )
+    }
+
+    /// A char is stored as its code point
+    fn char_type(&self) -> inkwell::types::IntType<'ctx> {
+        self.context.i32_type()
+    }
+
+    fn llvm_type(&self, var_type: ir::VarType) -> inkwell::types::BasicTypeEnum<'ctx> {
+        match var_type {
+            ir::VarType::Int => self.int_type().into(),
+            ir::VarType::Boolean => self.context.bool_type().into(),
+            ir::VarType::String => self.string_type().into(),
+            ir::VarType::Char => self.char_type().into(),
+            ir::VarType::List(_) => self.list_type().into(),
+        }
+    }
+
+    /// Declares a function with the C calling convention, every function is declared before any body is compiled.
+    ///
+    /// Fails if the runtime already declares a function with that name but a different signature.
+    fn declare_function(
+        &mut self,
+        name: &str,
+        parameters: &[ir::VarType],
+        return_type: Option<ir::VarType>,
+    ) -> anyhow::Result<inkwell::values::FunctionValue<'ctx>> {
+        let parameter_types = parameters
+            .iter()
+            .map(|parameter| self.llvm_type(*parameter).into())
+            .collect::<Vec<_>>();
+        let function_type = match return_type {
+            Some(return_type) => self.llvm_type(return_type).fn_type(&parameter_types, false),
+            None => self.context.void_type().fn_type(&parameter_types, false),
+        };
+
+        // the function might already be declared for our own use, like printf or exit
+        if let Some(existing) = self.module.get_function(name) {
+            if existing.get_type() != function_type {
+                anyhow::bail!(
+                    "{name} is declared as {}, but the runtime already declares it as {}",
+                    function_type.print_to_string().to_string(),
+                    existing.get_type().print_to_string().to_string()
+                );
+            }
+            return Ok(existing);
+        }
+        let function = self.module.add_function(name, function_type, None);
+
+        // C expects a bool to be zero extended to a full byte
+        let zero_extend = self.context.create_enum_attribute(
+            inkwell::attributes::Attribute::get_named_enum_kind_id("zeroext"),
+            0,
+        );
+        for (index, parameter) in parameters.iter().enumerate() {
+            if parameter == &ir::VarType::Boolean {
+                function.add_attribute(
+                    inkwell::attributes::AttributeLoc::Param(index as u32),
+                    zero_extend,
+                );
+            }
+        }
+        if return_type == Some(ir::VarType::Boolean) {
+            function.add_attribute(inkwell::attributes::AttributeLoc::Return, zero_extend);
+        }
+
+        Ok(function)
+    }
+
+    fn compile_value(&self, value: &ir::Value) -> inkwell::values::BasicMetadataValueEnum<'ctx> {
+        match value {
+            ir::Value::Int(expression) => self.compile_int_expression(expression).into(),
+            ir::Value::Boolean(expression) => self.compile_bool_expression(expression).into(),
+            ir::Value::String(expression) => self.compile_string_expression(expression).into(),
+            ir::Value::Char(expression) => self.compile_char_expression(expression).into(),
+            ir::Value::List(expression) => self.compile_list_expression(expression).into(),
+        }
+    }
+
+    /// Calls a function, returns the result of the call which is only valid if the function returns a value.
+    ///
+    /// Functions defined in Viv take over the lists and strings passed to them. Extern functions only borrow
+    /// them for the call, a string they return belongs to C and is copied.
+    /// A list they return comes from an exported function, so it's owned by the caller.
+    fn compile_call(&self, call: &ir::Call) -> inkwell::values::BasicValueEnum<'ctx> {
+        let arguments = call
+            .arguments
+            .iter()
+            .map(|argument| self.compile_value(argument))
+            .collect::<Vec<_>>();
+        let name = self
+            .implementations
+            .get(&call.function)
+            .unwrap_or(&call.function);
+        let function = self.module.get_function(name).unwrap();
+
+        let result = self
+            .builder
+            .build_call(function, &arguments, "Call")
+            .try_as_basic_value()
+            .left()
+            .unwrap_or_else(|| self.context.i32_type().const_zero().into());
+        let Some(return_type) = self.externs.get(&call.function) else {
+            return result;
+        };
+
+        for (argument, value) in call.arguments.iter().zip(arguments) {
+            match argument {
+                ir::Value::String(_) => self.compile_string_release(value.into_pointer_value()),
+                ir::Value::List(_) => self.compile_list_release(value.into_pointer_value()),
+                _ => {}
+            }
+        }
+        match return_type {
+            Some(ir::VarType::String) => {
+                self.compile_builtin_call("viv_string_from_c", &[result.into()])
+            }
+            _ => result,
+        }
+    }
+
+    fn compile_runtime_definitions(&mut self) {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let string_type = self.string_type();
+
+        // main stores its arguments here so the builtins can reach them from any function,
+        // they are weak so every object file refers to the same globals once linked together
+        let argc = self.module.add_global(i32_type, None, "viv_argc");
+        argc.set_linkage(inkwell::module::Linkage::WeakAny);
+        argc.set_initializer(&i32_type.const_int(0, false));
+        let argv_type = string_type.ptr_type(inkwell::AddressSpace::default());
+        let argv = self.module.add_global(argv_type, None, "viv_argv");
+        argv.set_linkage(inkwell::module::Linkage::WeakAny);
+        argv.set_initializer(&argv_type.const_null());
+
+        self.compile_string_runtime();
+
+        // viv_read_int() -> int, aborts at the end of input or when the input isn't an integer
+        let read_int = self.module.add_function(
+            "viv_read_int",
+            self.int_type().fn_type(&[], false),
+            Some(inkwell::module::Linkage::LinkOnceODR),
+        );
+        let entry_block = self.context.append_basic_block(read_int, "entry");
+        let fail_block = self.context.append_basic_block(read_int, "invalid_input");
+        let ok_block = self.context.append_basic_block(read_int, "valid_input");
+
+        self.builder.position_at_end(entry_block);
+        let result = self.builder.build_alloca(self.int_type(), "result");
+        self.builder
+            .build_store(result, self.int_type().const_int(0, false));
+        let format_string = self
+            .builder
+            .build_global_string_ptr("%d", "Read_Int_Format")
+            .as_pointer_value();
+        let matched = self
+            .builder
+            .build_call(
+                self.module.get_function("scanf").unwrap(),
+                &[format_string.into(), result.into()],
+                "scanf",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        // scanf returns the number of values it read, or EOF
+        let is_valid = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            matched,
+            i32_type.const_int(1, false),
+            "is_valid",
+        );
+        self.builder
+            .build_conditional_branch(is_valid, ok_block, fail_block);
+
+        self.builder.position_at_end(fail_block);
+        let format_string = self
+            .builder
+            .build_global_string_ptr("Expected an integer on stdin\n", "Read_Int_Error")
+            .as_pointer_value();
+        self.builder.build_call(
+            self.module.get_function("printf").unwrap(),
+            &[format_string.into()],
+            "printf",
+        );
+        self.compile_abort();
+
+        self.builder.position_at_end(ok_block);
+        let value = self.builder.build_load(self.int_type(), result, "value");
+        self.builder.build_return(Some(&value));
+
+        // viv_read_line() -> string, every line is copied into a new string and getline's buffer is freed,
+        // at the end of input an empty string is returned instead
+        let read_line = self.module.add_function(
+            "viv_read_line",
+            string_type.fn_type(&[], false),
+            Some(inkwell::module::Linkage::LinkOnceODR),
+        );
+        let entry_block = self.context.append_basic_block(read_line, "entry");
+        let eof_block = self.context.append_basic_block(read_line, "eof");
+        let check_block = self.context.append_basic_block(read_line, "check_newline");
+        let strip_block = self.context.append_basic_block(read_line, "strip_newline");
+        let done_block = self.context.append_basic_block(read_line, "done");
+
+        self.builder.position_at_end(entry_block);
+        let line = self.builder.build_alloca(string_type, "line");
+        self.builder.build_store(line, string_type.const_null());
+        let size = self.builder.build_alloca(i64_type, "size");
+        self.builder.build_store(size, i64_type.const_int(0, false));
+        let stdin = self.builder.build_load(
+            string_type,
+            self.module.get_global("stdin").unwrap().as_pointer_value(),
+            "stdin",
+        );
+        let length = self
+            .builder
+            .build_call(
+                self.module.get_function("getline").unwrap(),
+                &[line.into(), size.into(), stdin.into()],
+                "getline",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let is_eof = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            length,
+            i64_type.const_int(1, false),
+            "is_eof",
+        );
+        self.builder
+            .build_conditional_branch(is_eof, eof_block, check_block);
+
+        self.builder.position_at_end(eof_block);
+        // getline allocates a buffer even when it fails
+        let buffer = self.builder.build_load(string_type, line, "buffer");
+        self.builder.build_call(
+            self.module.get_function("free").unwrap(),
+            &[buffer.into()],
+            "free",
+        );
+        let empty = self.compile_constant_string("", "Empty_String");
+        self.builder.build_return(Some(&empty));
+
+        self.builder.position_at_end(check_block);
+        let line = self
+            .builder
+            .build_load(string_type, line, "line")
+            .into_pointer_value();
+        let last_index =
+            self.builder
+                .build_int_sub(length, i64_type.const_int(1, false), "last_index");
+        let last_char = unsafe {
+            self.builder
+                .build_gep(self.context.i8_type(), line, &[last_index], "last_char")
+        };
+        let last_value = self
+            .builder
+            .build_load(self.context.i8_type(), last_char, "last_value")
+            .into_int_value();
+        let is_newline = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            last_value,
+            self.context.i8_type().const_int('\n' as u64, false),
+            "is_newline",
+        );
+        self.builder
+            .build_conditional_branch(is_newline, strip_block, done_block);
+
+        self.builder.position_at_end(strip_block);
+        self.builder
+            .build_store(last_char, self.context.i8_type().const_int(0, false));
+        self.builder.build_unconditional_branch(done_block);
+
+        self.builder.position_at_end(done_block);
+        let string = self.compile_builtin_call("viv_string_from_c", &[line.into()]);
+        self.builder.build_call(
+            self.module.get_function("free").unwrap(),
+            &[line.into()],
+            "free",
+        );
+        self.builder.build_return(Some(&string));
+
+        // viv_arg(int) -> string, a copy of the argument
+        let arg = self.module.add_function(
+            "viv_arg",
+            string_type.fn_type(&[self.int_type().into()], false),
+            Some(inkwell::module::Linkage::LinkOnceODR),
+        );
+        let entry_block = self.context.append_basic_block(arg, "entry");
+        let fail_block = self.context.append_basic_block(arg, "out_of_range");
+        let ok_block = self.context.append_basic_block(arg, "in_range");
+
+        self.builder.position_at_end(entry_block);
+        let index = arg.get_nth_param(0).unwrap().into_int_value();
+        let count = self.compile_arg_count();
+        // an unsigned compare also rejects negative indexes
+        let in_range = self
+            .builder
+            .build_int_compare(IntPredicate::ULT, index, count, "in_range");
+        self.builder
+            .build_conditional_branch(in_range, ok_block, fail_block);
+
+        self.builder.position_at_end(fail_block);
+        let format_string = self
+            .builder
+            .build_global_string_ptr("Argument index %d out of range\n", "Arg_Error")
+            .as_pointer_value();
+        self.builder.build_call(
+            self.module.get_function("printf").unwrap(),
+            &[format_string.into(), index.into()],
+            "printf",
+        );
+        self.compile_abort();
+
+        self.builder.position_at_end(ok_block);
+        let argv = self
+            .builder
+            .build_load(argv_type, argv.as_pointer_value(), "argv")
+            .into_pointer_value();
+        // skip the program name
+        let index = self
+            .builder
+            .build_int_add(index, self.int_type().const_int(1, false), "index");
+        let pointer = unsafe { self.builder.build_gep(string_type, argv, &[index], "arg") };
+        let value = self.builder.build_load(string_type, pointer, "value");
+        let string = self.compile_builtin_call("viv_string_from_c", &[value.into()]);
+        self.builder.build_return(Some(&string));
+
+        self.compile_list_runtime();
+        self.compile_char_runtime();
+    }
+
+    fn compile_char_runtime(&mut self) {
+        let char_type = self.char_type();
+        let i8_type = self.context.i8_type();
+        let string_type = self.string_type();
+
+        // viv_int_to_char(int) -> char
+        let int_to_char = self.module.add_function(
+            "viv_int_to_char",
+            char_type.fn_type(&[self.int_type().into()], false),
+            Some(inkwell::module::Linkage::LinkOnceODR),
+        );
+        let entry_block = self.context.append_basic_block(int_to_char, "entry");
+        let fail_block = self.context.append_basic_block(int_to_char, "invalid");
+        let ok_block = self.context.append_basic_block(int_to_char, "valid");
+
+        self.builder.position_at_end(entry_block);
+        let code = int_to_char.get_nth_param(0).unwrap().into_int_value();
+        // an unsigned compare also rejects negative codes
+        let in_range = self.builder.build_int_compare(
+            IntPredicate::ULT,
+            code,
+            self.int_type().const_int(0x110000, false),
+            "in_range",
+        );
+        // surrogates are code points, but not unicode scalar values
+        let surrogate_offset =
+            self.builder
+                .build_int_sub(code, self.int_type().const_int(0xD800, false), "offset");
+        let is_surrogate = self.builder.build_int_compare(
+            IntPredicate::ULT,
+            surrogate_offset,
+            self.int_type().const_int(0x800, false),
+            "is_surrogate",
+        );
+        let not_surrogate = self.builder.build_not(is_surrogate, "not_surrogate");
+        let valid = self.builder.build_and(in_range, not_surrogate, "valid");
+        self.builder
+            .build_conditional_branch(valid, ok_block, fail_block);
+
+        self.builder.position_at_end(fail_block);
+        let format_string = self
+            .builder
+            .build_global_string_ptr("Invalid char code %d\n", "Char_Error")
+            .as_pointer_value();
+        self.builder.build_call(
+            self.module.get_function("printf").unwrap(),
+            &[format_string.into(), code.into()],
+            "printf",
+        );
+        self.compile_abort();
+
+        self.builder.position_at_end(ok_block);
+        let value = self
+            .builder
+            .build_int_truncate_or_bit_cast(code, char_type, "char");
+        self.builder.build_return(Some(&value));
+
+        // viv_encode_char(char, buffer) -> buffer, writes the utf-8 bytes of the char followed by a
+        // nul, the buffer has room for 5 bytes
+        let encode_char = self.module.add_function(
+            "viv_encode_char",
+            string_type.fn_type(&[char_type.into(), string_type.into()], false),
+            Some(inkwell::module::Linkage::LinkOnceODR),
+        );
+        let entry_block = self.context.append_basic_block(encode_char, "entry");
+        let code = encode_char.get_nth_param(0).unwrap().into_int_value();
+        let buffer = encode_char.get_nth_param(1).unwrap().into_pointer_value();
+
+        // the first byte holds the highest bits after a marker for the length, the others hold
+        // 6 bits each after 0b10
+        let lengths: [(u64, u64, u64); 4] = [
+            (0x80, 0x00, 0x7F),
+            (0x800, 0xC0, 0x1F),
+            (0x10000, 0xE0, 0x0F),
+            (0x110000, 0xF0, 0x07),
+        ];
+        let mut check_block = entry_block;
+        for (length, (limit, marker, mask)) in lengths.into_iter().enumerate() {
+            let length = length + 1;
+            let encode_block = self
+                .context
+                .append_basic_block(encode_char, &format!("encode_{length}"));
+
+            self.builder.position_at_end(check_block);
+            if length < lengths.len() {
+                check_block = self
+                    .context
+                    .append_basic_block(encode_char, &format!("check_{}", length + 1));
+                let fits = self.builder.build_int_compare(
+                    IntPredicate::ULT,
+                    code,
+                    char_type.const_int(limit, false),
+                    "fits",
+                );
+                self.builder
+                    .build_conditional_branch(fits, encode_block, check_block);
+            } else {
+                self.builder.build_unconditional_branch(encode_block);
+            }
+
+            self.builder.position_at_end(encode_block);
+            for index in 0..length {
+                let (marker, mask) = if index == 0 {
+                    (marker, mask)
+                } else {
+                    (0x80, 0x3F)
+                };
+                let shift = 6 * (length - 1 - index) as u64;
+                let bits = self.builder.build_right_shift(
+                    code,
+                    char_type.const_int(shift, false),
+                    false,
+                    "bits",
+                );
+                let bits = self
+                    .builder
+                    .build_and(bits, char_type.const_int(mask, false), "bits");
+                let byte = self
+                    .builder
+                    .build_or(bits, char_type.const_int(marker, false), "byte");
+                let byte = self.builder.build_int_truncate(byte, i8_type, "byte");
+                let pointer = unsafe {
+                    self.builder.build_gep(
+                        i8_type,
+                        buffer,
+                        &[self.int_type().const_int(index as u64, false)],
+                        "byte_pointer",
+                    )
+                };
+                self.builder.build_store(pointer, byte);
+            }
+            let end = unsafe {
+                self.builder.build_gep(
+                    i8_type,
+                    buffer,
+                    &[self.int_type().const_int(length as u64, false)],
+                    "end",
+                )
+            };
+            self.builder.build_store(end, i8_type.const_zero());
+            self.builder.build_return(Some(&buffer));
+        }
+    }
+
+    /// Allocates stack space in the entry block of the current function, so it is only allocated
+    /// once even when the code using it runs in a loop
+    fn build_entry_alloca(
+        &self,
+        var_type: impl BasicType<'ctx>,
+        name: &str,
+    ) -> inkwell::values::PointerValue<'ctx> {
+        let function = self
+            .builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent())
+            .unwrap();
+        let entry_block = function.get_first_basic_block().unwrap();
+
+        let builder = self.context.create_builder();
+        match entry_block.get_first_instruction() {
+            Some(instruction) => builder.position_before(&instruction),
+            None => builder.position_at_end(entry_block),
+        }
+        builder.build_alloca(var_type, name)
+    }
+
+    fn list_field(
+        &self,
+        list: inkwell::values::PointerValue<'ctx>,
+        field: u32,
+    ) -> inkwell::values::PointerValue<'ctx> {
+        self.builder
+            .build_struct_gep(self.list_header_type(), list, field, "List_Field")
+            .unwrap()
+    }
+
+    /// Checks the index in a runtime function, returning a pointer to the element at it
+    fn compile_list_element_pointer(
+        &self,
+        function: inkwell::values::FunctionValue<'ctx>,
+        list: inkwell::values::PointerValue<'ctx>,
+        index: inkwell::values::IntValue<'ctx>,
+    ) -> inkwell::values::PointerValue<'ctx> {
+        let i32_type = self.context.i32_type();
+        let fail_block = self.context.append_basic_block(function, "out_of_range");
+        let ok_block = self.context.append_basic_block(function, "in_range");
+
+        let length = self
+            .builder
+            .build_load(i32_type, self.list_field(list, LIST_LENGTH), "length")
+            .into_int_value();
+        // an unsigned compare also rejects negative indexes
+        let in_range = self
+            .builder
+            .build_int_compare(IntPredicate::ULT, index, length, "in_range");
+        self.builder
+            .build_conditional_branch(in_range, ok_block, fail_block);
+
+        self.builder.position_at_end(fail_block);
+        let format_string = self
+            .builder
+            .build_global_string_ptr(
+                "List index %d out of range for length %d\n",
+                "List_Index_Error",
+            )
+            .as_pointer_value();
+        self.builder.build_call(
+            self.module.get_function("printf").unwrap(),
+            &[format_string.into(), index.into(), length.into()],
+            "printf",
+        );
+        self.compile_abort();
+
+        self.builder.position_at_end(ok_block);
+        let data = self
+            .builder
+            .build_load(self.string_type(), self.list_field(list, LIST_DATA), "data")
+            .into_pointer_value();
+        unsafe {
+            self.builder
+                .build_gep(self.context.i64_type(), data, &[index], "element")
+        }
+    }
+
+    /// Adds `delta` to an i32 in memory
+    fn compile_counter_add(&self, pointer: inkwell::values::PointerValue<'ctx>, delta: i64) {
+        let i32_type = self.context.i32_type();
+        let value = self
+            .builder
+            .build_load(i32_type, pointer, "count")
+            .into_int_value();
+        let value =
+            self.builder
+                .build_int_add(value, i32_type.const_int(delta as u64, true), "count");
+        self.builder.build_store(pointer, value);
+    }
+
+    /// The header in front of the characters of a string
+    fn string_header(
+        &self,
+        string: inkwell::values::PointerValue<'ctx>,
+    ) -> inkwell::values::PointerValue<'ctx> {
+        let offset = self
+            .context
+            .i64_type()
+            .const_int(-(STRING_HEADER_SIZE as i64) as u64, true);
+        unsafe {
+            self.builder
+                .build_gep(self.context.i8_type(), string, &[offset], "header")
+        }
+    }
+
+    /// A string that is never freed, it has the header of other strings with a reference count of -1
+    fn compile_constant_string(
+        &self,
+        string: &str,
+        name: &str,
+    ) -> inkwell::values::PointerValue<'ctx> {
+        let i32_type = self.context.i32_type();
+        let value = self.context.const_struct(
+            &[
+                i32_type.const_all_ones().into(),
+                i32_type.const_zero().into(),
+                self.context.const_string(string.as_bytes(), true).into(),
+            ],
+            false,
+        );
+        let global = self.module.add_global(value.get_type(), None, name);
+        global.set_linkage(inkwell::module::Linkage::Private);
+        global.set_constant(true);
+        global.set_unnamed_addr(true);
+        global.set_initializer(&value);
+        unsafe {
+            global.as_pointer_value().const_in_bounds_gep(
+                value.get_type(),
+                &[i32_type.const_zero(), i32_type.const_int(2, false)],
+            )
+        }
+    }
+
+    /// Strings point to their characters, which follow a header of `STRING_HEADER_SIZE` bytes holding the
+    /// number of references to the string, so they can be passed to C as they are.
+    ///
+    /// A string is freed when the last reference to it is released, `viv_live_strings` counts the
+    /// strings that haven't been freed yet for the leak check
+    fn compile_string_runtime(&mut self) {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let string_type = self.string_type();
+        let void_type = self.context.void_type();
+        let linkage = Some(inkwell::module::Linkage::LinkOnceODR);
+        let header_size = i64_type.const_int(STRING_HEADER_SIZE, false);
+
+        // shared by every object file, like the runtime functions
+        let live_strings = self.module.add_global(i32_type, None, "viv_live_strings");
+        live_strings.set_linkage(inkwell::module::Linkage::LinkOnceODR);
+        live_strings.set_initializer(&i32_type.const_int(0, false));
+        let live_strings = live_strings.as_pointer_value();
+
+        // viv_string_from_c(chars) -> string, copies a string from C into a new string
+        let from_c = self.module.add_function(
+            "viv_string_from_c",
+            string_type.fn_type(&[string_type.into()], false),
+            linkage,
+        );
+        self.builder
+            .position_at_end(self.context.append_basic_block(from_c, "entry"));
+        let chars = from_c.get_nth_param(0).unwrap().into_pointer_value();
+        let length = self
+            .compile_builtin_call("strlen", &[chars.into()])
+            .into_int_value();
+        // with room for the terminating zero
+        let size = self
+            .builder
+            .build_int_add(length, i64_type.const_int(1, false), "size");
+        let allocation_size = self
+            .builder
+            .build_int_add(size, header_size, "allocation_size");
+        let header = self
+            .compile_builtin_call("malloc", &[allocation_size.into()])
+            .into_pointer_value();
+        self.builder
+            .build_store(header, i32_type.const_int(1, false));
+        let string = unsafe {
+            self.builder
+                .build_gep(self.context.i8_type(), header, &[header_size], "string")
+        };
+        self.compile_builtin_call("memcpy", &[string.into(), chars.into(), size.into()]);
+        self.compile_counter_add(live_strings, 1);
+        self.builder.build_return(Some(&string));
+
+        // viv_string_to_c(string) -> chars, copies a string into memory C frees with free
+        let to_c = self.module.add_function(
+            "viv_string_to_c",
+            string_type.fn_type(&[string_type.into()], false),
+            linkage,
+        );
+        self.builder
+            .position_at_end(self.context.append_basic_block(to_c, "entry"));
+        let string = to_c.get_nth_param(0).unwrap().into_pointer_value();
+        let length = self
+            .compile_builtin_call("strlen", &[string.into()])
+            .into_int_value();
+        let size = self
+            .builder
+            .build_int_add(length, i64_type.const_int(1, false), "size");
+        let chars = self.compile_builtin_call("malloc", &[size.into()]);
+        self.compile_builtin_call("memcpy", &[chars.into(), string.into(), size.into()]);
+        self.builder.build_return(Some(&chars));
+
+        // viv_string_retain(string)
+        let retain = self.module.add_function(
+            "viv_string_retain",
+            void_type.fn_type(&[string_type.into()], false),
+            linkage,
+        );
+        let entry_block = self.context.append_basic_block(retain, "entry");
+        let counted_block = self.context.append_basic_block(retain, "counted");
+        let done_block = self.context.append_basic_block(retain, "done");
+
+        self.builder.position_at_end(entry_block);
+        let header = self.string_header(retain.get_nth_param(0).unwrap().into_pointer_value());
+        let references = self
+            .builder
+            .build_load(i32_type, header, "references")
+            .into_int_value();
+        let is_counted = self.builder.build_int_compare(
+            IntPredicate::SGE,
+            references,
+            i32_type.const_int(0, false),
+            "is_counted",
+        );
+        self.builder
+            .build_conditional_branch(is_counted, counted_block, done_block);
+
+        self.builder.position_at_end(counted_block);
+        self.compile_counter_add(header, 1);
+        self.builder.build_unconditional_branch(done_block);
+
+        self.builder.position_at_end(done_block);
+        self.builder.build_return(None);
+
+        // viv_string_release(string), variables that were never assigned hold null
+        let release = self.module.add_function(
+            "viv_string_release",
+            void_type.fn_type(&[string_type.into()], false),
+            linkage,
+        );
+        let entry_block = self.context.append_basic_block(release, "entry");
+        let check_block = self.context.append_basic_block(release, "check_constant");
+        let release_block = self.context.append_basic_block(release, "release");
+        let free_block = self.context.append_basic_block(release, "free");
+        let done_block = self.context.append_basic_block(release, "done");
+
+        self.builder.position_at_end(entry_block);
+        let string = release.get_nth_param(0).unwrap().into_pointer_value();
+        let is_null = self.builder.build_is_null(string, "is_null");
+        self.builder
+            .build_conditional_branch(is_null, done_block, check_block);
+
+        self.builder.position_at_end(check_block);
+        let header = self.string_header(string);
+        let references = self
+            .builder
+            .build_load(i32_type, header, "references")
+            .into_int_value();
+        let is_counted = self.builder.build_int_compare(
+            IntPredicate::SGE,
+            references,
+            i32_type.const_int(0, false),
+            "is_counted",
+        );
+        self.builder
+            .build_conditional_branch(is_counted, release_block, done_block);
+
+        self.builder.position_at_end(release_block);
+        self.compile_counter_add(header, -1);
+        let references = self
+            .builder
+            .build_load(i32_type, header, "references")
+            .into_int_value();
+        let is_unused = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            references,
+            i32_type.const_int(0, false),
+            "is_unused",
+        );
+        self.builder
+            .build_conditional_branch(is_unused, free_block, done_block);
+
+        self.builder.position_at_end(free_block);
+        self.builder.build_call(
+            self.module.get_function("free").unwrap(),
+            &[header.into()],
+            "free",
+        );
+        self.compile_counter_add(live_strings, -1);
+        self.builder.build_unconditional_branch(done_block);
+
+        self.builder.position_at_end(done_block);
+        self.builder.build_return(None);
+    }
+
+    /// Lists grow by doubling their capacity, their elements are stored as 8 byte slots.
+    ///
+    /// A list is freed when the last reference to it is released, together with its references to
+    /// its elements if they are strings. `viv_live_lists` counts the lists that haven't been freed yet
+    /// for the leak check
+    fn compile_list_runtime(&mut self) {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let list_type = self.list_type();
+        let void_type = self.context.void_type();
+        let linkage = Some(inkwell::module::Linkage::LinkOnceODR);
+        let slot_size = i64_type.size_of();
+
+        // shared by every object file, like the runtime functions
+        let live_lists = self.module.add_global(i32_type, None, "viv_live_lists");
+        live_lists.set_linkage(inkwell::module::Linkage::LinkOnceODR);
+        live_lists.set_initializer(&i32_type.const_int(0, false));
+        let live_lists = live_lists.as_pointer_value();
+
+        // viv_list_new(capacity, holds_strings) -> list
+        let new = self.module.add_function(
+            "viv_list_new",
+            list_type.fn_type(&[i32_type.into(), self.context.bool_type().into()], false),
+            linkage,
+        );
+        self.builder
+            .position_at_end(self.context.append_basic_block(new, "entry"));
+        let requested = new.get_nth_param(0).unwrap().into_int_value();
+        // with room for at least one element, doubling always makes room for another
+        let too_small = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            requested,
+            i32_type.const_int(1, false),
+            "too_small",
+        );
+        let capacity = self
+            .builder
+            .build_select(
+                too_small,
+                i32_type.const_int(1, false),
+                requested,
+                "capacity",
+            )
+            .into_int_value();
+        let list = self
+            .compile_builtin_call(
+                "malloc",
+                &[self.list_header_type().size_of().unwrap().into()],
+            )
+            .into_pointer_value();
+        let data_size = self
+            .builder
+            .build_int_z_extend(capacity, i64_type, "data_size");
+        let data_size = self
+            .builder
+            .build_int_mul(data_size, slot_size, "data_size");
+        let data = self.compile_builtin_call("malloc", &[data_size.into()]);
+        self.builder.build_store(
+            self.list_field(list, LIST_LENGTH),
+            i32_type.const_int(0, false),
+        );
+        self.builder
+            .build_store(self.list_field(list, LIST_CAPACITY), capacity);
+        self.builder
+            .build_store(self.list_field(list, LIST_DATA), data);
+        self.builder.build_store(
+            self.list_field(list, LIST_REFERENCES),
+            i32_type.const_int(1, false),
+        );
+        self.builder.build_store(
+            self.list_field(list, LIST_HOLDS_STRINGS),
+            new.get_nth_param(1).unwrap(),
+        );
+        self.compile_counter_add(live_lists, 1);
+        self.builder.build_return(Some(&list));
+
+        // viv_list_retain(list)
+        let retain = self.module.add_function(
+            "viv_list_retain",
+            void_type.fn_type(&[list_type.into()], false),
+            linkage,
+        );
+        self.builder
+            .position_at_end(self.context.append_basic_block(retain, "entry"));
+        let list = retain.get_nth_param(0).unwrap().into_pointer_value();
+        self.compile_counter_add(self.list_field(list, LIST_REFERENCES), 1);
+        self.builder.build_return(None);
+
+        // viv_list_release(list), variables that were never assigned hold null.
+        // C releases the lists it gets from exported functions with it, so it's kept even if Viv doesn't use it
+        let release = self.module.add_function(
+            "viv_list_release",
+            void_type.fn_type(&[list_type.into()], false),
+            Some(inkwell::module::Linkage::WeakODR),
+        );
+        let entry_block = self.context.append_basic_block(release, "entry");
+        let release_block = self.context.append_basic_block(release, "release");
+        let strings_block = self.context.append_basic_block(release, "release_strings");
+        let string_block = self.context.append_basic_block(release, "release_string");
+        let free_block = self.context.append_basic_block(release, "free");
+        let done_block = self.context.append_basic_block(release, "done");
+
+        self.builder.position_at_end(entry_block);
+        let index = self.builder.build_alloca(i32_type, "index");
+        let list = release.get_nth_param(0).unwrap().into_pointer_value();
+        let is_null = self.builder.build_is_null(list, "is_null");
+        self.builder
+            .build_conditional_branch(is_null, done_block, release_block);
+
+        self.builder.position_at_end(release_block);
+        let references = self.list_field(list, LIST_REFERENCES);
+        self.compile_counter_add(references, -1);
+        let references = self
+            .builder
+            .build_load(i32_type, references, "references")
+            .into_int_value();
+        let is_unused = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            references,
+            i32_type.const_int(0, false),
+            "is_unused",
+        );
+        self.builder
+            .build_conditional_branch(is_unused, strings_block, done_block);
+
+        // the elements are released from the last to the first, lists that don't hold strings skip the loop
+        self.builder.position_at_end(strings_block);
+        let holds_strings = self
+            .builder
+            .build_load(
+                self.context.bool_type(),
+                self.list_field(list, LIST_HOLDS_STRINGS),
+                "holds_strings",
+            )
+            .into_int_value();
+        let length = self
+            .builder
+            .build_load(i32_type, self.list_field(list, LIST_LENGTH), "length")
+            .into_int_value();
+        let count = self
+            .builder
+            .build_select(holds_strings, length, i32_type.const_zero(), "count")
+            .into_int_value();
+        self.builder.build_store(index, count);
+        let loop_block = self.context.append_basic_block(release, "strings_left");
+        self.builder.build_unconditional_branch(loop_block);
+
+        self.builder.position_at_end(loop_block);
+        let remaining = self
+            .builder
+            .build_load(i32_type, index, "remaining")
+            .into_int_value();
+        let has_strings = self.builder.build_int_compare(
+            IntPredicate::SGT,
+            remaining,
+            i32_type.const_zero(),
+            "has_strings",
+        );
+        self.builder
+            .build_conditional_branch(has_strings, string_block, free_block);
+
+        self.builder.position_at_end(string_block);
+        let last = self
+            .builder
+            .build_int_sub(remaining, i32_type.const_int(1, false), "last");
+        self.builder.build_store(index, last);
+        let data = self
+            .builder
+            .build_load(self.string_type(), self.list_field(list, LIST_DATA), "data")
+            .into_pointer_value();
+        let element = unsafe { self.builder.build_gep(i64_type, data, &[last], "element") };
+        let slot = self
+            .builder
+            .build_load(i64_type, element, "slot")
+            .into_int_value();
+        let string = self
+            .builder
+            .build_int_to_ptr(slot, self.string_type(), "string");
+        self.compile_string_release(string);
+        self.builder.build_unconditional_branch(loop_block);
+
+        self.builder.position_at_end(free_block);
+        let free = self.module.get_function("free").unwrap();
+        let data =
+            self.builder
+                .build_load(self.string_type(), self.list_field(list, LIST_DATA), "data");
+        self.builder.build_call(free, &[data.into()], "free");
+        self.builder.build_call(free, &[list.into()], "free");
+        self.compile_counter_add(live_lists, -1);
+        self.builder.build_unconditional_branch(done_block);
+
+        self.builder.position_at_end(done_block);
+        self.builder.build_return(None);
+
+        // viv_leak_check(), reports the lists and strings that are still allocated on stderr
+        let leak_check =
+            self.module
+                .add_function("viv_leak_check", void_type.fn_type(&[], false), linkage);
+        self.builder
+            .position_at_end(self.context.append_basic_block(leak_check, "entry"));
+        let live_strings = self
+            .module
+            .get_global("viv_live_strings")
+            .unwrap()
+            .as_pointer_value();
+        for (counter, kind) in [(live_lists, "list"), (live_strings, "string")] {
+            let leak_block = self.context.append_basic_block(leak_check, "leak");
+            let done_block = self.context.append_basic_block(leak_check, "done");
+
+            let live = self
+                .builder
+                .build_load(i32_type, counter, "live")
+                .into_int_value();
+            let has_leaked = self.builder.build_int_compare(
+                IntPredicate::NE,
+                live,
+                i32_type.const_int(0, false),
+                "has_leaked",
+            );
+            self.builder
+                .build_conditional_branch(has_leaked, leak_block, done_block);
+
+            self.builder.position_at_end(leak_block);
+            let format_string = self
+                .builder
+                .build_global_string_ptr(
+                    &format!("Leak check: %d {kind}(s) still allocated\n"),
+                    "Leak_Error",
+                )
+                .as_pointer_value();
+            self.builder.build_call(
+                self.module.get_function("dprintf").unwrap(),
+                &[
+                    i32_type.const_int(2, false).into(),
+                    format_string.into(),
+                    live.into(),
+                ],
+                "dprintf",
+            );
+            self.builder.build_unconditional_branch(done_block);
+
+            self.builder.position_at_end(done_block);
+        }
+        self.builder.build_return(None);
+
+        // viv_list_push(list, slot)
+        let push = self.module.add_function(
+            "viv_list_push",
+            void_type.fn_type(&[list_type.into(), i64_type.into()], false),
+            linkage,
+        );
+        let entry_block = self.context.append_basic_block(push, "entry");
+        let grow_block = self.context.append_basic_block(push, "grow");
+        let store_block = self.context.append_basic_block(push, "store");
+
+        self.builder.position_at_end(entry_block);
+        let list = push.get_nth_param(0).unwrap().into_pointer_value();
+        let slot = push.get_nth_param(1).unwrap().into_int_value();
+        let length = self
+            .builder
+            .build_load(i32_type, self.list_field(list, LIST_LENGTH), "length")
+            .into_int_value();
+        let capacity = self
+            .builder
+            .build_load(i32_type, self.list_field(list, LIST_CAPACITY), "capacity")
+            .into_int_value();
+        let is_full = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, length, capacity, "is_full");
+        self.builder
+            .build_conditional_branch(is_full, grow_block, store_block);
+
+        self.builder.position_at_end(grow_block);
+        let capacity =
+            self.builder
+                .build_int_mul(capacity, i32_type.const_int(2, false), "capacity");
+        let data_size = self
+            .builder
+            .build_int_z_extend(capacity, i64_type, "data_size");
+        let data_size = self
+            .builder
+            .build_int_mul(data_size, slot_size, "data_size");
+        let data =
+            self.builder
+                .build_load(self.string_type(), self.list_field(list, LIST_DATA), "data");
+        let data = self.compile_builtin_call("realloc", &[data.into(), data_size.into()]);
+        self.builder
+            .build_store(self.list_field(list, LIST_DATA), data);
+        self.builder
+            .build_store(self.list_field(list, LIST_CAPACITY), capacity);
+        self.builder.build_unconditional_branch(store_block);
+
+        self.builder.position_at_end(store_block);
+        let data = self
+            .builder
+            .build_load(self.string_type(), self.list_field(list, LIST_DATA), "data")
+            .into_pointer_value();
+        let element = unsafe { self.builder.build_gep(i64_type, data, &[length], "element") };
+        self.builder.build_store(element, slot);
+        let length = self
+            .builder
+            .build_int_add(length, i32_type.const_int(1, false), "length");
+        self.builder
+            .build_store(self.list_field(list, LIST_LENGTH), length);
+        self.builder.build_return(None);
+
+        // viv_list_pop(list) -> slot
+        let pop = self.module.add_function(
+            "viv_list_pop",
+            i64_type.fn_type(&[list_type.into()], false),
+            linkage,
+        );
+        let entry_block = self.context.append_basic_block(pop, "entry");
+        let fail_block = self.context.append_basic_block(pop, "empty");
+        let ok_block = self.context.append_basic_block(pop, "not_empty");
+
+        self.builder.position_at_end(entry_block);
+        let list = pop.get_nth_param(0).unwrap().into_pointer_value();
+        let length = self
+            .builder
+            .build_load(i32_type, self.list_field(list, LIST_LENGTH), "length")
+            .into_int_value();
+        let is_empty = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            length,
+            i32_type.const_int(0, false),
+            "is_empty",
+        );
+        self.builder
+            .build_conditional_branch(is_empty, fail_block, ok_block);
+
+        self.builder.position_at_end(fail_block);
+        self.compile_const_printf("Pop from an empty list\n");
+        self.compile_abort();
+
+        self.builder.position_at_end(ok_block);
+        let length = self
+            .builder
+            .build_int_sub(length, i32_type.const_int(1, false), "length");
+        self.builder
+            .build_store(self.list_field(list, LIST_LENGTH), length);
+        let data = self
+            .builder
+            .build_load(self.string_type(), self.list_field(list, LIST_DATA), "data")
+            .into_pointer_value();
+        let element = unsafe { self.builder.build_gep(i64_type, data, &[length], "element") };
+        let slot = self.builder.build_load(i64_type, element, "slot");
+        self.builder.build_return(Some(&slot));
+
+        // viv_list_get(list, index) -> slot
+        let get = self.module.add_function(
+            "viv_list_get",
+            i64_type.fn_type(&[list_type.into(), i32_type.into()], false),
+            linkage,
+        );
+        self.builder
+            .position_at_end(self.context.append_basic_block(get, "entry"));
+        let element = self.compile_list_element_pointer(
+            get,
+            get.get_nth_param(0).unwrap().into_pointer_value(),
+            get.get_nth_param(1).unwrap().into_int_value(),
+        );
+        let slot = self.builder.build_load(i64_type, element, "slot");
+        self.builder.build_return(Some(&slot));
+
+        // viv_list_set(list, index, slot), a string that is replaced is released
+        let set = self.module.add_function(
+            "viv_list_set",
+            void_type.fn_type(&[list_type.into(), i32_type.into(), i64_type.into()], false),
+            linkage,
+        );
+        self.builder
+            .position_at_end(self.context.append_basic_block(set, "entry"));
+        let list = set.get_nth_param(0).unwrap().into_pointer_value();
+        let element = self.compile_list_element_pointer(
+            set,
+            list,
+            set.get_nth_param(1).unwrap().into_int_value(),
+        );
+        let replaced = self
+            .builder
+            .build_load(i64_type, element, "replaced")
+            .into_int_value();
+        self.builder
+            .build_store(element, set.get_nth_param(2).unwrap());
+        let release_block = self.context.append_basic_block(set, "release_string");
+        let done_block = self.context.append_basic_block(set, "done");
+        let holds_strings = self
+            .builder
+            .build_load(
+                self.context.bool_type(),
+                self.list_field(list, LIST_HOLDS_STRINGS),
+                "holds_strings",
+            )
+            .into_int_value();
+        self.builder
+            .build_conditional_branch(holds_strings, release_block, done_block);
+
+        self.builder.position_at_end(release_block);
+        let string = self
+            .builder
+            .build_int_to_ptr(replaced, self.string_type(), "string");
+        self.compile_string_release(string);
+        self.builder.build_unconditional_branch(done_block);
+
+        self.builder.position_at_end(done_block);
+        self.builder.build_return(None);
+
+        // viv_list_length(list) -> int
+        let length = self.module.add_function(
+            "viv_list_length",
+            i32_type.fn_type(&[list_type.into()], false),
+            linkage,
+        );
+        self.builder
+            .position_at_end(self.context.append_basic_block(length, "entry"));
+        let list = length.get_nth_param(0).unwrap().into_pointer_value();
+        let value = self
+            .builder
+            .build_load(i32_type, self.list_field(list, LIST_LENGTH), "length");
+        self.builder.build_return(Some(&value));
+    }
+
+    fn compile_arg_count(&self) -> inkwell::values::IntValue<'ctx> {
+        let argc = self
+            .builder
+            .build_load(
+                self.context.i32_type(),
+                self.module
+                    .get_global("viv_argc")
+                    .unwrap()
+                    .as_pointer_value(),
+                "argc",
+            )
+            .into_int_value();
+        self.builder.build_int_sub(
+            argc,
+            self.context.i32_type().const_int(1, false),
+            "Arg_Count",
+        )
+    }
+
+    fn compile_builtin_call(
+        &self,
+        name: &str,
+        arguments: &[inkwell::values::BasicMetadataValueEnum<'ctx>],
+    ) -> inkwell::values::BasicValueEnum<'ctx> {
+        let function = self.module.get_function(name).unwrap();
+        self.builder
+            .build_call(function, arguments, name)
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+    }
+
+    fn compile_abort(&self) {
+        // abort doesnt flush stdout, so anything printed so far would be lost when stdout is piped
+        let fflush = self.module.get_function("fflush").unwrap();
+        let all_streams = self.string_type().const_null();
+        self.builder
+            .build_call(fflush, &[all_streams.into()], "Flush_Output");
+
+        let abort = self.module.get_function("abort").unwrap();
+        self.builder.build_call(abort, &[], "Abort");
+        self.builder.build_unreachable();
+    }
+
+    /// The string comes with a reference of its own like a list, literals are never freed
+    fn compile_string_expression(
+        &self,
+        expression: &ir::StringExpression,
+    ) -> inkwell::values::PointerValue<'ctx> {
+        match expression {
+            ir::StringExpression::Literal(string) => {
+                self.compile_constant_string(string, "String_Literal")
+            }
+            ir::StringExpression::Var(identifier) => {
+                let pointer = self.local_vars.get(identifier).unwrap();
+                let string = self
+                    .builder
+                    .build_load(self.string_type(), *pointer, "Load")
+                    .into_pointer_value();
+                let retain = self.module.get_function("viv_string_retain").unwrap();
+                self.builder.build_call(retain, &[string.into()], "Retain");
+                string
+            }
+            ir::StringExpression::ReadLine => self
+                .compile_builtin_call("viv_read_line", &[])
+                .into_pointer_value(),
+            ir::StringExpression::Arg(index) => {
+                let index = self.compile_int_expression(index);
+                self.compile_builtin_call("viv_arg", &[index.into()])
+                    .into_pointer_value()
+            }
+            ir::StringExpression::Call(call) => self.compile_call(call).into_pointer_value(),
+            ir::StringExpression::If(condition, then_value, else_value) => self
+                .compile_if(
+                    condition,
+                    |code_gen| code_gen.compile_string_expression(then_value).into(),
+                    |code_gen| code_gen.compile_string_expression(else_value).into(),
+                )
+                .into_pointer_value(),
+            ir::StringExpression::Index(list, index) => {
+                self.compile_string_element(list, Some(index))
+            }
+            ir::StringExpression::Pop(list) => self.compile_string_element(list, None),
+        }
+    }
+
+    fn compile_list_release(&self, list: inkwell::values::PointerValue<'ctx>) {
+        let release = self.module.get_function("viv_list_release").unwrap();
+        self.builder.build_call(release, &[list.into()], "Release");
+    }
+
+    fn compile_string_release(&self, string: inkwell::values::PointerValue<'ctx>) {
+        let release = self.module.get_function("viv_string_release").unwrap();
+        self.builder
+            .build_call(release, &[string.into()], "Release");
+    }
+
+    fn compile_leak_check(&self) {
+        let leak_check = self.module.get_function("viv_leak_check").unwrap();
+        self.builder.build_call(leak_check, &[], "Leak_Check");
+    }
+
+    /// Releases the lists and strings held by the variables of the current function before it returns,
+    /// the leak check runs once main has released them
+    fn compile_release_locals(&self) {
+        for (pointer, var_type) in &self.counted_locals {
+            let value = self
+                .builder
+                .build_load(self.llvm_type(*var_type), *pointer, "Load")
+                .into_pointer_value();
+            match var_type {
+                ir::VarType::String => self.compile_string_release(value),
+                _ => self.compile_list_release(value),
+            }
+        }
+
+        if self.in_main && self.leak_check {
+            self.compile_leak_check();
+        }
+    }
+
+    /// The list comes with a reference of its own, which the caller has to release or hand on
+    fn compile_list_expression(
+        &self,
+        expression: &ir::ListExpression,
+    ) -> inkwell::values::PointerValue<'ctx> {
+        match expression {
+            ir::ListExpression::Literal(element_type, values) => {
+                let capacity = self
+                    .context
+                    .i32_type()
+                    .const_int(values.len() as u64, false);
+                let holds_strings = self
+                    .context
+                    .bool_type()
+                    .const_int((*element_type == ir::ElementType::String) as u64, false);
+                let list = self
+                    .compile_builtin_call("viv_list_new", &[capacity.into(), holds_strings.into()])
+                    .into_pointer_value();
+                for value in values {
+                    self.compile_push(list, value);
+                }
+                list
+            }
+            ir::ListExpression::Var(identifier) => {
+                let pointer = self.local_vars.get(identifier).unwrap();
+                let list = self
+                    .builder
+                    .build_load(self.list_type(), *pointer, "Load")
+                    .into_pointer_value();
+                let retain = self.module.get_function("viv_list_retain").unwrap();
+                self.builder.build_call(retain, &[list.into()], "Retain");
+                list
+            }
+            ir::ListExpression::Call(call) => self.compile_call(call).into_pointer_value(),
+            ir::ListExpression::If(condition, then_value, else_value) => self
+                .compile_if(
+                    condition,
+                    |code_gen| code_gen.compile_list_expression(then_value).into(),
+                    |code_gen| code_gen.compile_list_expression(else_value).into(),
+                )
+                .into_pointer_value(),
+        }
+    }
+
+    /// Lists store every element in an 8 byte slot, ints are sign extended, bools and chars zero
+    /// extended and strings stored as their address, the list takes over the reference to a string
+    fn compile_element_slot(&self, value: &ir::Value) -> inkwell::values::IntValue<'ctx> {
+        let i64_type = self.context.i64_type();
+        match value {
+            ir::Value::Int(expression) => {
+                let value = self.compile_int_expression(expression);
+                self.builder.build_int_s_extend(value, i64_type, "Slot")
+            }
+            ir::Value::Boolean(expression) => {
+                let value = self.compile_bool_expression(expression);
+                self.builder.build_int_z_extend(value, i64_type, "Slot")
+            }
+            ir::Value::String(expression) => {
+                let value = self.compile_string_expression(expression);
+                self.builder.build_ptr_to_int(value, i64_type, "Slot")
+            }
+            ir::Value::Char(expression) => {
+                let value = self.compile_char_expression(expression);
+                self.builder.build_int_z_extend(value, i64_type, "Slot")
+            }
+            ir::Value::List(expression) => {
+                let value = self.compile_list_expression(expression);
+                self.builder.build_ptr_to_int(value, i64_type, "Slot")
+            }
+        }
+    }
+
+    fn compile_push(&self, list: inkwell::values::PointerValue<'ctx>, value: &ir::Value) {
+        let slot = self.compile_element_slot(value);
+        let push = self.module.get_function("viv_list_push").unwrap();
+        self.builder
+            .build_call(push, &[list.into(), slot.into()], "Push");
+    }
+
+    /// The slot of the element at the index, or of the popped last element without an index.
+    ///
+    /// A string element is retained before the list is released, unless it's popped and the list's
+    /// reference to it is handed over
+    fn compile_element(
+        &self,
+        list: &ir::ListExpression,
+        index: Option<&ir::IntExpression>,
+        is_string: bool,
+    ) -> inkwell::values::IntValue<'ctx> {
+        let list = self.compile_list_expression(list);
+        let slot = match index {
+            Some(index) => {
+                let index = self.compile_int_expression(index);
+                let slot = self
+                    .compile_builtin_call("viv_list_get", &[list.into(), index.into()])
+                    .into_int_value();
+                if is_string {
+                    let string = self
+                        .builder
+                        .build_int_to_ptr(slot, self.string_type(), "Element");
+                    let retain = self.module.get_function("viv_string_retain").unwrap();
+                    self.builder.build_call(retain, &[string.into()], "Retain");
+                }
+                slot
+            }
+            None => self
+                .compile_builtin_call("viv_list_pop", &[list.into()])
+                .into_int_value(),
+        };
+        self.compile_list_release(list);
+        slot
+    }
+
+    fn compile_int_element(
+        &self,
+        list: &ir::ListExpression,
+        index: Option<&ir::IntExpression>,
+    ) -> inkwell::values::IntValue<'ctx> {
+        let slot = self.compile_element(list, index, false);
+        self.builder
+            .build_int_truncate(slot, self.int_type(), "Element")
+    }
+
+    fn compile_bool_element(
+        &self,
+        list: &ir::ListExpression,
+        index: Option<&ir::IntExpression>,
+    ) -> inkwell::values::IntValue<'ctx> {
+        let slot = self.compile_element(list, index, false);
+        self.builder
+            .build_int_truncate(slot, self.context.bool_type(), "Element")
+    }
+
+    fn compile_string_element(
+        &self,
+        list: &ir::ListExpression,
+        index: Option<&ir::IntExpression>,
+    ) -> inkwell::values::PointerValue<'ctx> {
+        let slot = self.compile_element(list, index, true);
+        self.builder
+            .build_int_to_ptr(slot, self.string_type(), "Element")
+    }
 
-        let printf_type = i32_type.fn_type(&[i8_ptr_type.into()], true);
-        self.module.add_function("printf", printf_type, None);
+    fn compile_char_element(
+        &self,
+        list: &ir::ListExpression,
+        index: Option<&ir::IntExpression>,
+    ) -> inkwell::values::IntValue<'ctx> {
+        let slot = self.compile_element(list, index, false);
+        self.builder
+            .build_int_truncate(slot, self.char_type(), "Element")
+    }
 
-        let abort_type = void_type.fn_type(&[], false);
-        self.module.add_function("abort", abort_type, None);
+    fn compile_char_expression(
+        &self,
+        expression: &ir::CharExpression,
+    ) -> inkwell::values::IntValue<'ctx> {
+        match expression {
+            ir::CharExpression::Literal(value) => self.char_type().const_int(*value as u64, false),
+            ir::CharExpression::Var(identifier) => {
+                let pointer = self.local_vars.get(identifier).unwrap();
+                self.builder
+                    .build_load(self.char_type(), *pointer, "Load")
+                    .into_int_value()
+            }
+            ir::CharExpression::FromInt(expression) => {
+                let code = self.compile_int_expression(expression);
+                self.compile_builtin_call("viv_int_to_char", &[code.into()])
+                    .into_int_value()
+            }
+            ir::CharExpression::Call(call) => self.compile_call(call).into_int_value(),
+            ir::CharExpression::If(condition, then_value, else_value) => self
+                .compile_if(
+                    condition,
+                    |code_gen| code_gen.compile_char_expression(then_value).into(),
+                    |code_gen| code_gen.compile_char_expression(else_value).into(),
+                )
+                .into_int_value(),
+            ir::CharExpression::Index(list, index) => self.compile_char_element(list, Some(index)),
+            ir::CharExpression::Pop(list) => self.compile_char_element(list, None),
+        }
     }
 
     fn compile_int_expression(
@@ -88,17 +1710,7 @@ impl<'ctx> CodeGen<'ctx> {
             ir::IntExpression::BinaryOperation(left, op, right) => {
                 let left = self.compile_int_expression(left);
                 let right = self.compile_int_expression(right);
-
-                match op {
-                    ir::IntBinaryOp::Plus => self.builder.build_int_add(left, right, "Plus"),
-                    ir::IntBinaryOp::Minus => self.builder.build_int_sub(left, right, "Minus"),
-                    ir::IntBinaryOp::Multiply => {
-                        self.builder.build_int_mul(left, right, "Multiply")
-                    }
-                    ir::IntBinaryOp::Divide => {
-                        self.builder.build_int_signed_div(left, right, "Divide")
-                    }
-                }
+                self.compile_int_binary_operation(left, op, right)
             }
             ir::IntExpression::Var(identifier) => {
                 let pointer = self.local_vars.get(identifier).unwrap();
@@ -106,7 +1718,117 @@ impl<'ctx> CodeGen<'ctx> {
                     .build_load(self.int_type(), *pointer, "Load")
                     .into_int_value()
             }
+            ir::IntExpression::ReadInt => self
+                .compile_builtin_call("viv_read_int", &[])
+                .into_int_value(),
+            ir::IntExpression::ArgCount => self.compile_arg_count(),
+            ir::IntExpression::ParseInt(string) => {
+                let string = self.compile_string_expression(string);
+                let value = self.compile_builtin_call("atoi", &[string.into()]);
+                self.compile_string_release(string);
+                value.into_int_value()
+            }
+            ir::IntExpression::Call(call) => self.compile_call(call).into_int_value(),
+            ir::IntExpression::If(condition, then_value, else_value) => self
+                .compile_if(
+                    condition,
+                    |code_gen| code_gen.compile_int_expression(then_value).into(),
+                    |code_gen| code_gen.compile_int_expression(else_value).into(),
+                )
+                .into_int_value(),
+            ir::IntExpression::Index(list, index) => self.compile_int_element(list, Some(index)),
+            ir::IntExpression::Pop(list) => self.compile_int_element(list, None),
+            ir::IntExpression::Length(list) => {
+                let list = self.compile_list_expression(list);
+                let length = self.compile_builtin_call("viv_list_length", &[list.into()]);
+                self.compile_list_release(list);
+                length.into_int_value()
+            }
+            ir::IntExpression::FromChar(expression) => {
+                let value = self.compile_char_expression(expression);
+                self.builder
+                    .build_int_cast_sign_flag(value, self.int_type(), false, "Code_Point")
+            }
+        }
+    }
+
+    fn compile_int_binary_operation(
+        &self,
+        left: inkwell::values::IntValue<'ctx>,
+        op: &ir::IntBinaryOp,
+        right: inkwell::values::IntValue<'ctx>,
+    ) -> inkwell::values::IntValue<'ctx> {
+        match op {
+            ir::IntBinaryOp::Plus => self.builder.build_int_add(left, right, "Plus"),
+            ir::IntBinaryOp::Minus => self.builder.build_int_sub(left, right, "Minus"),
+            ir::IntBinaryOp::Multiply => self.builder.build_int_mul(left, right, "Multiply"),
+            ir::IntBinaryOp::Divide => self.builder.build_int_signed_div(left, right, "Divide"),
+        }
+    }
+
+    fn int_comparison_predicate(op: &ir::IntComparisonOp) -> IntPredicate {
+        match op {
+            ir::IntComparisonOp::Equal => IntPredicate::EQ,
+            ir::IntComparisonOp::NotEquals => IntPredicate::NE,
+            ir::IntComparisonOp::LessThan => IntPredicate::SLT,
+            ir::IntComparisonOp::LessThanEquals => IntPredicate::SLE,
+            ir::IntComparisonOp::GreaterThan => IntPredicate::SGT,
+            ir::IntComparisonOp::GreaterThanEquals => IntPredicate::SGE,
+        }
+    }
+
+    fn int_comparison_symbol(op: &ir::IntComparisonOp) -> &'static str {
+        match op {
+            ir::IntComparisonOp::Equal => "==",
+            ir::IntComparisonOp::NotEquals => "!=",
+            ir::IntComparisonOp::LessThan => "<",
+            ir::IntComparisonOp::LessThanEquals => "<=",
+            ir::IntComparisonOp::GreaterThan => ">",
+            ir::IntComparisonOp::GreaterThanEquals => ">=",
+        }
+    }
+
+    /// Evaluates every operand of a comparison chain once, in order
+    fn compile_int_comparison_operands(
+        &self,
+        left: &ir::IntExpression,
+        chains: &[(ir::IntComparisonOp, ir::IntExpression)],
+    ) -> Vec<inkwell::values::IntValue<'ctx>> {
+        let mut values = Vec::with_capacity(chains.len() + 1);
+        values.push(self.compile_int_expression(left));
+
+        for (_, right_side) in chains {
+            values.push(self.compile_int_expression(right_side));
+        }
+
+        values
+    }
+
+    /// Compares each operand with the next one, chars are compared like ints by their code points
+    fn compile_int_comparison_chain<T>(
+        &self,
+        values: &[inkwell::values::IntValue<'ctx>],
+        chains: &[(ir::IntComparisonOp, T)],
+    ) -> inkwell::values::IntValue<'ctx> {
+        let mut parts = Vec::with_capacity(chains.len());
+
+        for (index, (op, _)) in chains.iter().enumerate() {
+            let part = self.builder.build_int_compare(
+                Self::int_comparison_predicate(op),
+                values[index],
+                values[index + 1],
+                "Compare",
+            );
+
+            parts.push(part);
+        }
+
+        let mut result = parts[0];
+        for part in parts.iter().skip(1) {
+            result = self.builder.build_and(result, *part, "And");
         }
+
+        result
     }
 
     fn compile_comparison(
@@ -115,34 +1837,15 @@ impl<'ctx> CodeGen<'ctx> {
     ) -> inkwell::values::IntValue<'ctx> {
         match comparison {
             ir::ComparisonExpression::IntComparison(left, chains) => {
-                let mut current_left = self.compile_int_expression(left);
-                let mut parts = Vec::with_capacity(chains.len() - 1);
-
-                for (op, right_side) in chains {
-                    let right_side = self.compile_int_expression(right_side);
-                    let op = match op {
-                        ir::IntComparisonOp::Equal => IntPredicate::EQ,
-                        ir::IntComparisonOp::NotEquals => IntPredicate::NE,
-                        ir::IntComparisonOp::LessThan => IntPredicate::SLT,
-                        ir::IntComparisonOp::LessThanEquals => IntPredicate::SLE,
-                        ir::IntComparisonOp::GreaterThan => IntPredicate::SGT,
-                        ir::IntComparisonOp::GreaterThanEquals => IntPredicate::SGE,
-                    };
-
-                    let part =
-                        self.builder
-                            .build_int_compare(op, current_left, right_side, "Compare");
-
-                    parts.push(part);
-                    current_left = right_side;
-                }
-
-                let mut result = parts[0];
-                for part in parts.iter().skip(1) {
-                    result = self.builder.build_and(result, *part, "And");
+                let values = self.compile_int_comparison_operands(left, chains);
+                self.compile_int_comparison_chain(&values, chains)
+            }
+            ir::ComparisonExpression::CharComparison(left, chains) => {
+                let mut values = vec![self.compile_char_expression(left)];
+                for (_, right_side) in chains {
+                    values.push(self.compile_char_expression(right_side));
                 }
-
-                result
+                self.compile_int_comparison_chain(&values, chains)
             }
         }
     }
@@ -211,54 +1914,163 @@ impl<'ctx> CodeGen<'ctx> {
                     .build_load(self.context.bool_type(), *pointer, "Load")
                     .into_int_value()
             }
+            ir::BooleanExpression::Call(call) => self.compile_call(call).into_int_value(),
+            ir::BooleanExpression::If(condition, then_value, else_value) => self
+                .compile_if(
+                    condition,
+                    |code_gen| code_gen.compile_bool_expression(then_value).into(),
+                    |code_gen| code_gen.compile_bool_expression(else_value).into(),
+                )
+                .into_int_value(),
+            ir::BooleanExpression::Index(list, index) => {
+                self.compile_bool_element(list, Some(index))
+            }
+            ir::BooleanExpression::Pop(list) => self.compile_bool_element(list, None),
         }
     }
 
-    fn compile_print_statement(&self, statement: &ir::PrintStatement) {
+    /// Evaluates one of the branches depending on the condition, joining their values with a phi
+    fn compile_if(
+        &self,
+        condition: &ir::BooleanExpression,
+        compile_then: impl FnOnce(&Self) -> inkwell::values::BasicValueEnum<'ctx>,
+        compile_else: impl FnOnce(&Self) -> inkwell::values::BasicValueEnum<'ctx>,
+    ) -> inkwell::values::BasicValueEnum<'ctx> {
+        let condition = self.compile_bool_expression(condition);
+
+        let current_block = self.builder.get_insert_block().unwrap();
+        let then_block = self
+            .context
+            .insert_basic_block_after(current_block, "then_block");
+        let else_block = self
+            .context
+            .insert_basic_block_after(then_block, "else_block");
+        let merge_block = self
+            .context
+            .insert_basic_block_after(else_block, "merge_block");
+
+        self.builder
+            .build_conditional_branch(condition, then_block, else_block);
+
+        // a branch can add blocks of its own, the phi needs the block each branch ends in
+        self.builder.position_at_end(then_block);
+        let then_value = compile_then(self);
+        let then_end = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(merge_block);
+
+        self.builder.position_at_end(else_block);
+        let else_value = compile_else(self);
+        let else_end = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(merge_block);
+
+        self.builder.position_at_end(merge_block);
+        let phi = self.builder.build_phi(then_value.get_type(), "If");
+        phi.add_incoming(&[(&then_value, then_end), (&else_value, else_end)]);
+        phi.as_basic_value()
+    }
+
+    fn compile_print_statement(&self, values: &[ir::PrintValue]) {
         let printf = self.module.get_function("printf").unwrap();
 
-        let format_string = match statement {
-            ir::PrintStatement::Int(_) => "%d\n",
-            ir::PrintStatement::Boolean(_) => "Bool(%d)\n", // This isnt the best way to do this
-        };
+        let mut format_string = String::new();
+        let mut arguments = Vec::new();
+        // released once they are printed
+        let mut strings = Vec::new();
+
+        for value in values {
+            match value {
+                ir::PrintValue::Int(int_expression) => {
+                    format_string.push_str("%d");
+                    arguments.push(self.compile_int_expression(int_expression).into());
+                }
+                ir::PrintValue::Boolean(boolean_expression) => {
+                    let boolean_value = self.compile_bool_expression(boolean_expression);
+                    let true_string = self
+                        .builder
+                        .build_global_string_ptr("true", "True_String")
+                        .as_pointer_value();
+                    let false_string = self
+                        .builder
+                        .build_global_string_ptr("false", "False_String")
+                        .as_pointer_value();
+
+                    format_string.push_str("%s");
+                    arguments.push(
+                        self.builder
+                            .build_select(boolean_value, true_string, false_string, "Bool_String")
+                            .into(),
+                    );
+                }
+                ir::PrintValue::String(string_expression) => {
+                    let string = self.compile_string_expression(string_expression);
+                    format_string.push_str("%s");
+                    arguments.push(string.into());
+                    strings.push(string);
+                }
+                ir::PrintValue::Char(char_expression) => {
+                    let value = self.compile_char_expression(char_expression);
+                    let buffer = self
+                        .build_entry_alloca(self.context.i8_type().array_type(5), "Char_Buffer");
+                    format_string.push_str("%s");
+                    arguments.push(
+                        self.compile_builtin_call(
+                            "viv_encode_char",
+                            &[value.into(), buffer.into()],
+                        )
+                        .into(),
+                    );
+                }
+                ir::PrintValue::Text(text) => format_string.push_str(&text.replace('%', "%%")),
+            }
+        }
+        format_string.push('\n');
+
         let format_string = self
             .builder
-            .build_global_string_ptr(format_string, "format_string")
+            .build_global_string_ptr(&format_string, "format_string")
             .as_pointer_value();
+        arguments.insert(0, format_string.into());
 
-        match statement {
-            ir::PrintStatement::Int(int_expression) => {
-                let int_value = self.compile_int_expression(int_expression);
-                self.builder.build_call(
-                    printf,
-                    &[format_string.into(), int_value.into()],
-                    "printf",
-                );
-            }
-            ir::PrintStatement::Boolean(boolean_expression) => {
-                let boolean_value = self.compile_bool_expression(boolean_expression);
-                self.builder.build_call(
-                    printf,
-                    &[format_string.into(), boolean_value.into()],
-                    "printf",
-                );
-            }
+        self.builder.build_call(printf, &arguments, "printf");
+        for string in strings {
+            self.compile_string_release(string);
         }
     }
 
     fn compile_const_printf(&self, msg: &str) {
         let printf = self.module.get_function("printf").unwrap();
+        // the message is passed as an argument so a % in it isnt read as a format specifier
         let format_string = self
+            .builder
+            .build_global_string_ptr("%s", "Const_Print_Format")
+            .as_pointer_value();
+        let msg = self
             .builder
             .build_global_string_ptr(msg, "Const_Print")
             .as_pointer_value();
 
         self.builder
-            .build_call(printf, &[format_string.into()], "Const_Print");
+            .build_call(printf, &[format_string.into(), msg.into()], "Const_Print");
     }
 
-    fn compile_assert(&self, expression: &ir::BooleanExpression, message: &Option<String>) {
-        let condition_value = self.compile_bool_expression(expression);
+    fn compile_assert(
+        &self,
+        expression: &ir::BooleanExpression,
+        message: &Option<String>,
+        location: &ir::SourceLocation,
+    ) {
+        // comparisons are evaluated by hand so the operand values can be shown if the assert fails
+        let (condition_value, comparison) = match expression {
+            ir::BooleanExpression::Comparison(ir::ComparisonExpression::IntComparison(
+                left,
+                chains,
+            )) => {
+                let values = self.compile_int_comparison_operands(left, chains);
+                let condition_value = self.compile_int_comparison_chain(&values, chains);
+                (condition_value, Some((values, chains)))
+            }
+            _ => (self.compile_bool_expression(expression), None),
+        };
 
         let current_block = self.builder.get_insert_block().unwrap();
         let fail_block = self
@@ -277,18 +2089,85 @@ impl<'ctx> CodeGen<'ctx> {
         } else {
             self.compile_const_printf("Assert failed\n");
         }
+        self.compile_const_printf(&format!(
+            "  at {}:{}\n  assert {}\n",
+            location.file, location.line, location.text
+        ));
 
-        let abort = self.module.get_function("abort").unwrap();
-        self.builder.build_call(abort, &[], "Assert_Fail_Exit");
-        self.builder.build_unreachable();
+        if let Some((values, chains)) = comparison {
+            let mut format_string = String::from("  where %d");
+            for (op, _) in chains {
+                format_string.push_str(&format!(" {} %d", Self::int_comparison_symbol(op)));
+            }
+            format_string.push('\n');
+
+            let printf = self.module.get_function("printf").unwrap();
+            let format_string = self
+                .builder
+                .build_global_string_ptr(&format_string, "Assert_Values")
+                .as_pointer_value();
+
+            let mut arguments = vec![format_string.into()];
+            for value in values {
+                arguments.push(value.into());
+            }
+            self.builder.build_call(printf, &arguments, "printf");
+        }
+
+        // a failing assert only fails the current test, the harness keeps running the others
+        if self.in_test {
+            let failed = self.context.i32_type().const_int(1, false);
+            self.compile_release_locals();
+            self.builder.build_return(Some(&failed));
+            self.builder.position_at_end(continue_block);
+            return;
+        }
+
+        self.compile_abort();
 
         self.builder.position_at_end(continue_block);
     }
 
+    /// Starts a new block for the code after a terminator, it is unreachable but keeps the builder valid
+    fn continue_after_terminator(&self) {
+        let current_block = self.builder.get_insert_block().unwrap();
+        let next_block = self
+            .context
+            .insert_basic_block_after(current_block, "after_terminator");
+        self.builder.position_at_end(next_block);
+    }
+
     fn compile_statement(&self, statement: &ir::Statement) {
         match statement {
-            ir::Statement::Print(print_statement) => self.compile_print_statement(print_statement),
-            ir::Statement::Assert(expression, message) => self.compile_assert(expression, message),
+            ir::Statement::Call(call) => {
+                self.compile_call(call);
+            }
+            ir::Statement::Return(value) => {
+                match value {
+                    Some(value) => {
+                        let value: inkwell::values::BasicValueEnum =
+                            self.compile_value(value).try_into().unwrap();
+                        self.compile_release_locals();
+                        self.builder.build_return(Some(&value));
+                    }
+                    None => {
+                        self.compile_release_locals();
+                        self.builder.build_return(None);
+                    }
+                }
+                self.continue_after_terminator();
+            }
+            ir::Statement::Exit(expression) => {
+                let value = self.compile_int_expression(expression);
+                let exit = self.module.get_function("exit").unwrap();
+                self.builder.build_call(exit, &[value.into()], "Exit");
+                self.builder.build_unreachable();
+                self.continue_after_terminator();
+            }
+            ir::Statement::Print(values) => self.compile_print_statement(values),
+            ir::Statement::Assert(expression, message, location) => {
+                self.compile_assert(expression, message, location)
+            }
             ir::Statement::Assignment(identifier, statement) => {
                 let pointer = self.local_vars.get(identifier).unwrap();
 
@@ -301,71 +2180,511 @@ impl<'ctx> CodeGen<'ctx> {
                         let value = self.compile_bool_expression(expression);
                         self.builder.build_store(*pointer, value);
                     }
+                    ir::AssignmentStatement::String(expression) => {
+                        let value = self.compile_string_expression(expression);
+                        let old_value = self
+                            .builder
+                            .build_load(self.string_type(), *pointer, "Load")
+                            .into_pointer_value();
+                        self.builder.build_store(*pointer, value);
+                        self.compile_string_release(old_value);
+                    }
+                    ir::AssignmentStatement::Char(expression) => {
+                        let value = self.compile_char_expression(expression);
+                        self.builder.build_store(*pointer, value);
+                    }
+                    ir::AssignmentStatement::List(expression) => {
+                        let value = self.compile_list_expression(expression);
+                        let old_value = self
+                            .builder
+                            .build_load(self.list_type(), *pointer, "Load")
+                            .into_pointer_value();
+                        self.builder.build_store(*pointer, value);
+                        self.compile_list_release(old_value);
+                    }
+                }
+            }
+            ir::Statement::CompoundAssignment(identifier, op, expression) => {
+                // like in rust, the right hand side is evaluated before the variable is loaded
+                let right = self.compile_int_expression(expression);
+                let pointer = *self.local_vars.get(identifier).unwrap();
+                let left = self
+                    .builder
+                    .build_load(self.int_type(), pointer, "Load")
+                    .into_int_value();
+                let value = self.compile_int_binary_operation(left, op, right);
+                self.builder.build_store(pointer, value);
+            }
+            ir::Statement::While(condition, body) => self.compile_while(condition, body),
+            ir::Statement::Push(list, value) => {
+                let list = self.compile_list_expression(list);
+                self.compile_push(list, value);
+                self.compile_list_release(list);
+            }
+            ir::Statement::SetIndex(list, index, value) => {
+                let list = self.compile_list_expression(list);
+                let index = self.compile_int_expression(index);
+                let slot = self.compile_element_slot(value);
+                let set = self.module.get_function("viv_list_set").unwrap();
+                self.builder.build_call(
+                    set,
+                    &[list.into(), index.into(), slot.into()],
+                    "Set_Index",
+                );
+                self.compile_list_release(list);
+            }
+        }
+    }
+
+    fn compile_while(&self, condition: &ir::BooleanExpression, body: &[ir::Statement]) {
+        let current_block = self.builder.get_insert_block().unwrap();
+        let condition_block = self
+            .context
+            .insert_basic_block_after(current_block, "loop_condition");
+        let body_block = self
+            .context
+            .insert_basic_block_after(condition_block, "loop_body");
+        let exit_block = self
+            .context
+            .insert_basic_block_after(body_block, "loop_exit");
+
+        self.builder.build_unconditional_branch(condition_block);
+
+        self.builder.position_at_end(condition_block);
+        let condition = self.compile_bool_expression(condition);
+        self.builder
+            .build_conditional_branch(condition, body_block, exit_block);
+
+        self.builder.position_at_end(body_block);
+        for statement in body {
+            self.compile_statement(statement);
+        }
+        self.builder.build_unconditional_branch(condition_block);
+
+        self.builder.position_at_end(exit_block);
+    }
+
+    fn compile_function(
+        &mut self,
+        name: &str,
+        parameters: &[ir::Parameter],
+        return_type: Option<ir::VarType>,
+        statements: &[ir::Statement],
+        locals: &[(ir::VariableIdentifier, ir::VarType)],
+    ) {
+        let i32_type = self.context.i32_type();
+        let function = if name == "main" {
+            self.add_main_function()
+        } else if let Some(function) = self
+            .module
+            .get_function(self.implementations.get(name).map_or(name, String::as_str))
+        {
+            // exported functions are already declared by `compile_module`
+            let entry_block = self.context.append_basic_block(function, "entry");
+            self.builder.position_at_end(entry_block);
+            function
+        } else {
+            // tests are only called by the harness in this object, so linking several objects never clashes on them
+            let function_type = i32_type.fn_type(&[], false);
+            let function = self.module.add_function(
+                name,
+                function_type,
+                Some(inkwell::module::Linkage::Internal),
+            );
+            let entry_block = self.context.append_basic_block(function, "entry");
+            self.builder.position_at_end(entry_block);
+            function
+        };
+
+        self.in_main = name == "main";
+        self.local_vars = self.globals.clone();
+        self.counted_locals.clear();
+        for (identifier, var_type) in locals {
+            let var = self
+                .builder
+                .build_alloca(self.llvm_type(*var_type), &format!("var_{}", identifier.0));
+            self.local_vars.insert(*identifier, var);
+
+            // a list or string variable holds null until it is assigned, releasing null does nothing
+            if let ir::VarType::List(_) | ir::VarType::String = var_type {
+                self.builder
+                    .build_store(var, self.string_type().const_null());
+                self.counted_locals.push((var, *var_type));
+            }
+        }
+        for (index, parameter) in parameters.iter().enumerate() {
+            let pointer = self.local_vars.get(&parameter.identifier).unwrap();
+            let value = function.get_nth_param(index as u32).unwrap();
+            self.builder.build_store(*pointer, value);
+        }
+
+        for statement in statements {
+            self.compile_statement(statement);
+        }
+
+        self.compile_release_locals();
+        if name == "main" || self.in_test {
+            self.builder
+                .build_return(Some(&i32_type.const_int(0, false)));
+        } else if return_type.is_none() {
+            self.builder.build_return(None);
+        } else {
+            // the analyzer makes sure a function with a return type ends with a return
+            self.builder.build_unreachable();
+        }
+    }
+
+    /// Whether an exported function passes strings or lists, which are reference counted in Viv but not in C,
+    /// so C calls it through a wrapper
+    fn needs_c_wrapper(parameters: &[ir::VarType], return_type: Option<ir::VarType>) -> bool {
+        parameters
+            .iter()
+            .chain(&return_type)
+            .any(|var_type| matches!(var_type, ir::VarType::String | ir::VarType::List(_)))
+    }
+
+    /// Fills in the exported function that C calls instead of the implementation.
+    ///
+    /// Strings passed to it are copied and lists are only borrowed, so they are retained for the implementation.
+    /// A returned string is copied into memory that stays valid until the function is called again,
+    /// a returned list belongs to the caller which releases it with `viv_list_release`
+    fn compile_c_wrapper(
+        &self,
+        name: &str,
+        parameters: &[ir::VarType],
+        return_type: Option<ir::VarType>,
+    ) {
+        let wrapper = self.module.get_function(name).unwrap();
+        let implementation = self
+            .module
+            .get_function(&self.implementations[name])
+            .unwrap();
+        self.builder
+            .position_at_end(self.context.append_basic_block(wrapper, "entry"));
+
+        let arguments = parameters
+            .iter()
+            .zip(wrapper.get_param_iter())
+            .map(|(parameter, value)| match parameter {
+                ir::VarType::String => self
+                    .compile_builtin_call("viv_string_from_c", &[value.into()])
+                    .into(),
+                ir::VarType::List(_) => {
+                    let retain = self.module.get_function("viv_list_retain").unwrap();
+                    self.builder.build_call(retain, &[value.into()], "Retain");
+                    value.into()
                 }
+                _ => value.into(),
+            })
+            .collect::<Vec<_>>();
+        let result = self
+            .builder
+            .build_call(implementation, &arguments, "Call")
+            .try_as_basic_value()
+            .left();
+
+        match (return_type, result) {
+            (Some(ir::VarType::String), Some(result)) => {
+                let returned = self.module.add_global(
+                    self.string_type(),
+                    None,
+                    &format!("viv_returned_{name}"),
+                );
+                returned.set_linkage(inkwell::module::Linkage::Internal);
+                returned.set_initializer(&self.string_type().const_null());
+                let returned = returned.as_pointer_value();
+
+                let previous = self
+                    .builder
+                    .build_load(self.string_type(), returned, "Previous");
+                self.builder.build_call(
+                    self.module.get_function("free").unwrap(),
+                    &[previous.into()],
+                    "free",
+                );
+                let copy = self.compile_builtin_call("viv_string_to_c", &[result.into()]);
+                self.builder.build_store(returned, copy);
+                self.compile_string_release(result.into_pointer_value());
+                self.builder.build_return(Some(&copy));
+            }
+            (_, Some(result)) => {
+                self.builder.build_return(Some(&result));
+            }
+            (_, None) => {
+                self.builder.build_return(None);
             }
         }
     }
 
+    /// Adds `main(argc, argv)` and positions the builder in it, after saving the arguments for the builtins
+    fn add_main_function(&mut self) -> inkwell::values::FunctionValue<'ctx> {
+        let i32_type = self.context.i32_type();
+        let argv_type = self
+            .string_type()
+            .ptr_type(inkwell::AddressSpace::default());
+        let function_type = i32_type.fn_type(&[i32_type.into(), argv_type.into()], false);
+        let function = self.module.add_function("main", function_type, None);
+        let entry_block = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry_block);
+
+        let argc = self.module.get_global("viv_argc").unwrap();
+        self.builder
+            .build_store(argc.as_pointer_value(), function.get_nth_param(0).unwrap());
+        let argv = self.module.get_global("viv_argv").unwrap();
+        self.builder
+            .build_store(argv.as_pointer_value(), function.get_nth_param(1).unwrap());
+
+        function
+    }
+
+    fn test_function_name(name: &str) -> String {
+        format!("test {name}")
+    }
+
+    fn compile_test_harness(&mut self, tests: &[String]) {
+        let i32_type = self.context.i32_type();
+        self.add_main_function();
+
+        let printf = self.module.get_function("printf").unwrap();
+        let ok_format = self
+            .builder
+            .build_global_string_ptr("test %s ... ok\n", "Test_Ok")
+            .as_pointer_value();
+        let failed_format = self
+            .builder
+            .build_global_string_ptr("test %s ... FAILED\n", "Test_Failed")
+            .as_pointer_value();
+
+        let mut failures = i32_type.const_int(0, false);
+        for test in tests {
+            let test_function = self
+                .module
+                .get_function(&Self::test_function_name(test))
+                .unwrap();
+            let result = self
+                .builder
+                .build_call(test_function, &[], "Test_Result")
+                .try_as_basic_value()
+                .left()
+                .unwrap()
+                .into_int_value();
+
+            let zero = i32_type.const_int(0, false);
+            let failed =
+                self.builder
+                    .build_int_compare(IntPredicate::NE, result, zero, "Test_Failed");
+            let format_string =
+                self.builder
+                    .build_select(failed, failed_format, ok_format, "Test_Format");
+            let test_name = self
+                .builder
+                .build_global_string_ptr(test, "Test_Name")
+                .as_pointer_value();
+            self.builder
+                .build_call(printf, &[format_string.into(), test_name.into()], "printf");
+
+            let failed = self.builder.build_int_z_extend(failed, i32_type, "Failed");
+            failures = self.builder.build_int_add(failures, failed, "Failures");
+        }
+
+        let total = i32_type.const_int(tests.len() as u64, false);
+        let passed = self.builder.build_int_sub(total, failures, "Passed");
+        let summary_format = self
+            .builder
+            .build_global_string_ptr("\n%d passed, %d failed\n", "Test_Summary")
+            .as_pointer_value();
+        self.builder.build_call(
+            printf,
+            &[summary_format.into(), passed.into(), failures.into()],
+            "printf",
+        );
+
+        let any_failed = self.builder.build_int_compare(
+            IntPredicate::NE,
+            failures,
+            i32_type.const_int(0, false),
+            "Any_Failed",
+        );
+        let exit_code = self
+            .builder
+            .build_int_z_extend(any_failed, i32_type, "Exit_Code");
+        if self.leak_check {
+            self.compile_leak_check();
+        }
+        self.builder.build_return(Some(&exit_code));
+    }
+
+    /// Adds a global initialized with a constant, named `viv_global_<name>` so it can't clash with C symbols.
+    ///
+    /// Globals have internal linkage, so every object file compiled from a module that declares a `static mut`
+    /// gets its own copy of it. Separately compiled objects don't share statics, only exported functions.
+    fn compile_global(
+        &mut self,
+        name: &str,
+        identifier: ir::VariableIdentifier,
+        value: &ir::Constant,
+    ) {
+        let symbol = format!("viv_global_{name}");
+        let initializer: inkwell::values::BasicValueEnum = match value {
+            ir::Constant::Int(int) => self.int_type().const_int(*int as u64, false).into(),
+            ir::Constant::Boolean(boolean) => self
+                .context
+                .bool_type()
+                .const_int(*boolean as u64, false)
+                .into(),
+            ir::Constant::String(string) => self
+                .compile_constant_string(string, &format!("{symbol}_text"))
+                .into(),
+            ir::Constant::Char(value) => self.char_type().const_int(*value as u64, false).into(),
+        };
+
+        let global = self
+            .module
+            .add_global(self.llvm_type(value.var_type()), None, &symbol);
+        global.set_linkage(inkwell::module::Linkage::Internal);
+        global.set_initializer(&initializer);
+        self.globals.insert(identifier, global.as_pointer_value());
+    }
+
     fn compile_top_level_statement(&mut self, statement: &ir::ToplevelStatement) {
         match statement {
             ir::ToplevelStatement::Function {
                 name,
-                body: statements,
+                parameters,
+                return_type,
+                body,
                 locals,
             } => {
-                let i32_type = self.context.i32_type();
-                let function_type = i32_type.fn_type(&[], false);
-                let function = self.module.add_function(name, function_type, None);
-                let entry_block = self.context.append_basic_block(function, "entry");
-                self.builder.position_at_end(entry_block);
-
-                self.local_vars.clear();
-                for (identifier, var_type) in locals {
-                    match var_type {
-                        ir::VarType::Int => {
-                            let int_type = self.int_type();
-                            let var = self
-                                .builder
-                                .build_alloca(int_type, &format!("var_{}", identifier.0));
-                            self.local_vars.insert(*identifier, var);
-                        }
-                        ir::VarType::Boolean => {
-                            let bool_type = self.context.bool_type();
-                            let var = self
-                                .builder
-                                .build_alloca(bool_type, &format!("var_{}", identifier.0));
-                            self.local_vars.insert(*identifier, var);
-                        }
-                    }
-                }
-
-                for statement in statements {
-                    self.compile_statement(statement);
-                }
-
-                self.builder
-                    .build_return(Some(&i32_type.const_int(0, false)));
+                self.in_test = false;
+                self.compile_function(name, parameters, *return_type, body, locals);
+            }
+            ir::ToplevelStatement::Test { name, body, locals } => {
+                self.in_test = true;
+                self.compile_function(&Self::test_function_name(name), &[], None, body, locals);
+            }
+            ir::ToplevelStatement::TestHarness(tests) => self.compile_test_harness(tests),
+            // globals and extern functions are added up front by `compile_module`
+            ir::ToplevelStatement::ExternFunction { .. } | ir::ToplevelStatement::Global { .. } => {
             }
         }
     }
 
-    pub fn compile_module(&mut self, module: &ir::Module) {
+    pub fn compile_module(&mut self, module: &ir::Module) -> anyhow::Result<()> {
         self.compile_libc_definitions();
+        self.compile_runtime_definitions();
+
+        for statement in &module.0 {
+            if let ir::ToplevelStatement::Global {
+                name,
+                identifier,
+                value,
+            } = statement
+            {
+                self.compile_global(name, *identifier, value);
+            }
+        }
+
+        let defined = module
+            .0
+            .iter()
+            .filter_map(|statement| match statement {
+                ir::ToplevelStatement::Function { name, .. } => Some(name),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // exported functions can be called before their definition
+        let mut wrapped = Vec::new();
+        for statement in &module.0 {
+            match statement {
+                ir::ToplevelStatement::Function {
+                    name,
+                    parameters,
+                    return_type,
+                    ..
+                } if name != "main" => {
+                    let parameters = parameters
+                        .iter()
+                        .map(|parameter| parameter.var_type)
+                        .collect::<Vec<_>>();
+                    self.declare_function(name, &parameters, *return_type)?;
+
+                    if Self::needs_c_wrapper(&parameters, *return_type) {
+                        let implementation = format!("viv_impl_{name}");
+                        self.declare_function(&implementation, &parameters, *return_type)?
+                            .set_linkage(inkwell::module::Linkage::Internal);
+                        self.implementations.insert(name.clone(), implementation);
+                        wrapped.push((name, parameters, *return_type));
+                    }
+                }
+                ir::ToplevelStatement::ExternFunction {
+                    name,
+                    parameters,
+                    return_type,
+                } => {
+                    self.declare_function(name, parameters, *return_type)?;
+                    // one module can declare a function as extern that another module defines
+                    if !defined.contains(&name) {
+                        self.externs.insert(name.clone(), *return_type);
+                    }
+                }
+                _ => {}
+            }
+        }
 
         for statement in &module.0 {
             self.compile_top_level_statement(statement);
         }
+        for (name, parameters, return_type) in wrapped {
+            self.compile_c_wrapper(name, &parameters, return_type);
+        }
+
+        Ok(())
     }
 
-    pub fn output_to_file(&mut self, file_path: &std::path::Path, options: &CompilerOptions) {
+    pub fn optimize(&mut self, options: &CompilerOptions) {
         if !options.dont_optimize {
             self.fpm.run_on(&self.module);
         }
+    }
 
-        self.module.write_bitcode_to_path(file_path);
+    /// The textual LLVM ir of the module
+    pub fn llvm_ir(&self) -> String {
+        self.module.print_to_string().to_string()
+    }
 
-        if options.output_llvm {
-            self.module.print_to_stderr();
-        }
+    /// Generates machine code for the host and returns the contents of the object file
+    pub fn object(&self, position_independent: bool) -> anyhow::Result<Vec<u8>> {
+        use inkwell::targets::{
+            CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine,
+        };
+
+        Target::initialize_native(&InitializationConfig::default()).map_err(anyhow::Error::msg)?;
+        let triple = TargetMachine::get_default_triple();
+        let target = Target::from_triple(&triple).map_err(|error| anyhow::anyhow!("{error}"))?;
+        let relocation_mode = if position_independent {
+            RelocMode::PIC
+        } else {
+            RelocMode::Default
+        };
+        let machine = target
+            .create_target_machine(
+                &triple,
+                "generic",
+                "",
+                inkwell::OptimizationLevel::Default,
+                relocation_mode,
+                CodeModel::Default,
+            )
+            .ok_or(anyhow::anyhow!("Can't generate code for {triple}"))?;
+
+        self.module.set_triple(&triple);
+        self.module
+            .set_data_layout(&machine.get_target_data().get_data_layout());
+        let buffer = machine
+            .write_to_memory_buffer(&self.module, FileType::Object)
+            .map_err(|error| anyhow::anyhow!("{error}"))?;
+
+        Ok(buffer.as_slice().to_vec())
     }
 }
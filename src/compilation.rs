@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{code_gen, ir, parsing, type_analyzer, CompilerOptions};
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A message about the compiled program, with its position if it's known
+#[derive(Debug, Serialize, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// The lint that produced this diagnostic, for warnings and denied warnings
+    pub lint: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub char: Option<usize>,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let (Some(file), Some(line)) = (&self.file, self.line) {
+            write!(f, "{file}:{line}: ")?;
+        }
+        write!(f, "{}", self.message)?;
+        if let Some(lint) = &self.lint {
+            write!(f, " [{lint}]")?;
+        }
+        Ok(())
+    }
+}
+
+impl Diagnostic {
+    /// A diagnostic for an error, in the file that was being processed when it happened
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        let position = error.chain().find_map(|cause| {
+            if let Some(error) = cause.downcast_ref::<parsing::ParsingError>() {
+                Some((error.line, error.char))
+            } else {
+                cause
+                    .downcast_ref::<parsing::tokens::TokenizerError>()
+                    .map(|error| (error.line, error.char))
+            }
+        });
+
+        Self {
+            severity: Severity::Error,
+            message: format!("{error:#}"),
+            lint: None,
+            file: error
+                .downcast_ref::<parsing::FileContext>()
+                .map(|context| context.file.clone()),
+            line: position.map(|(line, _)| line),
+            char: position.map(|(_, char)| char),
+        }
+    }
+
+    fn from_warning(warning: ir::lints::Warning, options: &CompilerOptions) -> Option<Self> {
+        let lint = warning.lint.to_string();
+        let severity = if options.allowed_lints.contains(&lint) {
+            return None;
+        } else if options.deny_warnings || options.denied_lints.contains(&lint) {
+            Severity::Error
+        } else {
+            Severity::Warning
+        };
+
+        Some(Self {
+            severity,
+            message: warning.message,
+            lint: Some(lint),
+            file: warning.file,
+            line: warning.line,
+            char: None,
+        })
+    }
+}
+
+/// Everything produced while compiling a program, each stage is only filled in if the ones before it succeeded
+#[derive(Debug, Default)]
+pub struct Compilation {
+    pub diagnostics: Vec<Diagnostic>,
+    /// The tokens of every module of the program
+    pub tokens: Vec<parsing::ModuleTokens>,
+    pub ast: Option<parsing::ast::Program>,
+    pub ir: Option<ir::Module>,
+    pub llvm_ir: Option<String>,
+    /// The contents of the object file
+    pub object: Option<Vec<u8>>,
+}
+
+impl Compilation {
+    pub fn first_error(&self) -> Option<&Diagnostic> {
+        self.diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    /// Whether a `# viv:allow(...)` comment on the line of the warning or the line before allows it
+    fn is_suppressed(&self, warning: &ir::lints::Warning) -> bool {
+        let (Some(ast), Some(file), Some(line)) = (&self.ast, &warning.file, warning.line) else {
+            return false;
+        };
+
+        ast.0
+            .iter()
+            .filter(|module| &module.file == file)
+            .flat_map(|module| &module.suppressions)
+            .any(|suppression| {
+                suppression.lint == warning.lint
+                    && (suppression.line == line || suppression.line + 1 == line)
+            })
+    }
+
+    fn run(
+        &mut self,
+        file_name: &str,
+        source: &str,
+        options: &CompilerOptions,
+    ) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        for lint in options.denied_lints.iter().chain(&options.allowed_lints) {
+            if !ir::lints::LINTS.contains(&lint.as_str()) {
+                anyhow::bail!("Unknown lint {lint}");
+            }
+        }
+
+        let (ir, variables, mut warnings) = if is_ir_file(file_name) {
+            // hand written ir has no variable names to lint
+            (
+                ir::parse(source).with_context(|| parsing::FileContext {
+                    action: "Parsing ir",
+                    file: file_name.to_string(),
+                })?,
+                HashMap::new(),
+                Vec::new(),
+            )
+        } else {
+            let (program, tokens) =
+                parsing::parse_program(file_name, Some(source)).context("Parsing input file")?;
+            self.tokens = tokens;
+            let ast = self.ast.insert(program);
+
+            for module in &ast.0 {
+                for suppression in &module.suppressions {
+                    if !ir::lints::LINTS.contains(&suppression.lint.as_str()) {
+                        anyhow::bail!(
+                            "Unknown lint {} in viv:allow comment at {}:{}",
+                            suppression.lint,
+                            module.file,
+                            suppression.line
+                        );
+                    }
+                }
+            }
+
+            let mut analyzer = type_analyzer::Analyzer::new(options.test_mode);
+            let ir = analyzer.resolve_program(ast).context("Resolving types")?;
+            (ir, analyzer.variables().clone(), analyzer.take_warnings())
+        };
+        self.ir = Some(ir);
+        let ir = self.ir.as_ref().unwrap();
+
+        if options.verify_ir || cfg!(debug_assertions) {
+            ir::validate(ir).context("Validating ir")?;
+        }
+
+        warnings.extend(ir::lints::lint(ir, &variables));
+        let diagnostics = warnings
+            .into_iter()
+            .filter(|warning| !self.is_suppressed(warning))
+            .filter_map(|warning| Diagnostic::from_warning(warning, options))
+            .collect::<Vec<_>>();
+        self.diagnostics.extend(diagnostics);
+        if self.first_error().is_some() {
+            return Ok(());
+        }
+
+        let module_name = std::path::Path::new(file_name)
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .unwrap_or("main");
+
+        let inkwell_context = inkwell::context::Context::create();
+        let mut code_gen =
+            code_gen::CodeGen::new(&inkwell_context, module_name, options.leak_check);
+        code_gen.compile_module(ir).context("Generating code")?;
+        code_gen.optimize(options);
+        self.llvm_ir = Some(code_gen.llvm_ir());
+
+        self.object = Some(
+            code_gen
+                .object(options.position_independent)
+                .context("Generating object file")?,
+        );
+
+        Ok(())
+    }
+}
+
+/// Files ending in `.vir` contain ir in its textual syntax, skipping the front end
+fn is_ir_file(file_name: &str) -> bool {
+    file_name.ends_with(".vir")
+}
+
+/// Compiles the source of a file, imports are read from disk relative to `file_name`.
+///
+/// Nothing is printed, all errors end up in the diagnostics of the result.
+/// The object file is generated in memory for the host, no external tools are needed until linking.
+pub fn compile(file_name: &str, source: &str, options: &CompilerOptions) -> Compilation {
+    let mut compilation = Compilation::default();
+
+    if let Err(error) = compilation.run(file_name, source, options) {
+        compilation.diagnostics.push(Diagnostic::from_error(&error));
+    }
+
+    compilation
+}
@@ -0,0 +1,406 @@
+//! Dataflow pass over every function looking for variables that are never read, stores that are
+//! overwritten before being read and code that can never run.
+//!
+//! The names of all lints live here, including the ones the type analyzer reports while resolving
+//! expressions.
+
+use std::collections::HashMap;
+
+use super::*;
+
+pub const UNUSED_VARIABLE: &str = "unused_variable";
+pub const DEAD_STORE: &str = "dead_store";
+pub const UNREACHABLE_CODE: &str = "unreachable_code";
+pub const CONSTANT_CONDITION: &str = "constant_condition";
+pub const SELF_COMPARISON: &str = "self_comparison";
+pub const REDUNDANT_NOT: &str = "redundant_not";
+pub const UNUSED_VALUE: &str = "unused_value";
+
+/// Every lint the compiler can report
+pub const LINTS: [&str; 7] = [
+    UNUSED_VARIABLE,
+    DEAD_STORE,
+    UNREACHABLE_CODE,
+    CONSTANT_CONDITION,
+    SELF_COMPARISON,
+    REDUNDANT_NOT,
+    UNUSED_VALUE,
+];
+
+#[derive(Debug)]
+pub struct Warning {
+    pub lint: &'static str,
+    pub message: String,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+}
+
+/// Collects the variables read by expressions
+#[derive(Default)]
+struct Reads(Vec<VariableIdentifier>);
+
+impl Reads {
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Int(expression) => self.int_expression(expression),
+            Value::Boolean(expression) => self.bool_expression(expression),
+            Value::String(expression) => self.string_expression(expression),
+            Value::Char(expression) => self.char_expression(expression),
+            Value::List(expression) => self.list_expression(expression),
+        }
+    }
+
+    fn call(&mut self, call: &Call) {
+        for argument in &call.arguments {
+            self.value(argument);
+        }
+    }
+
+    fn int_expression(&mut self, expression: &IntExpression) {
+        match expression {
+            IntExpression::Literal(_) | IntExpression::ReadInt | IntExpression::ArgCount => {}
+            IntExpression::Negate(expression) => self.int_expression(expression),
+            IntExpression::BinaryOperation(left, _, right) => {
+                self.int_expression(left);
+                self.int_expression(right);
+            }
+            IntExpression::Var(identifier) => self.0.push(*identifier),
+            IntExpression::ParseInt(expression) => self.string_expression(expression),
+            IntExpression::Call(call) => self.call(call),
+            IntExpression::If(condition, then_value, else_value) => {
+                self.bool_expression(condition);
+                self.int_expression(then_value);
+                self.int_expression(else_value);
+            }
+            IntExpression::Index(list, index) => {
+                self.list_expression(list);
+                self.int_expression(index);
+            }
+            IntExpression::Pop(list) | IntExpression::Length(list) => self.list_expression(list),
+            IntExpression::FromChar(expression) => self.char_expression(expression),
+        }
+    }
+
+    fn string_expression(&mut self, expression: &StringExpression) {
+        match expression {
+            StringExpression::Literal(_) | StringExpression::ReadLine => {}
+            StringExpression::Var(identifier) => self.0.push(*identifier),
+            StringExpression::Arg(index) => self.int_expression(index),
+            StringExpression::Call(call) => self.call(call),
+            StringExpression::If(condition, then_value, else_value) => {
+                self.bool_expression(condition);
+                self.string_expression(then_value);
+                self.string_expression(else_value);
+            }
+            StringExpression::Index(list, index) => {
+                self.list_expression(list);
+                self.int_expression(index);
+            }
+            StringExpression::Pop(list) => self.list_expression(list),
+        }
+    }
+
+    fn char_expression(&mut self, expression: &CharExpression) {
+        match expression {
+            CharExpression::Literal(_) => {}
+            CharExpression::Var(identifier) => self.0.push(*identifier),
+            CharExpression::FromInt(expression) => self.int_expression(expression),
+            CharExpression::Call(call) => self.call(call),
+            CharExpression::If(condition, then_value, else_value) => {
+                self.bool_expression(condition);
+                self.char_expression(then_value);
+                self.char_expression(else_value);
+            }
+            CharExpression::Index(list, index) => {
+                self.list_expression(list);
+                self.int_expression(index);
+            }
+            CharExpression::Pop(list) => self.list_expression(list),
+        }
+    }
+
+    fn bool_expression(&mut self, expression: &BooleanExpression) {
+        match expression {
+            BooleanExpression::Literal(_) => {}
+            BooleanExpression::Not(expression) => self.bool_expression(expression),
+            BooleanExpression::Comparison(ComparisonExpression::IntComparison(first, rest)) => {
+                self.int_expression(first);
+                for (_, expression) in rest {
+                    self.int_expression(expression);
+                }
+            }
+            BooleanExpression::Comparison(ComparisonExpression::CharComparison(first, rest)) => {
+                self.char_expression(first);
+                for (_, expression) in rest {
+                    self.char_expression(expression);
+                }
+            }
+            BooleanExpression::Operator(_, left, _, right) => {
+                self.bool_expression(left);
+                self.bool_expression(right);
+            }
+            BooleanExpression::Var(identifier) => self.0.push(*identifier),
+            BooleanExpression::Call(call) => self.call(call),
+            BooleanExpression::If(condition, then_value, else_value) => {
+                self.bool_expression(condition);
+                self.bool_expression(then_value);
+                self.bool_expression(else_value);
+            }
+            BooleanExpression::Index(list, index) => {
+                self.list_expression(list);
+                self.int_expression(index);
+            }
+            BooleanExpression::Pop(list) => self.list_expression(list),
+        }
+    }
+
+    fn list_expression(&mut self, expression: &ListExpression) {
+        match expression {
+            ListExpression::Literal(_, values) => {
+                for value in values {
+                    self.value(value);
+                }
+            }
+            ListExpression::Var(identifier) => self.0.push(*identifier),
+            ListExpression::Call(call) => self.call(call),
+            ListExpression::If(condition, then_value, else_value) => {
+                self.bool_expression(condition);
+                self.list_expression(then_value);
+                self.list_expression(else_value);
+            }
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Print(values) => {
+                for value in values {
+                    match value {
+                        PrintValue::Int(expression) => self.int_expression(expression),
+                        PrintValue::Boolean(expression) => self.bool_expression(expression),
+                        PrintValue::String(expression) => self.string_expression(expression),
+                        PrintValue::Char(expression) => self.char_expression(expression),
+                        PrintValue::Text(_) => {}
+                    }
+                }
+            }
+            Statement::Assert(expression, _, _) => self.bool_expression(expression),
+            Statement::Assignment(_, value) => match value {
+                AssignmentStatement::Int(expression) => self.int_expression(expression),
+                AssignmentStatement::Boolean(expression) => self.bool_expression(expression),
+                AssignmentStatement::String(expression) => self.string_expression(expression),
+                AssignmentStatement::Char(expression) => self.char_expression(expression),
+                AssignmentStatement::List(expression) => self.list_expression(expression),
+            },
+            Statement::CompoundAssignment(identifier, _, expression) => {
+                self.0.push(*identifier);
+                self.int_expression(expression);
+            }
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    self.value(value);
+                }
+            }
+            Statement::Exit(expression) => self.int_expression(expression),
+            Statement::Call(call) => self.call(call),
+            Statement::While(condition, body) => {
+                self.bool_expression(condition);
+                for statement in body {
+                    self.statement(statement);
+                }
+            }
+            Statement::Push(list, value) => {
+                self.list_expression(list);
+                self.value(value);
+            }
+            Statement::SetIndex(list, index, value) => {
+                self.list_expression(list);
+                self.int_expression(index);
+                self.value(value);
+            }
+        }
+    }
+}
+
+/// State of a variable while walking through a function
+#[derive(Default)]
+struct VariableState {
+    ever_read: bool,
+    /// The last store hasn't been read yet
+    pending_store: bool,
+}
+
+struct Linter<'a> {
+    variables: &'a HashMap<VariableIdentifier, VariableOrigin>,
+    warnings: Vec<Warning>,
+}
+
+impl Linter<'_> {
+    /// Warns about a variable at its declaration, `describe` gets the name of the variable
+    fn variable_warning(
+        &mut self,
+        lint: &'static str,
+        identifier: VariableIdentifier,
+        describe: impl FnOnce(&str) -> String,
+    ) {
+        let origin = &self.variables[&identifier];
+        // like in rust, a leading underscore marks a variable as intentionally unused
+        if origin.name.starts_with('_') {
+            return;
+        }
+
+        self.warnings.push(Warning {
+            lint,
+            message: describe(&origin.name),
+            file: Some(origin.file.clone()),
+            line: Some(origin.line),
+        });
+    }
+
+    fn read(states: &mut HashMap<VariableIdentifier, VariableState>, reads: Reads) {
+        for identifier in reads.0 {
+            let state = states.entry(identifier).or_default();
+            state.ever_read = true;
+            state.pending_store = false;
+        }
+    }
+
+    fn statements(
+        &mut self,
+        body: &[Statement],
+        states: &mut HashMap<VariableIdentifier, VariableState>,
+    ) {
+        for statement in body {
+            if let Statement::While(condition, loop_body) = statement {
+                self.while_loop(condition, loop_body, states);
+                continue;
+            }
+
+            let mut reads = Reads::default();
+            reads.statement(statement);
+            Self::read(states, reads);
+
+            if let Statement::Assignment(identifier, _)
+            | Statement::CompoundAssignment(identifier, ..) = statement
+            {
+                if self.variables.contains_key(identifier) {
+                    let state = states.entry(*identifier).or_default();
+                    if state.pending_store {
+                        self.variable_warning(DEAD_STORE, *identifier, |name| {
+                            format!("value assigned to `{name}` is overwritten before being read")
+                        });
+                    }
+                    state.pending_store = true;
+                }
+            }
+
+            if let Some((reason, location)) = Self::terminator(statement) {
+                if !std::ptr::eq(statement, body.last().unwrap()) {
+                    self.warnings.push(Warning {
+                        lint: UNREACHABLE_CODE,
+                        message: format!("unreachable code after {reason}"),
+                        file: location.map(|location| location.file.clone()),
+                        line: location.map(|location| location.line),
+                    });
+                }
+                break;
+            }
+        }
+    }
+
+    /// The body may run any number of times, including none
+    fn while_loop(
+        &mut self,
+        condition: &BooleanExpression,
+        body: &[Statement],
+        states: &mut HashMap<VariableIdentifier, VariableState>,
+    ) {
+        // whatever the loop reads can be stored before it or by an earlier run of the body
+        let mut loop_reads = Reads::default();
+        loop_reads.bool_expression(condition);
+        for statement in body {
+            loop_reads.statement(statement);
+        }
+        let loop_reads = loop_reads.0;
+        Self::read(states, Reads(loop_reads.clone()));
+
+        // stores from before the loop stay unread when the body doesn't run, but the body
+        // overwriting them isn't a dead store since it might not run
+        let pending_before = states
+            .iter_mut()
+            .filter(|(_, state)| state.pending_store)
+            .map(|(identifier, state)| {
+                state.pending_store = false;
+                *identifier
+            })
+            .collect::<Vec<_>>();
+
+        self.statements(body, states);
+
+        Self::read(states, Reads(loop_reads));
+        for identifier in pending_before {
+            states.get_mut(&identifier).unwrap().pending_store = true;
+        }
+    }
+
+    /// Describes why the statements after this one can't run, if they can't
+    fn terminator(statement: &Statement) -> Option<(&'static str, Option<&SourceLocation>)> {
+        match statement {
+            Statement::Assert(BooleanExpression::Literal(false), _, location) => {
+                Some(("assert false", Some(location)))
+            }
+            Statement::Return(_) => Some(("return", None)),
+            Statement::Exit(_) => Some(("exit", None)),
+            _ => None,
+        }
+    }
+
+    fn function(&mut self, body: &[Statement]) {
+        // only variables declared in the source are linted, not parameters or temporaries
+        let mut states = HashMap::<VariableIdentifier, VariableState>::new();
+        self.statements(body, &mut states);
+
+        let mut states = states
+            .into_iter()
+            .filter(|(identifier, _)| self.variables.contains_key(identifier))
+            .collect::<Vec<_>>();
+        // report in declaration order
+        states.sort_by_key(|(identifier, _)| identifier.0);
+
+        for (identifier, state) in states {
+            if !state.ever_read {
+                self.variable_warning(UNUSED_VARIABLE, identifier, |name| {
+                    format!("unused variable `{name}`")
+                });
+            } else if state.pending_store {
+                self.variable_warning(DEAD_STORE, identifier, |name| {
+                    format!("value assigned to `{name}` is never read")
+                });
+            }
+        }
+    }
+}
+
+/// Lints every function of a module, `variables` gives the names of the variables to lint
+pub fn lint(
+    module: &Module,
+    variables: &HashMap<VariableIdentifier, VariableOrigin>,
+) -> Vec<Warning> {
+    let mut linter = Linter {
+        variables,
+        warnings: Vec::new(),
+    };
+
+    for statement in &module.0 {
+        match statement {
+            ToplevelStatement::Function { body, .. } | ToplevelStatement::Test { body, .. } => {
+                linter.function(body)
+            }
+            ToplevelStatement::TestHarness(_)
+            | ToplevelStatement::ExternFunction { .. }
+            | ToplevelStatement::Global { .. } => {}
+        }
+    }
+
+    linter.warnings
+}
@@ -0,0 +1,312 @@
+pub use crate::parsing::ast::SourceLocation;
+use crate::IntType;
+pub use parser::{parse, IrParsingError};
+pub use validator::{validate, IrValidationError};
+
+pub mod lints;
+mod parser;
+mod printer;
+mod validator;
+
+#[derive(Debug)]
+pub struct Module(pub Vec<ToplevelStatement>);
+
+#[derive(Debug)]
+pub enum ToplevelStatement {
+    /// The main function, or an exported function called with the C calling convention
+    Function {
+        name: String,
+        parameters: Vec<Parameter>,
+        return_type: Option<VarType>,
+        body: Vec<Statement>,
+        locals: Vec<(VariableIdentifier, VarType)>,
+    },
+    /// A test block, failing asserts return from it instead of aborting the program
+    Test {
+        name: String,
+        body: Vec<Statement>,
+        locals: Vec<(VariableIdentifier, VarType)>,
+    },
+    /// Main function running every named test and reporting the results
+    TestHarness(Vec<String>),
+    /// A function implemented outside of viv, called with the C calling convention.
+    ///
+    /// Strings and lists are only borrowed for the call. A returned string still belongs to C and is copied,
+    /// a returned list is owned by the caller like the lists returned by exported functions
+    ExternFunction {
+        name: String,
+        parameters: Vec<VarType>,
+        return_type: Option<VarType>,
+    },
+    /// A `static mut` variable, every function can read and assign it like a local
+    Global {
+        name: String,
+        identifier: VariableIdentifier,
+        value: Constant,
+    },
+}
+
+/// A value known at compile time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constant {
+    Int(IntType),
+    Boolean(bool),
+    String(String),
+    Char(char),
+}
+
+impl Constant {
+    pub fn var_type(&self) -> VarType {
+        match self {
+            Constant::Int(_) => VarType::Int,
+            Constant::Boolean(_) => VarType::Boolean,
+            Constant::String(_) => VarType::String,
+            Constant::Char(_) => VarType::Char,
+        }
+    }
+}
+
+/// A function parameter, it is also listed in the locals of the function
+#[derive(Debug)]
+pub struct Parameter {
+    pub name: String,
+    pub identifier: VariableIdentifier,
+    pub var_type: VarType,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum VarType {
+    Int,
+    Boolean,
+    String,
+    /// A unicode scalar value, stored as its code point
+    Char,
+    /// A growable list on the heap, copying a list copies the reference to it
+    List(ElementType),
+}
+
+/// What a list can hold, lists of lists aren't supported
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ElementType {
+    Int,
+    Boolean,
+    String,
+    Char,
+}
+
+impl ElementType {
+    pub fn var_type(self) -> VarType {
+        match self {
+            ElementType::Int => VarType::Int,
+            ElementType::Boolean => VarType::Boolean,
+            ElementType::String => VarType::String,
+            ElementType::Char => VarType::Char,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct VariableIdentifier(pub usize);
+
+/// Where a variable was declared in the source, temporaries don't have one
+#[derive(Debug, Clone)]
+pub struct VariableOrigin {
+    pub name: String,
+    pub file: String,
+    pub line: usize,
+}
+
+/// Every variable of a function and its type, including parameters and temporaries
+pub type Locals = Vec<(VariableIdentifier, VarType)>;
+
+#[derive(Debug)]
+pub enum Statement {
+    Print(Vec<PrintValue>),
+    Assert(BooleanExpression, Option<String>, SourceLocation),
+    Assignment(VariableIdentifier, AssignmentStatement),
+    /// `x += 1`, the variable is only loaded once
+    CompoundAssignment(VariableIdentifier, IntBinaryOp, IntExpression),
+    Return(Option<Value>),
+    Exit(IntExpression),
+    /// Calls a function and discards its result
+    Call(Call),
+    /// Runs the body as long as the condition holds, the condition is checked before every run
+    While(BooleanExpression, Vec<Statement>),
+    /// Appends a value of the element type to the end of the list
+    Push(ListExpression, Value),
+    /// Replaces the element at an index, aborting if it is out of range
+    SetIndex(ListExpression, IntExpression, Value),
+}
+
+#[derive(Debug)]
+pub struct Call {
+    pub function: String,
+    pub arguments: Vec<Value>,
+}
+
+/// An expression of any type
+#[derive(Debug)]
+pub enum Value {
+    Int(IntExpression),
+    Boolean(BooleanExpression),
+    String(StringExpression),
+    Char(CharExpression),
+    List(ListExpression),
+}
+
+#[derive(Debug)]
+pub enum AssignmentStatement {
+    Int(IntExpression),
+    Boolean(BooleanExpression),
+    String(StringExpression),
+    Char(CharExpression),
+    List(ListExpression),
+}
+
+/// One piece of a print statement, the pieces are printed after each other followed by a newline
+#[derive(Debug)]
+pub enum PrintValue {
+    Int(IntExpression),
+    Boolean(BooleanExpression),
+    String(StringExpression),
+    Char(CharExpression),
+    Text(String),
+}
+
+#[derive(Debug)]
+pub enum IntExpression {
+    Literal(i32),
+    Negate(Box<IntExpression>),
+    BinaryOperation(Box<IntExpression>, IntBinaryOp, Box<IntExpression>),
+    Var(VariableIdentifier),
+    /// Reads an int from stdin
+    ReadInt,
+    /// Number of command line arguments, not counting the program name
+    ArgCount,
+    ParseInt(Box<StringExpression>),
+    Call(Call),
+    /// Only the chosen branch is evaluated
+    If(
+        Box<BooleanExpression>,
+        Box<IntExpression>,
+        Box<IntExpression>,
+    ),
+    /// Element of a list, aborts if the index is out of range
+    Index(Box<ListExpression>, Box<IntExpression>),
+    /// Removes the last element of a list and returns it, aborts if the list is empty
+    Pop(Box<ListExpression>),
+    /// Number of elements in a list
+    Length(Box<ListExpression>),
+    /// Code point of a char
+    FromChar(Box<CharExpression>),
+}
+
+#[derive(Debug)]
+pub enum IntBinaryOp {
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+}
+
+#[derive(Debug)]
+pub enum StringExpression {
+    Literal(String),
+    Var(VariableIdentifier),
+    /// Reads a line from stdin, without the trailing newline
+    ReadLine,
+    /// Command line argument, `Arg(0)` is the first argument after the program name
+    Arg(Box<IntExpression>),
+    Call(Call),
+    /// Only the chosen branch is evaluated
+    If(
+        Box<BooleanExpression>,
+        Box<StringExpression>,
+        Box<StringExpression>,
+    ),
+    /// Element of a list, aborts if the index is out of range
+    Index(Box<ListExpression>, Box<IntExpression>),
+    /// Removes the last element of a list and returns it, aborts if the list is empty
+    Pop(Box<ListExpression>),
+}
+
+#[derive(Debug)]
+pub enum CharExpression {
+    Literal(char),
+    Var(VariableIdentifier),
+    /// The char with a code point, aborts if it isn't a unicode scalar value
+    FromInt(Box<IntExpression>),
+    Call(Call),
+    /// Only the chosen branch is evaluated
+    If(
+        Box<BooleanExpression>,
+        Box<CharExpression>,
+        Box<CharExpression>,
+    ),
+    /// Element of a list, aborts if the index is out of range
+    Index(Box<ListExpression>, Box<IntExpression>),
+    /// Removes the last element of a list and returns it, aborts if the list is empty
+    Pop(Box<ListExpression>),
+}
+
+#[derive(Debug)]
+pub enum BooleanExpression {
+    Literal(bool),
+    Not(Box<BooleanExpression>),
+    Comparison(ComparisonExpression),
+    Operator(
+        VariableIdentifier,
+        Box<BooleanExpression>,
+        BooleanOperator,
+        Box<BooleanExpression>,
+    ),
+    Var(VariableIdentifier),
+    Call(Call),
+    /// Only the chosen branch is evaluated
+    If(
+        Box<BooleanExpression>,
+        Box<BooleanExpression>,
+        Box<BooleanExpression>,
+    ),
+    /// Element of a list, aborts if the index is out of range
+    Index(Box<ListExpression>, Box<IntExpression>),
+    /// Removes the last element of a list and returns it, aborts if the list is empty
+    Pop(Box<ListExpression>),
+}
+
+#[derive(Debug)]
+pub enum ListExpression {
+    /// A new list holding the values, which are all of the element type
+    Literal(ElementType, Vec<Value>),
+    Var(VariableIdentifier),
+    Call(Call),
+    /// Only the chosen branch is evaluated
+    If(
+        Box<BooleanExpression>,
+        Box<ListExpression>,
+        Box<ListExpression>,
+    ),
+}
+
+#[derive(Debug)]
+pub enum BooleanOperator {
+    And,
+    Or,
+}
+
+#[derive(Debug)]
+pub enum ComparisonExpression {
+    IntComparison(Box<IntExpression>, Vec<(IntComparisonOp, IntExpression)>),
+    /// Chars are ordered by their code points
+    CharComparison(Box<CharExpression>, Vec<(IntComparisonOp, CharExpression)>),
+}
+
+#[derive(Debug)]
+pub enum IntComparisonOp {
+    Equal,
+    NotEquals,
+    LessThan,
+    LessThanEquals,
+    GreaterThan,
+    GreaterThanEquals,
+}
@@ -0,0 +1,863 @@
+//! Parses the textual ir syntax written by `ir::printer`, so ir can be written by hand for codegen tests.
+
+use std::collections::VecDeque;
+
+use thiserror::Error;
+
+use super::*;
+
+#[derive(Error, Debug)]
+#[error("IR parser error on line {line}: {message}")]
+pub struct IrParsingError {
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Identifier(String),
+    Integer(i64),
+    String(String),
+    Char(char),
+    Var(usize),
+    Symbol(&'static str),
+    Eof,
+}
+
+const SYMBOLS: [&str; 23] = [
+    "->", "==", "!=", "<=", ">=", "+=", "-=", "*=", "/=", "(", ")", "{", "}", ",", ":", "=", "<",
+    ">", "+", "-", "*", "/", "@",
+];
+
+fn tokenize(text: &str) -> Result<VecDeque<(Token, usize)>, IrParsingError> {
+    let mut tokens = VecDeque::new();
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut line = 1;
+    let mut position = 0;
+
+    let error = |line, message: String| IrParsingError { line, message };
+
+    while position < chars.len() {
+        let c = chars[position];
+        let rest = &chars[position..];
+        let starts_with = |text: &str| rest.iter().take(text.len()).copied().eq(text.chars());
+
+        if c == '\n' {
+            line += 1;
+            position += 1;
+        } else if c.is_whitespace() {
+            position += 1;
+        } else if c == '#' {
+            while position < chars.len() && chars[position] != '\n' {
+                position += 1;
+            }
+        } else if c.is_ascii_digit() || (c == '-' && rest.get(1).is_some_and(char::is_ascii_digit))
+        {
+            let length = 1 + rest[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let number = rest[..length].iter().collect::<String>();
+            let number = number
+                .parse()
+                .map_err(|_| error(line, format!("Invalid integer {number}")))?;
+            tokens.push_back((Token::Integer(number), line));
+            position += length;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let length = rest
+                .iter()
+                .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                .count();
+            let identifier = rest[..length].iter().collect();
+            tokens.push_back((Token::Identifier(identifier), line));
+            position += length;
+        } else if c == '%' {
+            let length = rest[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let number = rest[1..=length].iter().collect::<String>();
+            let number = number
+                .parse()
+                .map_err(|_| error(line, String::from("Expected a variable number after %")))?;
+            tokens.push_back((Token::Var(number), line));
+            position += 1 + length;
+        } else if c == '"' {
+            let mut string = String::new();
+            position += 1;
+            loop {
+                match chars.get(position) {
+                    Some('"') => break,
+                    Some('\\') => {
+                        string.push(match chars.get(position + 1) {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some('"') => '"',
+                            Some('\\') => '\\',
+                            _ => Err(error(line, String::from("Invalid escape in string")))?,
+                        });
+                        position += 2;
+                    }
+                    Some('\n') | None => Err(error(line, String::from("Unterminated string")))?,
+                    Some(c) => {
+                        string.push(*c);
+                        position += 1;
+                    }
+                }
+            }
+            tokens.push_back((Token::String(string), line));
+            position += 1;
+        } else if c == '\'' {
+            let invalid = || error(line, String::from("Invalid char literal"));
+            let (value, length) = match (rest.get(1), rest.get(2)) {
+                (Some('\\'), Some('n')) => ('\n', 2),
+                (Some('\\'), Some('t')) => ('\t', 2),
+                (Some('\\'), Some('\'')) => ('\'', 2),
+                (Some('\\'), Some('\\')) => ('\\', 2),
+                // \u{1f600}
+                (Some('\\'), Some('u')) if rest.get(3) == Some(&'{') => {
+                    let digits = rest[3..].iter().take_while(|c| **c != '}').count();
+                    let code = rest
+                        .get(4..3 + digits)
+                        .map(|digits| digits.iter().collect::<String>())
+                        .and_then(|digits| u32::from_str_radix(&digits, 16).ok())
+                        .and_then(char::from_u32)
+                        .ok_or_else(invalid)?;
+                    (code, 3 + digits)
+                }
+                // a quote has to be escaped, like in the printed ir
+                (Some('\\' | '\'' | '\n'), _) | (None, _) => Err(invalid())?,
+                (Some(c), _) => (*c, 1),
+            };
+            if rest.get(length + 1) != Some(&'\'') {
+                Err(invalid())?;
+            }
+            tokens.push_back((Token::Char(value), line));
+            position += length + 2;
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| starts_with(symbol)) {
+            tokens.push_back((Token::Symbol(symbol), line));
+            position += symbol.len();
+        } else {
+            Err(error(line, format!("Unexpected character: {c}")))?;
+        }
+    }
+
+    tokens.push_back((Token::Eof, line));
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: VecDeque<(Token, usize)>,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens.front().unwrap().0
+    }
+
+    fn advance(&mut self) -> Token {
+        if self.tokens.len() > 1 {
+            self.tokens.pop_front().unwrap().0
+        } else {
+            Token::Eof
+        }
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, IrParsingError> {
+        let (token, line) = self.tokens.front().unwrap();
+        Err(IrParsingError {
+            line: *line,
+            message: format!("expected {expected}, got {token:?}"),
+        })
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Identifier(identifier) if identifier == keyword)
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Token::Symbol(other) if *other == symbol)
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), IrParsingError> {
+        if !self.is_keyword(keyword) {
+            return self.error(keyword);
+        }
+        self.advance();
+        Ok(())
+    }
+
+    fn symbol(&mut self, symbol: &str) -> Result<(), IrParsingError> {
+        if !self.is_symbol(symbol) {
+            return self.error(&format!("'{symbol}'"));
+        }
+        self.advance();
+        Ok(())
+    }
+
+    fn identifier(&mut self) -> Result<String, IrParsingError> {
+        match self.peek() {
+            Token::Identifier(_) => match self.advance() {
+                Token::Identifier(identifier) => Ok(identifier),
+                _ => unreachable!(),
+            },
+            _ => self.error("an identifier"),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, IrParsingError> {
+        match self.peek() {
+            Token::String(_) => match self.advance() {
+                Token::String(string) => Ok(string),
+                _ => unreachable!(),
+            },
+            _ => self.error("a string"),
+        }
+    }
+
+    fn char(&mut self) -> Result<char, IrParsingError> {
+        match *self.peek() {
+            Token::Char(value) => {
+                self.advance();
+                Ok(value)
+            }
+            _ => self.error("a char"),
+        }
+    }
+
+    fn integer(&mut self) -> Result<i64, IrParsingError> {
+        match *self.peek() {
+            Token::Integer(value) => {
+                self.advance();
+                Ok(value)
+            }
+            _ => self.error("an integer"),
+        }
+    }
+
+    fn int_literal(&mut self) -> Result<i32, IrParsingError> {
+        let value = self.integer()?;
+        i32::try_from(value).map_err(|_| IrParsingError {
+            line: self.tokens.front().unwrap().1,
+            message: format!("integer {value} out of range"),
+        })
+    }
+
+    fn var(&mut self) -> Result<VariableIdentifier, IrParsingError> {
+        match *self.peek() {
+            Token::Var(identifier) => {
+                self.advance();
+                Ok(VariableIdentifier(identifier))
+            }
+            _ => self.error("a variable"),
+        }
+    }
+
+    /// Parses a comma separated list between parentheses
+    fn list<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, IrParsingError>,
+    ) -> Result<Vec<T>, IrParsingError> {
+        self.symbol("(")?;
+        let mut items = Vec::new();
+        while !self.is_symbol(")") {
+            if !items.is_empty() {
+                self.symbol(",")?;
+            }
+            items.push(item(self)?);
+        }
+        self.symbol(")")?;
+        Ok(items)
+    }
+
+    fn var_type(&mut self) -> Result<VarType, IrParsingError> {
+        let var_type = match self.peek() {
+            Token::Identifier(name) if name == "int" => VarType::Int,
+            Token::Identifier(name) if name == "bool" => VarType::Boolean,
+            Token::Identifier(name) if name == "string" => VarType::String,
+            Token::Identifier(name) if name == "char" => VarType::Char,
+            Token::Identifier(name) if name == "list" => {
+                self.advance();
+                return Ok(VarType::List(self.element_type()?));
+            }
+            _ => return self.error("int, bool, string, char or list"),
+        };
+        self.advance();
+        Ok(var_type)
+    }
+
+    /// Parses the `<int>` of `list<int>`
+    fn element_type(&mut self) -> Result<ElementType, IrParsingError> {
+        self.symbol("<")?;
+        let element_type = match self.peek() {
+            Token::Identifier(name) if name == "int" => ElementType::Int,
+            Token::Identifier(name) if name == "bool" => ElementType::Boolean,
+            Token::Identifier(name) if name == "string" => ElementType::String,
+            Token::Identifier(name) if name == "char" => ElementType::Char,
+            _ => return self.error("int, bool, string or char"),
+        };
+        self.advance();
+        self.symbol(">")?;
+        Ok(element_type)
+    }
+
+    fn is_var_type(&self) -> bool {
+        self.is_keyword("int")
+            || self.is_keyword("bool")
+            || self.is_keyword("string")
+            || self.is_keyword("char")
+            || self.is_keyword("list")
+    }
+
+    fn return_type(&mut self) -> Result<Option<VarType>, IrParsingError> {
+        if !self.is_symbol("->") {
+            return Ok(None);
+        }
+        self.advance();
+        Ok(Some(self.var_type()?))
+    }
+
+    fn module(&mut self) -> Result<Module, IrParsingError> {
+        let mut statements = Vec::new();
+        while self.peek() != &Token::Eof {
+            statements.push(self.toplevel_statement()?);
+        }
+        Ok(Module(statements))
+    }
+
+    fn toplevel_statement(&mut self) -> Result<ToplevelStatement, IrParsingError> {
+        let keyword = self.identifier()?;
+        match keyword.as_str() {
+            "fn" => {
+                let name = self.identifier()?;
+                let parameters = self.list(|parser| {
+                    let name = parser.identifier()?;
+                    parser.symbol(":")?;
+                    let var_type = parser.var_type()?;
+                    parser.symbol("=")?;
+                    let identifier = parser.var()?;
+                    Ok(Parameter {
+                        name,
+                        identifier,
+                        var_type,
+                    })
+                })?;
+                let return_type = self.return_type()?;
+                let (body, locals) = self.block()?;
+                Ok(ToplevelStatement::Function {
+                    name,
+                    parameters,
+                    return_type,
+                    body,
+                    locals,
+                })
+            }
+            "test" => {
+                let name = self.string()?;
+                let (body, locals) = self.block()?;
+                Ok(ToplevelStatement::Test { name, body, locals })
+            }
+            "harness" => Ok(ToplevelStatement::TestHarness(self.list(Self::string)?)),
+            "extern" => {
+                self.keyword("fn")?;
+                let name = self.identifier()?;
+                let parameters = self.list(Self::var_type)?;
+                let return_type = self.return_type()?;
+                Ok(ToplevelStatement::ExternFunction {
+                    name,
+                    parameters,
+                    return_type,
+                })
+            }
+            "global" => {
+                let name = self.identifier()?;
+                self.symbol(":")?;
+                let var_type = self.var_type()?;
+                self.symbol("=")?;
+                let identifier = self.var()?;
+                self.symbol("=")?;
+                let value = self.constant(var_type)?;
+                Ok(ToplevelStatement::Global {
+                    name,
+                    identifier,
+                    value,
+                })
+            }
+            _ => Err(IrParsingError {
+                line: self.tokens.front().unwrap().1,
+                message: format!("expected fn, test, harness, extern or global, got {keyword}"),
+            }),
+        }
+    }
+
+    fn constant(&mut self, var_type: VarType) -> Result<Constant, IrParsingError> {
+        match var_type {
+            VarType::Int => Ok(Constant::Int(self.int_literal()?)),
+            VarType::Boolean => match self.peek() {
+                Token::Identifier(name) if name == "true" || name == "false" => {
+                    let value = name == "true";
+                    self.advance();
+                    Ok(Constant::Boolean(value))
+                }
+                _ => self.error("true or false"),
+            },
+            VarType::String => Ok(Constant::String(self.string()?)),
+            VarType::Char => Ok(Constant::Char(self.char()?)),
+            VarType::List(_) => self.error("a global of type int, bool, string or char"),
+        }
+    }
+
+    fn block(&mut self) -> Result<(Vec<Statement>, Locals), IrParsingError> {
+        self.symbol("{")?;
+
+        let mut locals = Vec::new();
+        while self.is_keyword("local") {
+            self.advance();
+            let identifier = self.var()?;
+            self.symbol(":")?;
+            locals.push((identifier, self.var_type()?));
+        }
+
+        let mut body = Vec::new();
+        while !self.is_symbol("}") {
+            body.push(self.statement()?);
+        }
+        self.symbol("}")?;
+
+        Ok((body, locals))
+    }
+
+    fn statement(&mut self) -> Result<Statement, IrParsingError> {
+        if let Token::Var(_) = self.peek() {
+            let identifier = self.var()?;
+            let op = match self.peek() {
+                Token::Symbol("+=") => Some(IntBinaryOp::Plus),
+                Token::Symbol("-=") => Some(IntBinaryOp::Minus),
+                Token::Symbol("*=") => Some(IntBinaryOp::Multiply),
+                Token::Symbol("/=") => Some(IntBinaryOp::Divide),
+                _ => None,
+            };
+            if let Some(op) = op {
+                self.advance();
+                let expression = self.int_expression()?;
+                return Ok(Statement::CompoundAssignment(identifier, op, expression));
+            }
+            self.symbol("=")?;
+            let value = match self.value()? {
+                Value::Int(expression) => AssignmentStatement::Int(expression),
+                Value::Boolean(expression) => AssignmentStatement::Boolean(expression),
+                Value::String(expression) => AssignmentStatement::String(expression),
+                Value::Char(expression) => AssignmentStatement::Char(expression),
+                Value::List(expression) => AssignmentStatement::List(expression),
+            };
+            return Ok(Statement::Assignment(identifier, value));
+        }
+        if self.is_symbol("@") {
+            return Ok(Statement::Call(self.call()?));
+        }
+
+        let keyword = self.identifier()?;
+        match keyword.as_str() {
+            "print" => {
+                let mut values = vec![self.print_value()?];
+                while self.is_symbol(",") {
+                    self.advance();
+                    values.push(self.print_value()?);
+                }
+                Ok(Statement::Print(values))
+            }
+            "assert" => {
+                let expression = self.bool_expression()?;
+                let message = if self.is_keyword("message") {
+                    self.advance();
+                    Some(self.string()?)
+                } else {
+                    None
+                };
+                self.keyword("at")?;
+                let file = self.string()?;
+                self.symbol(":")?;
+                let line = self.integer()?;
+                let line = usize::try_from(line).map_err(|_| IrParsingError {
+                    line: self.tokens.front().unwrap().1,
+                    message: format!("invalid line number {line}"),
+                })?;
+                let text = self.string()?;
+                Ok(Statement::Assert(
+                    expression,
+                    message,
+                    SourceLocation { file, line, text },
+                ))
+            }
+            "return" if self.is_var_type() => Ok(Statement::Return(Some(self.value()?))),
+            "return" => Ok(Statement::Return(None)),
+            "exit" => Ok(Statement::Exit(self.int_expression()?)),
+            "while" => {
+                let condition = self.bool_expression()?;
+                self.symbol("{")?;
+                let mut body = Vec::new();
+                while !self.is_symbol("}") {
+                    body.push(self.statement()?);
+                }
+                self.symbol("}")?;
+                Ok(Statement::While(condition, body))
+            }
+            "push" => {
+                let list = self.list_expression()?;
+                self.symbol(",")?;
+                Ok(Statement::Push(list, self.value()?))
+            }
+            "set_index" => {
+                let list = self.list_expression()?;
+                self.symbol(",")?;
+                let index = self.int_expression()?;
+                self.symbol(",")?;
+                Ok(Statement::SetIndex(list, index, self.value()?))
+            }
+            _ => Err(IrParsingError {
+                line: self.tokens.front().unwrap().1,
+                message: format!("expected a statement, got {keyword}"),
+            }),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, IrParsingError> {
+        // list values are prefixed with just `list`, the element type comes from the expression
+        if self.is_keyword("list") {
+            self.advance();
+            return Ok(Value::List(self.list_expression()?));
+        }
+
+        Ok(match self.var_type()? {
+            VarType::Int => Value::Int(self.int_expression()?),
+            VarType::Boolean => Value::Boolean(self.bool_expression()?),
+            VarType::String => Value::String(self.string_expression()?),
+            VarType::Char => Value::Char(self.char_expression()?),
+            VarType::List(_) => unreachable!(),
+        })
+    }
+
+    fn print_value(&mut self) -> Result<PrintValue, IrParsingError> {
+        if self.is_keyword("text") {
+            self.advance();
+            return Ok(PrintValue::Text(self.string()?));
+        }
+
+        Ok(match self.value()? {
+            Value::Int(expression) => PrintValue::Int(expression),
+            Value::Boolean(expression) => PrintValue::Boolean(expression),
+            Value::String(expression) => PrintValue::String(expression),
+            Value::Char(expression) => PrintValue::Char(expression),
+            Value::List(_) => return self.error("a value that can be printed"),
+        })
+    }
+
+    fn call(&mut self) -> Result<Call, IrParsingError> {
+        self.symbol("@")?;
+        let function = self.identifier()?;
+        let arguments = self.list(Self::value)?;
+        Ok(Call {
+            function,
+            arguments,
+        })
+    }
+
+    /// Parses the single argument of a builtin like `neg(...)`
+    fn argument<T>(
+        &mut self,
+        argument: impl FnOnce(&mut Self) -> Result<T, IrParsingError>,
+    ) -> Result<T, IrParsingError> {
+        self.symbol("(")?;
+        let argument = argument(self)?;
+        self.symbol(")")?;
+        Ok(argument)
+    }
+
+    /// Parses `if(condition, then, else)`, the branches are parsed by `branch` and combined by `make`
+    fn if_expression<T>(
+        &mut self,
+        branch: impl Fn(&mut Self) -> Result<T, IrParsingError>,
+        make: impl FnOnce(Box<BooleanExpression>, Box<T>, Box<T>) -> T,
+    ) -> Result<T, IrParsingError> {
+        self.keyword("if")?;
+        self.symbol("(")?;
+        let condition = self.bool_expression()?;
+        self.symbol(",")?;
+        let then_value = branch(self)?;
+        self.symbol(",")?;
+        let else_value = branch(self)?;
+        self.symbol(")")?;
+        Ok(make(
+            Box::new(condition),
+            Box::new(then_value),
+            Box::new(else_value),
+        ))
+    }
+
+    /// Parses the `(list, index)` of `index(list, index)`
+    fn index_arguments(
+        &mut self,
+    ) -> Result<(Box<ListExpression>, Box<IntExpression>), IrParsingError> {
+        self.symbol("(")?;
+        let list = self.list_expression()?;
+        self.symbol(",")?;
+        let index = self.int_expression()?;
+        self.symbol(")")?;
+        Ok((Box::new(list), Box::new(index)))
+    }
+
+    fn no_arguments(&mut self) -> Result<(), IrParsingError> {
+        self.symbol("(")?;
+        self.symbol(")")
+    }
+
+    fn int_expression(&mut self) -> Result<IntExpression, IrParsingError> {
+        match self.peek() {
+            Token::Integer(_) => Ok(IntExpression::Literal(self.int_literal()?)),
+            Token::Var(_) => Ok(IntExpression::Var(self.var()?)),
+            Token::Symbol("@") => Ok(IntExpression::Call(self.call()?)),
+            Token::Symbol("(") => {
+                self.advance();
+                let left = self.int_expression()?;
+                let op = match self.peek() {
+                    Token::Symbol("+") => IntBinaryOp::Plus,
+                    Token::Symbol("-") => IntBinaryOp::Minus,
+                    Token::Symbol("*") => IntBinaryOp::Multiply,
+                    Token::Symbol("/") => IntBinaryOp::Divide,
+                    _ => return self.error("+, -, * or /"),
+                };
+                self.advance();
+                let right = self.int_expression()?;
+                self.symbol(")")?;
+                Ok(IntExpression::BinaryOperation(
+                    Box::new(left),
+                    op,
+                    Box::new(right),
+                ))
+            }
+            Token::Identifier(name) => match name.as_str() {
+                "neg" => {
+                    self.advance();
+                    let expression = self.argument(Self::int_expression)?;
+                    Ok(IntExpression::Negate(Box::new(expression)))
+                }
+                "read_int" => {
+                    self.advance();
+                    self.no_arguments()?;
+                    Ok(IntExpression::ReadInt)
+                }
+                "arg_count" => {
+                    self.advance();
+                    self.no_arguments()?;
+                    Ok(IntExpression::ArgCount)
+                }
+                "parse_int" => {
+                    self.advance();
+                    let expression = self.argument(Self::string_expression)?;
+                    Ok(IntExpression::ParseInt(Box::new(expression)))
+                }
+                "if" => self.if_expression(Self::int_expression, IntExpression::If),
+                "index" => {
+                    self.advance();
+                    let (list, index) = self.index_arguments()?;
+                    Ok(IntExpression::Index(list, index))
+                }
+                "pop" => {
+                    self.advance();
+                    let list = self.argument(Self::list_expression)?;
+                    Ok(IntExpression::Pop(Box::new(list)))
+                }
+                "len" => {
+                    self.advance();
+                    let list = self.argument(Self::list_expression)?;
+                    Ok(IntExpression::Length(Box::new(list)))
+                }
+                "to_int" => {
+                    self.advance();
+                    let expression = self.argument(Self::char_expression)?;
+                    Ok(IntExpression::FromChar(Box::new(expression)))
+                }
+                _ => self.error("an int expression"),
+            },
+            _ => self.error("an int expression"),
+        }
+    }
+
+    fn string_expression(&mut self) -> Result<StringExpression, IrParsingError> {
+        match self.peek() {
+            Token::String(_) => Ok(StringExpression::Literal(self.string()?)),
+            Token::Var(_) => Ok(StringExpression::Var(self.var()?)),
+            Token::Symbol("@") => Ok(StringExpression::Call(self.call()?)),
+            Token::Identifier(name) if name == "read_line" => {
+                self.advance();
+                self.no_arguments()?;
+                Ok(StringExpression::ReadLine)
+            }
+            Token::Identifier(name) if name == "arg" => {
+                self.advance();
+                let index = self.argument(Self::int_expression)?;
+                Ok(StringExpression::Arg(Box::new(index)))
+            }
+            Token::Identifier(name) if name == "if" => {
+                self.if_expression(Self::string_expression, StringExpression::If)
+            }
+            Token::Identifier(name) if name == "index" => {
+                self.advance();
+                let (list, index) = self.index_arguments()?;
+                Ok(StringExpression::Index(list, index))
+            }
+            Token::Identifier(name) if name == "pop" => {
+                self.advance();
+                let list = self.argument(Self::list_expression)?;
+                Ok(StringExpression::Pop(Box::new(list)))
+            }
+            _ => self.error("a string expression"),
+        }
+    }
+
+    fn char_expression(&mut self) -> Result<CharExpression, IrParsingError> {
+        match self.peek() {
+            Token::Char(_) => Ok(CharExpression::Literal(self.char()?)),
+            Token::Var(_) => Ok(CharExpression::Var(self.var()?)),
+            Token::Symbol("@") => Ok(CharExpression::Call(self.call()?)),
+            Token::Identifier(name) if name == "to_char" => {
+                self.advance();
+                let expression = self.argument(Self::int_expression)?;
+                Ok(CharExpression::FromInt(Box::new(expression)))
+            }
+            Token::Identifier(name) if name == "if" => {
+                self.if_expression(Self::char_expression, CharExpression::If)
+            }
+            Token::Identifier(name) if name == "index" => {
+                self.advance();
+                let (list, index) = self.index_arguments()?;
+                Ok(CharExpression::Index(list, index))
+            }
+            Token::Identifier(name) if name == "pop" => {
+                self.advance();
+                let list = self.argument(Self::list_expression)?;
+                Ok(CharExpression::Pop(Box::new(list)))
+            }
+            _ => self.error("a char expression"),
+        }
+    }
+
+    /// Parses the operators and right hand sides of a comparison chain, up to the closing `)`
+    fn comparison_chain<T>(
+        &mut self,
+        operand: impl Fn(&mut Self) -> Result<T, IrParsingError>,
+    ) -> Result<Vec<(IntComparisonOp, T)>, IrParsingError> {
+        let mut rest = Vec::new();
+        while !self.is_symbol(")") {
+            let op = match self.peek() {
+                Token::Symbol("==") => IntComparisonOp::Equal,
+                Token::Symbol("!=") => IntComparisonOp::NotEquals,
+                Token::Symbol("<") => IntComparisonOp::LessThan,
+                Token::Symbol("<=") => IntComparisonOp::LessThanEquals,
+                Token::Symbol(">") => IntComparisonOp::GreaterThan,
+                Token::Symbol(">=") => IntComparisonOp::GreaterThanEquals,
+                _ => return self.error("a comparison operator"),
+            };
+            self.advance();
+            rest.push((op, operand(self)?));
+        }
+        if rest.is_empty() {
+            return self.error("a comparison operator");
+        }
+        self.symbol(")")?;
+        Ok(rest)
+    }
+
+    fn bool_expression(&mut self) -> Result<BooleanExpression, IrParsingError> {
+        match self.peek() {
+            Token::Var(_) => Ok(BooleanExpression::Var(self.var()?)),
+            Token::Symbol("@") => Ok(BooleanExpression::Call(self.call()?)),
+            Token::Identifier(name) if name == "true" || name == "false" => {
+                let value = name == "true";
+                self.advance();
+                Ok(BooleanExpression::Literal(value))
+            }
+            Token::Identifier(name) if name == "not" => {
+                self.advance();
+                let expression = self.argument(Self::bool_expression)?;
+                Ok(BooleanExpression::Not(Box::new(expression)))
+            }
+            Token::Identifier(name) if name == "if" => {
+                self.if_expression(Self::bool_expression, BooleanExpression::If)
+            }
+            Token::Identifier(name) if name == "index" => {
+                self.advance();
+                let (list, index) = self.index_arguments()?;
+                Ok(BooleanExpression::Index(list, index))
+            }
+            Token::Identifier(name) if name == "pop" => {
+                self.advance();
+                let list = self.argument(Self::list_expression)?;
+                Ok(BooleanExpression::Pop(Box::new(list)))
+            }
+            // `(%1 = a and b)` stores the result in a temporary, anything else is a comparison
+            Token::Symbol("(")
+                if matches!(self.tokens.get(1), Some((Token::Var(_), _)))
+                    && self.tokens.get(2).map(|(token, _)| token) == Some(&Token::Symbol("=")) =>
+            {
+                self.advance();
+                let identifier = self.var()?;
+                self.symbol("=")?;
+                let left = self.bool_expression()?;
+                let op = match self.peek() {
+                    Token::Identifier(op) if op == "and" => BooleanOperator::And,
+                    Token::Identifier(op) if op == "or" => BooleanOperator::Or,
+                    _ => return self.error("and or or"),
+                };
+                self.advance();
+                let right = self.bool_expression()?;
+                self.symbol(")")?;
+                Ok(BooleanExpression::Operator(
+                    identifier,
+                    Box::new(left),
+                    op,
+                    Box::new(right),
+                ))
+            }
+            // `(char a < b)` compares chars
+            Token::Symbol("(") if matches!(self.tokens.get(1), Some((Token::Identifier(name), _)) if name == "char") =>
+            {
+                self.advance();
+                self.advance();
+                let first = self.char_expression()?;
+                let rest = self.comparison_chain(Self::char_expression)?;
+                Ok(BooleanExpression::Comparison(
+                    ComparisonExpression::CharComparison(Box::new(first), rest),
+                ))
+            }
+            Token::Symbol("(") => {
+                self.advance();
+                let first = self.int_expression()?;
+                let rest = self.comparison_chain(Self::int_expression)?;
+                Ok(BooleanExpression::Comparison(
+                    ComparisonExpression::IntComparison(Box::new(first), rest),
+                ))
+            }
+            _ => self.error("a bool expression"),
+        }
+    }
+
+    fn list_expression(&mut self) -> Result<ListExpression, IrParsingError> {
+        match self.peek() {
+            Token::Var(_) => Ok(ListExpression::Var(self.var()?)),
+            Token::Symbol("@") => Ok(ListExpression::Call(self.call()?)),
+            Token::Identifier(name) if name == "list" => {
+                self.advance();
+                let element_type = self.element_type()?;
+                let values = self.list(Self::value)?;
+                Ok(ListExpression::Literal(element_type, values))
+            }
+            Token::Identifier(name) if name == "if" => {
+                self.if_expression(Self::list_expression, ListExpression::If)
+            }
+            _ => self.error("a list expression"),
+        }
+    }
+}
+
+/// Parses a module written in the textual ir syntax
+pub fn parse(text: &str) -> Result<Module, IrParsingError> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+    };
+    parser.module()
+}
@@ -0,0 +1,436 @@
+//! Prints the ir in the textual syntax read by `ir::parser`.
+//!
+//! ```text
+//! extern fn puts(string) -> int
+//! global counter: int = %1 = 0
+//! fn square(x: int = %0) -> int {
+//!     local %0: int
+//!     return int (%0 * %0)
+//! }
+//! fn count() {
+//!     local %2: int
+//!     %2 = 0
+//!     while (%2 < 10) {
+//!         print int %2
+//!         %2 += 1
+//!     }
+//!     return
+//! }
+//! fn numbers() -> list<int> {
+//!     local %3: list<int>
+//!     %3 = list list<int>(int 1, int 2)
+//!     push %3, int len(%3)
+//!     return list %3
+//! }
+//! fn is_digit(c: char = %4) -> bool {
+//!     local %4: char
+//!     return bool (char '0' <= %4 <= '9')
+//! }
+//! ```
+
+use std::fmt::{Display, Formatter, Result};
+
+use super::*;
+
+/// Writes a string literal, escaping everything that would end it early
+fn write_string(f: &mut Formatter, text: &str) -> Result {
+    write!(f, "\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Writes a char literal, control characters are written as `\u{...}`
+fn write_char(f: &mut Formatter, c: char) -> Result {
+    match c {
+        '\'' => write!(f, "'\\''"),
+        '\\' => write!(f, "'\\\\'"),
+        '\n' => write!(f, "'\\n'"),
+        '\t' => write!(f, "'\\t'"),
+        c if c.is_control() => write!(f, "'\\u{{{:x}}}'", c as u32),
+        c => write!(f, "'{c}'"),
+    }
+}
+
+/// Writes the items separated by commas
+fn write_list<T>(
+    f: &mut Formatter,
+    items: &[T],
+    mut write_item: impl FnMut(&mut Formatter, &T) -> Result,
+) -> Result {
+    for (index, item) in items.iter().enumerate() {
+        if index > 0 {
+            write!(f, ", ")?;
+        }
+        write_item(f, item)?;
+    }
+    Ok(())
+}
+
+fn write_return_type(f: &mut Formatter, return_type: &Option<VarType>) -> Result {
+    match return_type {
+        Some(return_type) => write!(f, " -> {return_type}"),
+        None => Ok(()),
+    }
+}
+
+fn write_body(
+    f: &mut Formatter,
+    body: &[Statement],
+    locals: &[(VariableIdentifier, VarType)],
+) -> Result {
+    writeln!(f, " {{")?;
+    for (identifier, var_type) in locals {
+        writeln!(f, "    local {identifier}: {var_type}")?;
+    }
+    write_statements(f, body, 1)?;
+    writeln!(f, "}}")
+}
+
+/// Writes one statement per line, the bodies of loops are indented one level deeper
+fn write_statements(f: &mut Formatter, statements: &[Statement], depth: usize) -> Result {
+    let indent = "    ".repeat(depth);
+    for statement in statements {
+        match statement {
+            Statement::While(condition, body) => {
+                writeln!(f, "{indent}while {condition} {{")?;
+                write_statements(f, body, depth + 1)?;
+                writeln!(f, "{indent}}}")?;
+            }
+            statement => writeln!(f, "{indent}{statement}")?,
+        }
+    }
+    Ok(())
+}
+
+impl Display for Module {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        for statement in &self.0 {
+            write!(f, "{statement}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ToplevelStatement {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            ToplevelStatement::Function {
+                name,
+                parameters,
+                return_type,
+                body,
+                locals,
+            } => {
+                write!(f, "fn {name}(")?;
+                write_list(f, parameters, |f, parameter| {
+                    write!(
+                        f,
+                        "{}: {} = {}",
+                        parameter.name, parameter.var_type, parameter.identifier
+                    )
+                })?;
+                write!(f, ")")?;
+                write_return_type(f, return_type)?;
+                write_body(f, body, locals)
+            }
+            ToplevelStatement::Test { name, body, locals } => {
+                write!(f, "test ")?;
+                write_string(f, name)?;
+                write_body(f, body, locals)
+            }
+            ToplevelStatement::TestHarness(tests) => {
+                write!(f, "harness(")?;
+                write_list(f, tests, |f, test| write_string(f, test))?;
+                writeln!(f, ")")
+            }
+            ToplevelStatement::ExternFunction {
+                name,
+                parameters,
+                return_type,
+            } => {
+                write!(f, "extern fn {name}(")?;
+                write_list(f, parameters, |f, parameter| write!(f, "{parameter}"))?;
+                write!(f, ")")?;
+                write_return_type(f, return_type)?;
+                writeln!(f)
+            }
+            ToplevelStatement::Global {
+                name,
+                identifier,
+                value,
+            } => writeln!(
+                f,
+                "global {name}: {} = {identifier} = {value}",
+                value.var_type()
+            ),
+        }
+    }
+}
+
+impl Display for Constant {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            Constant::Int(value) => write!(f, "{value}"),
+            Constant::Boolean(value) => write!(f, "{value}"),
+            Constant::String(text) => write_string(f, text),
+            Constant::Char(c) => write_char(f, *c),
+        }
+    }
+}
+
+impl Display for VarType {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            VarType::Int => write!(f, "int"),
+            VarType::Boolean => write!(f, "bool"),
+            VarType::String => write!(f, "string"),
+            VarType::Char => write!(f, "char"),
+            VarType::List(element_type) => write!(f, "list<{element_type}>"),
+        }
+    }
+}
+
+impl Display for ElementType {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{}", self.var_type())
+    }
+}
+
+impl Display for VariableIdentifier {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl Display for Statement {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            Statement::Print(values) => {
+                write!(f, "print ")?;
+                write_list(f, values, |f, value| write!(f, "{value}"))
+            }
+            Statement::Assert(expression, message, location) => {
+                write!(f, "assert {expression}")?;
+                if let Some(message) = message {
+                    write!(f, " message ")?;
+                    write_string(f, message)?;
+                }
+                write!(f, " at ")?;
+                write_string(f, &location.file)?;
+                write!(f, ":{} ", location.line)?;
+                write_string(f, &location.text)
+            }
+            Statement::Assignment(identifier, value) => write!(f, "{identifier} = {value}"),
+            Statement::CompoundAssignment(identifier, op, expression) => {
+                write!(f, "{identifier} {op}= {expression}")
+            }
+            Statement::Return(Some(value)) => write!(f, "return {value}"),
+            Statement::Return(None) => write!(f, "return"),
+            Statement::Exit(expression) => write!(f, "exit {expression}"),
+            Statement::Call(call) => write!(f, "{call}"),
+            Statement::While(condition, body) => {
+                writeln!(f, "while {condition} {{")?;
+                write_statements(f, body, 1)?;
+                write!(f, "}}")
+            }
+            Statement::Push(list, value) => write!(f, "push {list}, {value}"),
+            Statement::SetIndex(list, index, value) => {
+                write!(f, "set_index {list}, {index}, {value}")
+            }
+        }
+    }
+}
+
+impl Display for Call {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "@{}(", self.function)?;
+        write_list(f, &self.arguments, |f, argument| write!(f, "{argument}"))?;
+        write!(f, ")")
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            Value::Int(expression) => write!(f, "int {expression}"),
+            Value::Boolean(expression) => write!(f, "bool {expression}"),
+            Value::String(expression) => write!(f, "string {expression}"),
+            Value::Char(expression) => write!(f, "char {expression}"),
+            Value::List(expression) => write!(f, "list {expression}"),
+        }
+    }
+}
+
+impl Display for AssignmentStatement {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            AssignmentStatement::Int(expression) => write!(f, "int {expression}"),
+            AssignmentStatement::Boolean(expression) => write!(f, "bool {expression}"),
+            AssignmentStatement::String(expression) => write!(f, "string {expression}"),
+            AssignmentStatement::Char(expression) => write!(f, "char {expression}"),
+            AssignmentStatement::List(expression) => write!(f, "list {expression}"),
+        }
+    }
+}
+
+impl Display for PrintValue {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            PrintValue::Int(expression) => write!(f, "int {expression}"),
+            PrintValue::Boolean(expression) => write!(f, "bool {expression}"),
+            PrintValue::String(expression) => write!(f, "string {expression}"),
+            PrintValue::Char(expression) => write!(f, "char {expression}"),
+            PrintValue::Text(text) => {
+                write!(f, "text ")?;
+                write_string(f, text)
+            }
+        }
+    }
+}
+
+impl Display for IntExpression {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            IntExpression::Literal(value) => write!(f, "{value}"),
+            IntExpression::Negate(expression) => write!(f, "neg({expression})"),
+            IntExpression::BinaryOperation(left, op, right) => write!(f, "({left} {op} {right})"),
+            IntExpression::Var(identifier) => write!(f, "{identifier}"),
+            IntExpression::ReadInt => write!(f, "read_int()"),
+            IntExpression::ArgCount => write!(f, "arg_count()"),
+            IntExpression::ParseInt(expression) => write!(f, "parse_int({expression})"),
+            IntExpression::Call(call) => write!(f, "{call}"),
+            IntExpression::If(condition, then_value, else_value) => {
+                write!(f, "if({condition}, {then_value}, {else_value})")
+            }
+            IntExpression::Index(list, index) => write!(f, "index({list}, {index})"),
+            IntExpression::Pop(list) => write!(f, "pop({list})"),
+            IntExpression::Length(list) => write!(f, "len({list})"),
+            IntExpression::FromChar(expression) => write!(f, "to_int({expression})"),
+        }
+    }
+}
+
+impl Display for IntBinaryOp {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            IntBinaryOp::Plus => write!(f, "+"),
+            IntBinaryOp::Minus => write!(f, "-"),
+            IntBinaryOp::Multiply => write!(f, "*"),
+            IntBinaryOp::Divide => write!(f, "/"),
+        }
+    }
+}
+
+impl Display for StringExpression {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            StringExpression::Literal(text) => write_string(f, text),
+            StringExpression::Var(identifier) => write!(f, "{identifier}"),
+            StringExpression::ReadLine => write!(f, "read_line()"),
+            StringExpression::Arg(index) => write!(f, "arg({index})"),
+            StringExpression::Call(call) => write!(f, "{call}"),
+            StringExpression::If(condition, then_value, else_value) => {
+                write!(f, "if({condition}, {then_value}, {else_value})")
+            }
+            StringExpression::Index(list, index) => write!(f, "index({list}, {index})"),
+            StringExpression::Pop(list) => write!(f, "pop({list})"),
+        }
+    }
+}
+
+impl Display for CharExpression {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            CharExpression::Literal(c) => write_char(f, *c),
+            CharExpression::Var(identifier) => write!(f, "{identifier}"),
+            CharExpression::FromInt(expression) => write!(f, "to_char({expression})"),
+            CharExpression::Call(call) => write!(f, "{call}"),
+            CharExpression::If(condition, then_value, else_value) => {
+                write!(f, "if({condition}, {then_value}, {else_value})")
+            }
+            CharExpression::Index(list, index) => write!(f, "index({list}, {index})"),
+            CharExpression::Pop(list) => write!(f, "pop({list})"),
+        }
+    }
+}
+
+impl Display for BooleanExpression {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            BooleanExpression::Literal(value) => write!(f, "{value}"),
+            BooleanExpression::Not(expression) => write!(f, "not({expression})"),
+            BooleanExpression::Comparison(ComparisonExpression::IntComparison(first, rest)) => {
+                write!(f, "({first}")?;
+                for (op, expression) in rest {
+                    write!(f, " {op} {expression}")?;
+                }
+                write!(f, ")")
+            }
+            // char comparisons are marked, so the parser knows the type of the operands
+            BooleanExpression::Comparison(ComparisonExpression::CharComparison(first, rest)) => {
+                write!(f, "(char {first}")?;
+                for (op, expression) in rest {
+                    write!(f, " {op} {expression}")?;
+                }
+                write!(f, ")")
+            }
+            BooleanExpression::Operator(identifier, left, op, right) => {
+                write!(f, "({identifier} = {left} {op} {right})")
+            }
+            BooleanExpression::Var(identifier) => write!(f, "{identifier}"),
+            BooleanExpression::Call(call) => write!(f, "{call}"),
+            BooleanExpression::If(condition, then_value, else_value) => {
+                write!(f, "if({condition}, {then_value}, {else_value})")
+            }
+            BooleanExpression::Index(list, index) => write!(f, "index({list}, {index})"),
+            BooleanExpression::Pop(list) => write!(f, "pop({list})"),
+        }
+    }
+}
+
+impl Display for ListExpression {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            ListExpression::Literal(element_type, values) => {
+                write!(f, "list<{element_type}>(")?;
+                write_list(f, values, |f, value| write!(f, "{value}"))?;
+                write!(f, ")")
+            }
+            ListExpression::Var(identifier) => write!(f, "{identifier}"),
+            ListExpression::Call(call) => write!(f, "{call}"),
+            ListExpression::If(condition, then_value, else_value) => {
+                write!(f, "if({condition}, {then_value}, {else_value})")
+            }
+        }
+    }
+}
+
+impl Display for BooleanOperator {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            BooleanOperator::And => write!(f, "and"),
+            BooleanOperator::Or => write!(f, "or"),
+        }
+    }
+}
+
+impl Display for IntComparisonOp {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            IntComparisonOp::Equal => write!(f, "=="),
+            IntComparisonOp::NotEquals => write!(f, "!="),
+            IntComparisonOp::LessThan => write!(f, "<"),
+            IntComparisonOp::LessThanEquals => write!(f, "<="),
+            IntComparisonOp::GreaterThan => write!(f, ">"),
+            IntComparisonOp::GreaterThanEquals => write!(f, ">="),
+        }
+    }
+}
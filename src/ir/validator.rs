@@ -0,0 +1,542 @@
+//! Checks that a module is consistent before it is handed to codegen, which assumes it is and panics otherwise.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use super::*;
+
+#[derive(Error, Debug)]
+#[error("Invalid ir in {function}: {message}")]
+pub struct IrValidationError {
+    pub function: String,
+    pub message: String,
+}
+
+struct Signature {
+    parameters: Vec<VarType>,
+    return_type: Option<VarType>,
+}
+
+struct Validator<'a> {
+    functions: HashMap<&'a str, Signature>,
+    /// Name of the function being validated, used in errors
+    function: String,
+    /// Globals can be used by every function, like its locals
+    globals: HashMap<VariableIdentifier, VarType>,
+    locals: HashMap<VariableIdentifier, VarType>,
+    /// What a return statement has to return, `None` if returning isn't allowed at all
+    return_type: Option<Option<VarType>>,
+}
+
+impl<'a> Validator<'a> {
+    fn error<T>(&self, message: String) -> Result<T, IrValidationError> {
+        Err(IrValidationError {
+            function: self.function.clone(),
+            message,
+        })
+    }
+
+    fn declare_functions(&mut self, module: &'a Module) -> Result<(), IrValidationError> {
+        let mut defines_main = false;
+        let mut defined = Vec::new();
+
+        for statement in &module.0 {
+            let (name, signature) = match statement {
+                ToplevelStatement::Function {
+                    name,
+                    parameters,
+                    return_type,
+                    ..
+                } if name != "main" => (
+                    name,
+                    Signature {
+                        parameters: parameters
+                            .iter()
+                            .map(|parameter| parameter.var_type)
+                            .collect(),
+                        return_type: *return_type,
+                    },
+                ),
+                ToplevelStatement::ExternFunction {
+                    name,
+                    parameters,
+                    return_type,
+                } => (
+                    name,
+                    Signature {
+                        parameters: parameters.clone(),
+                        return_type: *return_type,
+                    },
+                ),
+                ToplevelStatement::Function { .. } | ToplevelStatement::TestHarness(_) => {
+                    if defines_main {
+                        self.error(String::from("main is defined more than once"))?;
+                    }
+                    defines_main = true;
+                    continue;
+                }
+                ToplevelStatement::Test { .. } | ToplevelStatement::Global { .. } => continue,
+            };
+
+            if let Some(existing) = self.functions.get(name.as_str()) {
+                if existing.parameters != signature.parameters
+                    || existing.return_type != signature.return_type
+                {
+                    self.error(format!(
+                        "function {name} is declared with different signatures"
+                    ))?;
+                }
+            }
+            if let ToplevelStatement::Function { .. } = statement {
+                if defined.contains(&name) {
+                    self.error(format!("function {name} is defined more than once"))?;
+                }
+                defined.push(name);
+            }
+            self.functions.insert(name, signature);
+        }
+
+        Ok(())
+    }
+
+    fn declare_globals(&mut self, module: &Module) -> Result<(), IrValidationError> {
+        let mut names = Vec::new();
+
+        for statement in &module.0 {
+            if let ToplevelStatement::Global {
+                name,
+                identifier,
+                value,
+            } = statement
+            {
+                self.function = format!("global {name}");
+                if names.contains(&name) {
+                    self.error(format!("global {name} is defined more than once"))?;
+                }
+                names.push(name);
+                if self.globals.insert(*identifier, value.var_type()).is_some() {
+                    self.error(format!("variable {identifier} is declared more than once"))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn function(
+        &mut self,
+        name: String,
+        parameters: &[Parameter],
+        return_type: Option<Option<VarType>>,
+        body: &[Statement],
+        locals: &[(VariableIdentifier, VarType)],
+    ) -> Result<(), IrValidationError> {
+        self.function = name;
+        self.return_type = return_type;
+        self.locals = self.globals.clone();
+
+        for (identifier, var_type) in locals {
+            if self.locals.insert(*identifier, *var_type).is_some() {
+                self.error(format!("variable {identifier} is declared more than once"))?;
+            }
+        }
+
+        for parameter in parameters {
+            if self.locals.get(&parameter.identifier) != Some(&parameter.var_type) {
+                self.error(format!(
+                    "parameter {} should be a local {} of type {}",
+                    parameter.name, parameter.identifier, parameter.var_type
+                ))?;
+            }
+        }
+
+        for statement in body {
+            self.statement(statement)?;
+        }
+
+        // codegen ends a function returning a value with `unreachable`
+        if let Some(Some(_)) = return_type {
+            if self.function != "main" && !matches!(body.last(), Some(Statement::Return(_))) {
+                self.error(String::from("function must end with a return statement"))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn var(
+        &self,
+        identifier: &VariableIdentifier,
+        var_type: VarType,
+    ) -> Result<(), IrValidationError> {
+        match self.locals.get(identifier) {
+            Some(declared) if *declared == var_type => Ok(()),
+            Some(declared) => self.error(format!(
+                "variable {identifier} is used as {var_type}, but declared as {declared}"
+            )),
+            None => self.error(format!("variable {identifier} is not declared")),
+        }
+    }
+
+    fn statement(&self, statement: &Statement) -> Result<(), IrValidationError> {
+        match statement {
+            Statement::Print(values) => {
+                for value in values {
+                    match value {
+                        PrintValue::Int(expression) => self.int_expression(expression)?,
+                        PrintValue::Boolean(expression) => self.bool_expression(expression)?,
+                        PrintValue::String(expression) => self.string_expression(expression)?,
+                        PrintValue::Char(expression) => self.char_expression(expression)?,
+                        PrintValue::Text(_) => {}
+                    }
+                }
+                Ok(())
+            }
+            Statement::Assert(expression, _, _) => self.bool_expression(expression),
+            Statement::Assignment(identifier, value) => match value {
+                AssignmentStatement::Int(expression) => {
+                    self.var(identifier, VarType::Int)?;
+                    self.int_expression(expression)
+                }
+                AssignmentStatement::Boolean(expression) => {
+                    self.var(identifier, VarType::Boolean)?;
+                    self.bool_expression(expression)
+                }
+                AssignmentStatement::String(expression) => {
+                    self.var(identifier, VarType::String)?;
+                    self.string_expression(expression)
+                }
+                AssignmentStatement::Char(expression) => {
+                    self.var(identifier, VarType::Char)?;
+                    self.char_expression(expression)
+                }
+                AssignmentStatement::List(expression) => {
+                    let element_type = self.list_expression(expression)?;
+                    self.var(identifier, VarType::List(element_type))
+                }
+            },
+            Statement::CompoundAssignment(identifier, _, expression) => {
+                self.var(identifier, VarType::Int)?;
+                self.int_expression(expression)
+            }
+            Statement::Return(value) => {
+                let Some(return_type) = self.return_type else {
+                    return self.error(String::from("return is not allowed in a test"));
+                };
+                let value_type = value.as_ref().map(|value| self.value(value)).transpose()?;
+                if value_type != return_type {
+                    return self.error(format!(
+                        "returns {}, but the function returns {}",
+                        Self::describe(value_type),
+                        Self::describe(return_type)
+                    ));
+                }
+                Ok(())
+            }
+            Statement::Exit(expression) => self.int_expression(expression),
+            Statement::Call(call) => self.call(call).map(|_| ()),
+            Statement::While(condition, body) => {
+                self.bool_expression(condition)?;
+                for statement in body {
+                    self.statement(statement)?;
+                }
+                Ok(())
+            }
+            Statement::Push(list, value) => {
+                let element_type = self.list_expression(list)?;
+                self.element_value(value, element_type)
+            }
+            Statement::SetIndex(list, index, value) => {
+                let element_type = self.list_expression(list)?;
+                self.int_expression(index)?;
+                self.element_value(value, element_type)
+            }
+        }
+    }
+
+    /// Validates a value stored in a list
+    fn element_value(
+        &self,
+        value: &Value,
+        element_type: ElementType,
+    ) -> Result<(), IrValidationError> {
+        let value_type = self.value(value)?;
+        if value_type != element_type.var_type() {
+            return self.error(format!(
+                "{value_type} is stored in a list of {element_type}"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Validates reading an element of a list, `index` is `None` for a pop
+    fn element(
+        &self,
+        list: &ListExpression,
+        index: Option<&IntExpression>,
+        element_type: ElementType,
+    ) -> Result<(), IrValidationError> {
+        if let Some(index) = index {
+            self.int_expression(index)?;
+        }
+        let list_type = self.list_expression(list)?;
+        if list_type != element_type {
+            return self.error(format!(
+                "an element of a list of {list_type} is used as {element_type}"
+            ));
+        }
+        Ok(())
+    }
+
+    fn describe(var_type: Option<VarType>) -> String {
+        var_type.map_or(String::from("nothing"), |var_type| var_type.to_string())
+    }
+
+    /// Validates a value, returning its type
+    fn value(&self, value: &Value) -> Result<VarType, IrValidationError> {
+        match value {
+            Value::Int(expression) => self.int_expression(expression).map(|_| VarType::Int),
+            Value::Boolean(expression) => {
+                self.bool_expression(expression).map(|_| VarType::Boolean)
+            }
+            Value::String(expression) => {
+                self.string_expression(expression).map(|_| VarType::String)
+            }
+            Value::Char(expression) => self.char_expression(expression).map(|_| VarType::Char),
+            Value::List(expression) => self.list_expression(expression).map(VarType::List),
+        }
+    }
+
+    /// Validates a call, returning the return type of the called function
+    fn call(&self, call: &Call) -> Result<Option<VarType>, IrValidationError> {
+        let Some(signature) = self.functions.get(call.function.as_str()) else {
+            return self.error(format!("function {} is not declared", call.function));
+        };
+
+        let argument_types = call
+            .arguments
+            .iter()
+            .map(|argument| self.value(argument))
+            .collect::<Result<Vec<_>, _>>()?;
+        if argument_types != signature.parameters {
+            return self.error(format!(
+                "function {} is called with the wrong arguments",
+                call.function
+            ));
+        }
+
+        Ok(signature.return_type)
+    }
+
+    fn typed_call(&self, call: &Call, var_type: VarType) -> Result<(), IrValidationError> {
+        let return_type = self.call(call)?;
+        if return_type != Some(var_type) {
+            return self.error(format!(
+                "function {} returns {}, but is used as {var_type}",
+                call.function,
+                Self::describe(return_type)
+            ));
+        }
+        Ok(())
+    }
+
+    fn int_expression(&self, expression: &IntExpression) -> Result<(), IrValidationError> {
+        match expression {
+            IntExpression::Literal(_) | IntExpression::ReadInt | IntExpression::ArgCount => Ok(()),
+            IntExpression::Negate(expression) => self.int_expression(expression),
+            IntExpression::BinaryOperation(left, _, right) => {
+                self.int_expression(left)?;
+                self.int_expression(right)
+            }
+            IntExpression::Var(identifier) => self.var(identifier, VarType::Int),
+            IntExpression::ParseInt(expression) => self.string_expression(expression),
+            IntExpression::Call(call) => self.typed_call(call, VarType::Int),
+            IntExpression::If(condition, then_value, else_value) => {
+                self.bool_expression(condition)?;
+                self.int_expression(then_value)?;
+                self.int_expression(else_value)
+            }
+            IntExpression::Index(list, index) => self.element(list, Some(index), ElementType::Int),
+            IntExpression::Pop(list) => self.element(list, None, ElementType::Int),
+            IntExpression::Length(list) => self.list_expression(list).map(|_| ()),
+            IntExpression::FromChar(expression) => self.char_expression(expression),
+        }
+    }
+
+    fn string_expression(&self, expression: &StringExpression) -> Result<(), IrValidationError> {
+        match expression {
+            StringExpression::Literal(_) | StringExpression::ReadLine => Ok(()),
+            StringExpression::Var(identifier) => self.var(identifier, VarType::String),
+            StringExpression::Arg(index) => self.int_expression(index),
+            StringExpression::Call(call) => self.typed_call(call, VarType::String),
+            StringExpression::If(condition, then_value, else_value) => {
+                self.bool_expression(condition)?;
+                self.string_expression(then_value)?;
+                self.string_expression(else_value)
+            }
+            StringExpression::Index(list, index) => {
+                self.element(list, Some(index), ElementType::String)
+            }
+            StringExpression::Pop(list) => self.element(list, None, ElementType::String),
+        }
+    }
+
+    fn char_expression(&self, expression: &CharExpression) -> Result<(), IrValidationError> {
+        match expression {
+            CharExpression::Literal(_) => Ok(()),
+            CharExpression::Var(identifier) => self.var(identifier, VarType::Char),
+            CharExpression::FromInt(expression) => self.int_expression(expression),
+            CharExpression::Call(call) => self.typed_call(call, VarType::Char),
+            CharExpression::If(condition, then_value, else_value) => {
+                self.bool_expression(condition)?;
+                self.char_expression(then_value)?;
+                self.char_expression(else_value)
+            }
+            CharExpression::Index(list, index) => {
+                self.element(list, Some(index), ElementType::Char)
+            }
+            CharExpression::Pop(list) => self.element(list, None, ElementType::Char),
+        }
+    }
+
+    fn bool_expression(&self, expression: &BooleanExpression) -> Result<(), IrValidationError> {
+        match expression {
+            BooleanExpression::Literal(_) => Ok(()),
+            BooleanExpression::Not(expression) => self.bool_expression(expression),
+            BooleanExpression::Comparison(ComparisonExpression::IntComparison(first, rest)) => {
+                if rest.is_empty() {
+                    return self.error(String::from("comparison without an operator"));
+                }
+                self.int_expression(first)?;
+                for (_, expression) in rest {
+                    self.int_expression(expression)?;
+                }
+                Ok(())
+            }
+            BooleanExpression::Comparison(ComparisonExpression::CharComparison(first, rest)) => {
+                if rest.is_empty() {
+                    return self.error(String::from("comparison without an operator"));
+                }
+                self.char_expression(first)?;
+                for (_, expression) in rest {
+                    self.char_expression(expression)?;
+                }
+                Ok(())
+            }
+            // the result of `and`/`or` is stored in a temporary boolean variable
+            BooleanExpression::Operator(identifier, left, _, right) => {
+                self.var(identifier, VarType::Boolean)?;
+                self.bool_expression(left)?;
+                self.bool_expression(right)
+            }
+            BooleanExpression::Var(identifier) => self.var(identifier, VarType::Boolean),
+            BooleanExpression::Call(call) => self.typed_call(call, VarType::Boolean),
+            BooleanExpression::If(condition, then_value, else_value) => {
+                self.bool_expression(condition)?;
+                self.bool_expression(then_value)?;
+                self.bool_expression(else_value)
+            }
+            BooleanExpression::Index(list, index) => {
+                self.element(list, Some(index), ElementType::Boolean)
+            }
+            BooleanExpression::Pop(list) => self.element(list, None, ElementType::Boolean),
+        }
+    }
+
+    /// Validates a list expression, returning its element type
+    fn list_expression(
+        &self,
+        expression: &ListExpression,
+    ) -> Result<ElementType, IrValidationError> {
+        match expression {
+            ListExpression::Literal(element_type, values) => {
+                for value in values {
+                    self.element_value(value, *element_type)?;
+                }
+                Ok(*element_type)
+            }
+            ListExpression::Var(identifier) => match self.locals.get(identifier) {
+                Some(VarType::List(element_type)) => Ok(*element_type),
+                Some(declared) => self.error(format!(
+                    "variable {identifier} is used as a list, but declared as {declared}"
+                )),
+                None => self.error(format!("variable {identifier} is not declared")),
+            },
+            ListExpression::Call(call) => match self.call(call)? {
+                Some(VarType::List(element_type)) => Ok(element_type),
+                return_type => self.error(format!(
+                    "function {} returns {}, but is used as a list",
+                    call.function,
+                    Self::describe(return_type)
+                )),
+            },
+            ListExpression::If(condition, then_value, else_value) => {
+                self.bool_expression(condition)?;
+                let then_type = self.list_expression(then_value)?;
+                let else_type = self.list_expression(else_value)?;
+                if then_type != else_type {
+                    return self.error(format!(
+                        "branches of if are lists of {then_type} and {else_type}"
+                    ));
+                }
+                Ok(then_type)
+            }
+        }
+    }
+}
+
+/// Validates a module, codegen can compile every module that passes without panicking
+pub fn validate(module: &Module) -> Result<(), IrValidationError> {
+    let mut validator = Validator {
+        functions: HashMap::new(),
+        function: String::from("module"),
+        globals: HashMap::new(),
+        locals: HashMap::new(),
+        return_type: None,
+    };
+    validator.declare_functions(module)?;
+    validator.declare_globals(module)?;
+
+    let tests = module
+        .0
+        .iter()
+        .filter_map(|statement| match statement {
+            ToplevelStatement::Test { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    for statement in &module.0 {
+        match statement {
+            ToplevelStatement::Function {
+                name,
+                parameters,
+                return_type,
+                body,
+                locals,
+            } => {
+                if name == "main" && (!parameters.is_empty() || *return_type != Some(VarType::Int))
+                {
+                    validator.function = name.clone();
+                    validator.error(String::from("main must take nothing and return int"))?;
+                }
+                validator.function(name.clone(), parameters, Some(*return_type), body, locals)?;
+            }
+            ToplevelStatement::Test { name, body, locals } => {
+                validator.function(format!("test {name:?}"), &[], None, body, locals)?;
+            }
+            ToplevelStatement::TestHarness(names) => {
+                validator.function = String::from("test harness");
+                for name in names {
+                    if !tests.contains(&name.as_str()) {
+                        validator.error(format!("test {name:?} is not defined"))?;
+                    }
+                }
+            }
+            ToplevelStatement::ExternFunction { .. } | ToplevelStatement::Global { .. } => {}
+        }
+    }
+
+    Ok(())
+}
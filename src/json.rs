@@ -0,0 +1,1256 @@
+//! The `--format json` output.
+//!
+//! The tokens, ast and ir are converted into the types of this module before they are serialized, so the
+//! compiler's own types can change without changing the output. Every node is an object with a `kind` naming
+//! what it is and named fields for its parts, types are written like in the source (`list<int>`) and operators
+//! as their symbols.
+
+use serde::Serialize;
+
+use crate::{
+    ir,
+    parsing::{ast, tokens},
+    Diagnostic, IntType,
+};
+
+/// Version of the `--format json` output, increased whenever the shape of any line changes.
+///
+/// `tests/json_output.rs` compares the output for a small program against a golden file.
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// A line of JSON output
+pub enum JsonOutput<'a> {
+    Tokens {
+        file: &'a str,
+        tokens: &'a [tokens::Token],
+    },
+    Ast {
+        program: &'a ast::Program,
+    },
+    Ir {
+        module: &'a ir::Module,
+    },
+    Diagnostics {
+        diagnostics: &'a [Diagnostic],
+    },
+}
+
+impl JsonOutput<'_> {
+    /// The output as a single line of JSON, along with the schema version
+    pub fn to_line(&self) -> String {
+        let output = match self {
+            JsonOutput::Tokens { file, tokens } => Output::Tokens {
+                file,
+                tokens: tokens.iter().map(Token::from).collect(),
+            },
+            JsonOutput::Ast { program } => Output::Ast {
+                modules: program.0.iter().map(Module::from).collect(),
+            },
+            JsonOutput::Ir { module } => Output::Ir {
+                statements: module.0.iter().map(IrToplevelStatement::from).collect(),
+            },
+            JsonOutput::Diagnostics { diagnostics } => Output::Diagnostics { diagnostics },
+        };
+
+        serde_json::to_string(&VersionedOutput {
+            version: SCHEMA_VERSION,
+            output,
+        })
+        .unwrap()
+    }
+}
+
+#[derive(Serialize)]
+struct VersionedOutput<'a> {
+    version: u32,
+    #[serde(flatten)]
+    output: Output<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Output<'a> {
+    Tokens {
+        file: &'a str,
+        tokens: Vec<Token<'a>>,
+    },
+    Ast {
+        modules: Vec<Module<'a>>,
+    },
+    Ir {
+        statements: Vec<IrToplevelStatement<'a>>,
+    },
+    Diagnostics {
+        diagnostics: &'a [Diagnostic],
+    },
+}
+
+/// A token, `kind` is the name of its token type and `value` is only there for literals and identifiers
+#[derive(Serialize)]
+struct Token<'a> {
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<LiteralValue<'a>>,
+    line: usize,
+    char: usize,
+    start: usize,
+}
+
+/// The value of a literal, its kind or type tells which one it is
+#[derive(Serialize)]
+#[serde(untagged)]
+enum LiteralValue<'a> {
+    Int(IntType),
+    Boolean(bool),
+    Text(&'a str),
+    Char(char),
+}
+
+impl<'a> From<&'a tokens::Token> for Token<'a> {
+    fn from(token: &'a tokens::Token) -> Self {
+        use tokens::TokenType::*;
+
+        let (kind, value) = match &token._type {
+            Integer(int) => ("Integer", Some(LiteralValue::Int(*int))),
+            Identifier(name) => ("Identifier", Some(LiteralValue::Text(name))),
+            String(text) => ("String", Some(LiteralValue::Text(text))),
+            Char(value) => ("Char", Some(LiteralValue::Char(*value))),
+            Print => ("Print", None),
+            Let => ("Let", None),
+            Mut => ("Mut", None),
+            Set => ("Set", None),
+            SemiColon => ("SemiColon", None),
+            Dollar => ("Dollar", None),
+            CurlyOpen => ("CurlyOpen", None),
+            CurlyClose => ("CurlyClose", None),
+            ParenOpen => ("ParenOpen", None),
+            ParenClose => ("ParenClose", None),
+            BracketOpen => ("BracketOpen", None),
+            BracketClose => ("BracketClose", None),
+            Comma => ("Comma", None),
+            True => ("True", None),
+            False => ("False", None),
+            Assert => ("Assert", None),
+            Test => ("Test", None),
+            Return => ("Return", None),
+            Import => ("Import", None),
+            Extern => ("Extern", None),
+            Export => ("Export", None),
+            Fn => ("Fn", None),
+            If => ("If", None),
+            Else => ("Else", None),
+            For => ("For", None),
+            In => ("In", None),
+            Const => ("Const", None),
+            Static => ("Static", None),
+            Colon => ("Colon", None),
+            ColonColon => ("ColonColon", None),
+            Arrow => ("Arrow", None),
+            DotDot => ("DotDot", None),
+            DotDotEq => ("DotDotEq", None),
+            Eq => ("Eq", None),
+            Bang => ("Bang", None),
+            EqEq => ("EqEq", None),
+            BangEq => ("BangEq", None),
+            Lt => ("Lt", None),
+            Gt => ("Gt", None),
+            GtEq => ("GtEq", None),
+            LtEq => ("LtEq", None),
+            Minus => ("Minus", None),
+            Plus => ("Plus", None),
+            Star => ("Star", None),
+            Slash => ("Slash", None),
+            MinusEq => ("MinusEq", None),
+            PlusEq => ("PlusEq", None),
+            StarEq => ("StarEq", None),
+            SlashEq => ("SlashEq", None),
+            AndAnd => ("AndAnd", None),
+            OrOr => ("OrOr", None),
+            Or => ("Or", None),
+            And => ("And", None),
+            Eof => ("Eof", None),
+        };
+
+        Self {
+            kind,
+            value,
+            line: token.line,
+            char: token.char,
+            start: token.start,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Module<'a> {
+    name: &'a str,
+    file: &'a str,
+    is_root: bool,
+    imports: &'a [String],
+    statements: Vec<ToplevelStatement<'a>>,
+    suppressions: Vec<Suppression<'a>>,
+}
+
+#[derive(Serialize)]
+struct Suppression<'a> {
+    lint: &'a str,
+    line: usize,
+}
+
+impl<'a> From<&'a ast::ProgramModule> for Module<'a> {
+    fn from(module: &'a ast::ProgramModule) -> Self {
+        Self {
+            name: &module.name,
+            file: &module.file,
+            is_root: module.is_root,
+            imports: &module.imports,
+            statements: module
+                .module
+                .0
+                .iter()
+                .map(ToplevelStatement::from)
+                .collect(),
+            suppressions: module
+                .suppressions
+                .iter()
+                .map(|suppression| Suppression {
+                    lint: &suppression.lint,
+                    line: suppression.line,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind")]
+enum ToplevelStatement<'a> {
+    MainFunction {
+        body: Vec<Statement<'a>>,
+    },
+    Test {
+        name: &'a str,
+        body: Vec<Statement<'a>>,
+    },
+    Import {
+        path: &'a str,
+    },
+    ExternFunction {
+        #[serde(flatten)]
+        signature: FunctionSignature<'a>,
+    },
+    ExportFunction {
+        #[serde(flatten)]
+        signature: FunctionSignature<'a>,
+        body: Vec<Statement<'a>>,
+    },
+    Const {
+        #[serde(flatten)]
+        definition: GlobalDefinition<'a>,
+    },
+    Static {
+        #[serde(flatten)]
+        definition: GlobalDefinition<'a>,
+    },
+}
+
+#[derive(Serialize)]
+struct FunctionSignature<'a> {
+    name: &'a str,
+    parameters: Vec<Parameter<'a>>,
+    return_type: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct Parameter<'a> {
+    name: &'a str,
+    #[serde(rename = "type")]
+    type_name: &'a str,
+}
+
+#[derive(Serialize)]
+struct GlobalDefinition<'a> {
+    name: &'a str,
+    #[serde(rename = "type")]
+    type_name: &'a str,
+    value: Expression<'a>,
+    line: usize,
+}
+
+fn statements(statements: &[ast::Statement]) -> Vec<Statement<'_>> {
+    statements.iter().map(Statement::from).collect()
+}
+
+impl<'a> From<&'a ast::FunctionSignature> for FunctionSignature<'a> {
+    fn from(signature: &'a ast::FunctionSignature) -> Self {
+        Self {
+            name: &signature.name,
+            parameters: signature
+                .parameters
+                .iter()
+                .map(|(name, type_name)| Parameter { name, type_name })
+                .collect(),
+            return_type: signature.return_type.as_deref(),
+        }
+    }
+}
+
+impl<'a> From<&'a ast::GlobalDefinition> for GlobalDefinition<'a> {
+    fn from(definition: &'a ast::GlobalDefinition) -> Self {
+        Self {
+            name: &definition.name,
+            type_name: &definition.type_name,
+            value: (&definition.value).into(),
+            line: definition.line,
+        }
+    }
+}
+
+impl<'a> From<&'a ast::ToplevelStatement> for ToplevelStatement<'a> {
+    fn from(statement: &'a ast::ToplevelStatement) -> Self {
+        match statement {
+            ast::ToplevelStatement::MainFunction(body) => Self::MainFunction {
+                body: statements(body),
+            },
+            ast::ToplevelStatement::Test(name, body) => Self::Test {
+                name,
+                body: statements(body),
+            },
+            ast::ToplevelStatement::Import(path) => Self::Import { path },
+            ast::ToplevelStatement::ExternFunction(signature) => Self::ExternFunction {
+                signature: signature.into(),
+            },
+            ast::ToplevelStatement::ExportFunction(signature, body) => Self::ExportFunction {
+                signature: signature.into(),
+                body: statements(body),
+            },
+            ast::ToplevelStatement::Const(definition) => Self::Const {
+                definition: definition.into(),
+            },
+            ast::ToplevelStatement::Static(definition) => Self::Static {
+                definition: definition.into(),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SourceLocation<'a> {
+    file: &'a str,
+    line: usize,
+    text: &'a str,
+}
+
+impl<'a> From<&'a ast::SourceLocation> for SourceLocation<'a> {
+    fn from(location: &'a ast::SourceLocation) -> Self {
+        Self {
+            file: &location.file,
+            line: location.line,
+            text: &location.text,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind")]
+enum Statement<'a> {
+    Print {
+        arguments: Vec<PrintArgument<'a>>,
+    },
+    Assert {
+        condition: Expression<'a>,
+        message: Option<&'a str>,
+        location: SourceLocation<'a>,
+    },
+    Declaration {
+        name: &'a str,
+        #[serde(rename = "type")]
+        type_name: Option<&'a str>,
+        value: Expression<'a>,
+        mutable: bool,
+        line: usize,
+    },
+    Assignment {
+        name: &'a str,
+        value: Expression<'a>,
+        line: usize,
+    },
+    IndexAssignment {
+        name: &'a str,
+        index: Expression<'a>,
+        value: Expression<'a>,
+        line: usize,
+    },
+    CompoundAssignment {
+        name: &'a str,
+        operator: &'static str,
+        value: Expression<'a>,
+        line: usize,
+    },
+    Return {
+        value: Option<Expression<'a>>,
+    },
+    Expression {
+        expression: Expression<'a>,
+        line: usize,
+    },
+    For {
+        variable: &'a str,
+        iterable: Iterable<'a>,
+        body: Vec<Statement<'a>>,
+        line: usize,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind")]
+enum Iterable<'a> {
+    Range {
+        start: Expression<'a>,
+        end: Expression<'a>,
+        inclusive: bool,
+        step: Option<Expression<'a>>,
+    },
+    List {
+        list: Expression<'a>,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind")]
+enum PrintArgument<'a> {
+    Expression { expression: Expression<'a> },
+    Format { parts: Vec<FormatPart<'a>> },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind")]
+enum FormatPart<'a> {
+    Text { text: &'a str },
+    Expression { expression: Expression<'a> },
+}
+
+impl<'a> From<&'a ast::Statement> for Statement<'a> {
+    fn from(statement: &'a ast::Statement) -> Self {
+        match statement {
+            ast::Statement::Print(arguments) => Self::Print {
+                arguments: arguments
+                    .iter()
+                    .map(|argument| match argument {
+                        ast::PrintArgument::Expression(expression) => PrintArgument::Expression {
+                            expression: expression.into(),
+                        },
+                        ast::PrintArgument::Format(parts) => PrintArgument::Format {
+                            parts: parts
+                                .iter()
+                                .map(|part| match part {
+                                    ast::FormatPart::Text(text) => FormatPart::Text { text },
+                                    ast::FormatPart::Expression(expression) => {
+                                        FormatPart::Expression {
+                                            expression: expression.into(),
+                                        }
+                                    }
+                                })
+                                .collect(),
+                        },
+                    })
+                    .collect(),
+            },
+            ast::Statement::Assert(condition, message, location) => Self::Assert {
+                condition: condition.into(),
+                message: message.as_deref(),
+                location: location.into(),
+            },
+            ast::Statement::Declaration(name, type_name, value, mutable, line) => {
+                Self::Declaration {
+                    name,
+                    type_name: type_name.as_deref(),
+                    value: value.into(),
+                    mutable: *mutable,
+                    line: *line,
+                }
+            }
+            ast::Statement::Assignment(name, value, line) => Self::Assignment {
+                name,
+                value: value.into(),
+                line: *line,
+            },
+            ast::Statement::IndexAssignment(name, index, value, line) => Self::IndexAssignment {
+                name,
+                index: index.into(),
+                value: value.into(),
+                line: *line,
+            },
+            ast::Statement::CompoundAssignment(name, op, value, line) => Self::CompoundAssignment {
+                name,
+                operator: binary_operator(*op),
+                value: value.into(),
+                line: *line,
+            },
+            ast::Statement::Return(value) => Self::Return {
+                value: value.as_ref().map(Expression::from),
+            },
+            ast::Statement::Expression(expression, line) => Self::Expression {
+                expression: expression.into(),
+                line: *line,
+            },
+            ast::Statement::For(for_loop) => Self::For {
+                variable: &for_loop.variable,
+                iterable: match &for_loop.iterable {
+                    ast::Iterable::Range {
+                        start,
+                        end,
+                        inclusive,
+                        step,
+                    } => Iterable::Range {
+                        start: start.into(),
+                        end: end.into(),
+                        inclusive: *inclusive,
+                        step: step.as_ref().map(Expression::from),
+                    },
+                    ast::Iterable::List(list) => Iterable::List { list: list.into() },
+                },
+                body: statements(&for_loop.body),
+                line: for_loop.line,
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind")]
+enum Expression<'a> {
+    Integer {
+        value: IntType,
+    },
+    Boolean {
+        value: bool,
+    },
+    String {
+        value: &'a str,
+    },
+    Char {
+        value: char,
+    },
+    Variable {
+        name: &'a str,
+    },
+    Binary {
+        left: Box<Expression<'a>>,
+        operator: &'static str,
+        right: Box<Expression<'a>>,
+    },
+    Prefix {
+        operator: &'static str,
+        operand: Box<Expression<'a>>,
+        line: usize,
+    },
+    /// `a < b <= c` has `a` on the left and a comparison for `< b` and `<= c`
+    Comparison {
+        left: Box<Expression<'a>>,
+        comparisons: Vec<Comparison<Expression<'a>>>,
+        line: usize,
+    },
+    Call {
+        function: &'a str,
+        arguments: Vec<Expression<'a>>,
+    },
+    If {
+        condition: Box<Expression<'a>>,
+        then_value: Box<Expression<'a>>,
+        else_value: Box<Expression<'a>>,
+        line: usize,
+    },
+    List {
+        elements: Vec<Expression<'a>>,
+    },
+    Index {
+        list: Box<Expression<'a>>,
+        index: Box<Expression<'a>>,
+    },
+}
+
+#[derive(Serialize)]
+struct Comparison<T> {
+    operator: &'static str,
+    right: T,
+}
+
+fn binary_operator(op: ast::BinaryOp) -> &'static str {
+    match op {
+        ast::BinaryOp::Plus => "+",
+        ast::BinaryOp::Minus => "-",
+        ast::BinaryOp::Multiply => "*",
+        ast::BinaryOp::Divide => "/",
+        ast::BinaryOp::And => "&&",
+        ast::BinaryOp::Or => "||",
+    }
+}
+
+fn boxed<'a>(expression: &'a ast::Expression) -> Box<Expression<'a>> {
+    Box::new(expression.into())
+}
+
+impl<'a> From<&'a ast::Expression> for Expression<'a> {
+    fn from(expression: &'a ast::Expression) -> Self {
+        match expression {
+            ast::Expression::Literal(ast::Literal::Integer(value)) => {
+                Self::Integer { value: *value }
+            }
+            ast::Expression::Literal(ast::Literal::Boolean(value)) => {
+                Self::Boolean { value: *value }
+            }
+            ast::Expression::Literal(ast::Literal::String(value)) => Self::String { value },
+            ast::Expression::Literal(ast::Literal::Char(value)) => Self::Char { value: *value },
+            ast::Expression::Literal(ast::Literal::Variable(name)) => Self::Variable { name },
+            ast::Expression::BinaryOp(left, op, right) => Self::Binary {
+                left: boxed(left),
+                operator: binary_operator(*op),
+                right: boxed(right),
+            },
+            ast::Expression::Prefix(op, operand, line) => Self::Prefix {
+                operator: match op {
+                    ast::PrefixOp::Negate => "-",
+                    ast::PrefixOp::Not => "!",
+                },
+                operand: boxed(operand),
+                line: *line,
+            },
+            ast::Expression::Comparison(left, chains, line) => Self::Comparison {
+                left: boxed(left),
+                comparisons: chains
+                    .iter()
+                    .map(|(op, right)| Comparison {
+                        operator: match op {
+                            ast::ComparisonOp::Equals => "==",
+                            ast::ComparisonOp::NotEquals => "!=",
+                            ast::ComparisonOp::LessThan => "<",
+                            ast::ComparisonOp::LessThanEquals => "<=",
+                            ast::ComparisonOp::GreaterThan => ">",
+                            ast::ComparisonOp::GreaterThanEquals => ">=",
+                        },
+                        right: right.into(),
+                    })
+                    .collect(),
+                line: *line,
+            },
+            ast::Expression::Call(function, arguments) => Self::Call {
+                function,
+                arguments: arguments.iter().map(Expression::from).collect(),
+            },
+            ast::Expression::If(condition, then_value, else_value, line) => Self::If {
+                condition: boxed(condition),
+                then_value: boxed(then_value),
+                else_value: boxed(else_value),
+                line: *line,
+            },
+            ast::Expression::List(elements) => Self::List {
+                elements: elements.iter().map(Expression::from).collect(),
+            },
+            ast::Expression::Index(list, index) => Self::Index {
+                list: boxed(list),
+                index: boxed(index),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind")]
+enum IrToplevelStatement<'a> {
+    Function {
+        name: &'a str,
+        parameters: Vec<IrParameter<'a>>,
+        return_type: Option<String>,
+        body: Vec<IrStatement<'a>>,
+        locals: Vec<Local>,
+    },
+    Test {
+        name: &'a str,
+        body: Vec<IrStatement<'a>>,
+        locals: Vec<Local>,
+    },
+    TestHarness {
+        tests: &'a [String],
+    },
+    ExternFunction {
+        name: &'a str,
+        parameters: Vec<String>,
+        return_type: Option<String>,
+    },
+    Global {
+        name: &'a str,
+        variable: usize,
+        #[serde(rename = "type")]
+        var_type: String,
+        value: LiteralValue<'a>,
+    },
+}
+
+#[derive(Serialize)]
+struct IrParameter<'a> {
+    name: &'a str,
+    variable: usize,
+    #[serde(rename = "type")]
+    var_type: String,
+}
+
+#[derive(Serialize)]
+struct Local {
+    variable: usize,
+    #[serde(rename = "type")]
+    var_type: String,
+}
+
+fn ir_statements(statements: &[ir::Statement]) -> Vec<IrStatement<'_>> {
+    statements.iter().map(IrStatement::from).collect()
+}
+
+fn locals(locals: &ir::Locals) -> Vec<Local> {
+    locals
+        .iter()
+        .map(|(identifier, var_type)| Local {
+            variable: identifier.0,
+            var_type: var_type.to_string(),
+        })
+        .collect()
+}
+
+fn constant(constant: &ir::Constant) -> LiteralValue<'_> {
+    match constant {
+        ir::Constant::Int(value) => LiteralValue::Int(*value),
+        ir::Constant::Boolean(value) => LiteralValue::Boolean(*value),
+        ir::Constant::String(value) => LiteralValue::Text(value),
+        ir::Constant::Char(value) => LiteralValue::Char(*value),
+    }
+}
+
+impl<'a> From<&'a ir::ToplevelStatement> for IrToplevelStatement<'a> {
+    fn from(statement: &'a ir::ToplevelStatement) -> Self {
+        match statement {
+            ir::ToplevelStatement::Function {
+                name,
+                parameters,
+                return_type,
+                body,
+                locals: function_locals,
+            } => Self::Function {
+                name,
+                parameters: parameters
+                    .iter()
+                    .map(|parameter| IrParameter {
+                        name: &parameter.name,
+                        variable: parameter.identifier.0,
+                        var_type: parameter.var_type.to_string(),
+                    })
+                    .collect(),
+                return_type: return_type.map(|return_type| return_type.to_string()),
+                body: ir_statements(body),
+                locals: locals(function_locals),
+            },
+            ir::ToplevelStatement::Test {
+                name,
+                body,
+                locals: test_locals,
+            } => Self::Test {
+                name,
+                body: ir_statements(body),
+                locals: locals(test_locals),
+            },
+            ir::ToplevelStatement::TestHarness(tests) => Self::TestHarness { tests },
+            ir::ToplevelStatement::ExternFunction {
+                name,
+                parameters,
+                return_type,
+            } => Self::ExternFunction {
+                name,
+                parameters: parameters.iter().map(ToString::to_string).collect(),
+                return_type: return_type.map(|return_type| return_type.to_string()),
+            },
+            ir::ToplevelStatement::Global {
+                name,
+                identifier,
+                value,
+            } => Self::Global {
+                name,
+                variable: identifier.0,
+                var_type: value.var_type().to_string(),
+                value: constant(value),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind")]
+enum IrStatement<'a> {
+    Print {
+        values: Vec<PrintValue<'a>>,
+    },
+    Assert {
+        condition: IrExpression<'a>,
+        message: Option<&'a str>,
+        location: SourceLocation<'a>,
+    },
+    Assignment {
+        variable: usize,
+        value: IrExpression<'a>,
+    },
+    CompoundAssignment {
+        variable: usize,
+        operator: &'static str,
+        value: IrExpression<'a>,
+    },
+    Return {
+        value: Option<IrExpression<'a>>,
+    },
+    Exit {
+        code: IrExpression<'a>,
+    },
+    Call {
+        #[serde(flatten)]
+        call: Call<'a>,
+    },
+    While {
+        condition: IrExpression<'a>,
+        body: Vec<IrStatement<'a>>,
+    },
+    Push {
+        list: IrExpression<'a>,
+        value: IrExpression<'a>,
+    },
+    SetIndex {
+        list: IrExpression<'a>,
+        index: IrExpression<'a>,
+        value: IrExpression<'a>,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind")]
+enum PrintValue<'a> {
+    Text { text: &'a str },
+    Value { value: IrExpression<'a> },
+}
+
+#[derive(Serialize)]
+struct Call<'a> {
+    function: &'a str,
+    arguments: Vec<IrExpression<'a>>,
+}
+
+impl<'a> From<&'a ir::Call> for Call<'a> {
+    fn from(call: &'a ir::Call) -> Self {
+        Self {
+            function: &call.function,
+            arguments: call.arguments.iter().map(IrExpression::from).collect(),
+        }
+    }
+}
+
+fn int_operator(op: &ir::IntBinaryOp) -> &'static str {
+    match op {
+        ir::IntBinaryOp::Plus => "+",
+        ir::IntBinaryOp::Minus => "-",
+        ir::IntBinaryOp::Multiply => "*",
+        ir::IntBinaryOp::Divide => "/",
+    }
+}
+
+impl<'a> From<&'a ir::Statement> for IrStatement<'a> {
+    fn from(statement: &'a ir::Statement) -> Self {
+        match statement {
+            ir::Statement::Print(values) => Self::Print {
+                values: values
+                    .iter()
+                    .map(|value| match value {
+                        ir::PrintValue::Text(text) => PrintValue::Text { text },
+                        ir::PrintValue::Int(value) => PrintValue::Value {
+                            value: value.into(),
+                        },
+                        ir::PrintValue::Boolean(value) => PrintValue::Value {
+                            value: value.into(),
+                        },
+                        ir::PrintValue::String(value) => PrintValue::Value {
+                            value: value.into(),
+                        },
+                        ir::PrintValue::Char(value) => PrintValue::Value {
+                            value: value.into(),
+                        },
+                    })
+                    .collect(),
+            },
+            ir::Statement::Assert(condition, message, location) => Self::Assert {
+                condition: condition.into(),
+                message: message.as_deref(),
+                location: location.into(),
+            },
+            ir::Statement::Assignment(identifier, value) => Self::Assignment {
+                variable: identifier.0,
+                value: match value {
+                    ir::AssignmentStatement::Int(value) => value.into(),
+                    ir::AssignmentStatement::Boolean(value) => value.into(),
+                    ir::AssignmentStatement::String(value) => value.into(),
+                    ir::AssignmentStatement::Char(value) => value.into(),
+                    ir::AssignmentStatement::List(value) => value.into(),
+                },
+            },
+            ir::Statement::CompoundAssignment(identifier, op, value) => Self::CompoundAssignment {
+                variable: identifier.0,
+                operator: int_operator(op),
+                value: value.into(),
+            },
+            ir::Statement::Return(value) => Self::Return {
+                value: value.as_ref().map(IrExpression::from),
+            },
+            ir::Statement::Exit(code) => Self::Exit { code: code.into() },
+            ir::Statement::Call(call) => Self::Call { call: call.into() },
+            ir::Statement::While(condition, body) => Self::While {
+                condition: condition.into(),
+                body: ir_statements(body),
+            },
+            ir::Statement::Push(list, value) => Self::Push {
+                list: list.into(),
+                value: value.into(),
+            },
+            ir::Statement::SetIndex(list, index, value) => Self::SetIndex {
+                list: list.into(),
+                index: index.into(),
+                value: value.into(),
+            },
+        }
+    }
+}
+
+/// An expression of the ir, `type` is the type of its value. List expressions only know that they are a list,
+/// the element type is only written on list literals.
+#[derive(Serialize)]
+struct IrExpression<'a> {
+    #[serde(rename = "type")]
+    var_type: &'static str,
+    #[serde(flatten)]
+    expression: IrExpressionKind<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind")]
+enum IrExpressionKind<'a> {
+    Literal {
+        value: LiteralValue<'a>,
+    },
+    /// A new list holding the elements
+    List {
+        element_type: String,
+        elements: Vec<IrExpression<'a>>,
+    },
+    Var {
+        variable: usize,
+    },
+    Call {
+        #[serde(flatten)]
+        call: Call<'a>,
+    },
+    If {
+        condition: Box<IrExpression<'a>>,
+        then_value: Box<IrExpression<'a>>,
+        else_value: Box<IrExpression<'a>>,
+    },
+    Index {
+        list: Box<IrExpression<'a>>,
+        index: Box<IrExpression<'a>>,
+    },
+    Pop {
+        list: Box<IrExpression<'a>>,
+    },
+    Negate {
+        operand: Box<IrExpression<'a>>,
+    },
+    Not {
+        operand: Box<IrExpression<'a>>,
+    },
+    Binary {
+        left: Box<IrExpression<'a>>,
+        operator: &'static str,
+        right: Box<IrExpression<'a>>,
+    },
+    /// `&&` and `||`, `variable` holds the result while the right side is only evaluated if needed
+    Logical {
+        variable: usize,
+        left: Box<IrExpression<'a>>,
+        operator: &'static str,
+        right: Box<IrExpression<'a>>,
+    },
+    Comparison {
+        left: Box<IrExpression<'a>>,
+        comparisons: Vec<Comparison<IrExpression<'a>>>,
+    },
+    ReadInt,
+    ReadLine,
+    ArgCount,
+    Arg {
+        index: Box<IrExpression<'a>>,
+    },
+    ParseInt {
+        string: Box<IrExpression<'a>>,
+    },
+    Length {
+        list: Box<IrExpression<'a>>,
+    },
+    /// Code point of a char
+    FromChar {
+        char: Box<IrExpression<'a>>,
+    },
+    /// The char with a code point
+    FromInt {
+        code: Box<IrExpression<'a>>,
+    },
+}
+
+impl<'a> IrExpression<'a> {
+    fn new(var_type: &'static str, expression: IrExpressionKind<'a>) -> Self {
+        Self {
+            var_type,
+            expression,
+        }
+    }
+}
+
+fn ir_boxed<'a, T>(expression: &'a T) -> Box<IrExpression<'a>>
+where
+    &'a T: Into<IrExpression<'a>>,
+{
+    Box::new(expression.into())
+}
+
+fn comparisons<'a, T>(chains: &'a [(ir::IntComparisonOp, T)]) -> Vec<Comparison<IrExpression<'a>>>
+where
+    &'a T: Into<IrExpression<'a>>,
+{
+    chains
+        .iter()
+        .map(|(op, right)| Comparison {
+            operator: match op {
+                ir::IntComparisonOp::Equal => "==",
+                ir::IntComparisonOp::NotEquals => "!=",
+                ir::IntComparisonOp::LessThan => "<",
+                ir::IntComparisonOp::LessThanEquals => "<=",
+                ir::IntComparisonOp::GreaterThan => ">",
+                ir::IntComparisonOp::GreaterThanEquals => ">=",
+            },
+            right: right.into(),
+        })
+        .collect()
+}
+
+impl<'a> From<&'a ir::Value> for IrExpression<'a> {
+    fn from(value: &'a ir::Value) -> Self {
+        match value {
+            ir::Value::Int(value) => value.into(),
+            ir::Value::Boolean(value) => value.into(),
+            ir::Value::String(value) => value.into(),
+            ir::Value::Char(value) => value.into(),
+            ir::Value::List(value) => value.into(),
+        }
+    }
+}
+
+impl<'a> From<&'a ir::IntExpression> for IrExpression<'a> {
+    fn from(expression: &'a ir::IntExpression) -> Self {
+        use IrExpressionKind as Kind;
+
+        IrExpression::new(
+            "int",
+            match expression {
+                ir::IntExpression::Literal(value) => Kind::Literal {
+                    value: LiteralValue::Int(*value),
+                },
+                ir::IntExpression::Negate(operand) => Kind::Negate {
+                    operand: ir_boxed(&**operand),
+                },
+                ir::IntExpression::BinaryOperation(left, op, right) => Kind::Binary {
+                    left: ir_boxed(&**left),
+                    operator: int_operator(op),
+                    right: ir_boxed(&**right),
+                },
+                ir::IntExpression::Var(identifier) => Kind::Var {
+                    variable: identifier.0,
+                },
+                ir::IntExpression::ReadInt => Kind::ReadInt,
+                ir::IntExpression::ArgCount => Kind::ArgCount,
+                ir::IntExpression::ParseInt(string) => Kind::ParseInt {
+                    string: ir_boxed(&**string),
+                },
+                ir::IntExpression::Call(call) => Kind::Call { call: call.into() },
+                ir::IntExpression::If(condition, then_value, else_value) => Kind::If {
+                    condition: ir_boxed(&**condition),
+                    then_value: ir_boxed(&**then_value),
+                    else_value: ir_boxed(&**else_value),
+                },
+                ir::IntExpression::Index(list, index) => Kind::Index {
+                    list: ir_boxed(&**list),
+                    index: ir_boxed(&**index),
+                },
+                ir::IntExpression::Pop(list) => Kind::Pop {
+                    list: ir_boxed(&**list),
+                },
+                ir::IntExpression::Length(list) => Kind::Length {
+                    list: ir_boxed(&**list),
+                },
+                ir::IntExpression::FromChar(char) => Kind::FromChar {
+                    char: ir_boxed(&**char),
+                },
+            },
+        )
+    }
+}
+
+impl<'a> From<&'a ir::StringExpression> for IrExpression<'a> {
+    fn from(expression: &'a ir::StringExpression) -> Self {
+        use IrExpressionKind as Kind;
+
+        IrExpression::new(
+            "string",
+            match expression {
+                ir::StringExpression::Literal(value) => Kind::Literal {
+                    value: LiteralValue::Text(value),
+                },
+                ir::StringExpression::Var(identifier) => Kind::Var {
+                    variable: identifier.0,
+                },
+                ir::StringExpression::ReadLine => Kind::ReadLine,
+                ir::StringExpression::Arg(index) => Kind::Arg {
+                    index: ir_boxed(&**index),
+                },
+                ir::StringExpression::Call(call) => Kind::Call { call: call.into() },
+                ir::StringExpression::If(condition, then_value, else_value) => Kind::If {
+                    condition: ir_boxed(&**condition),
+                    then_value: ir_boxed(&**then_value),
+                    else_value: ir_boxed(&**else_value),
+                },
+                ir::StringExpression::Index(list, index) => Kind::Index {
+                    list: ir_boxed(&**list),
+                    index: ir_boxed(&**index),
+                },
+                ir::StringExpression::Pop(list) => Kind::Pop {
+                    list: ir_boxed(&**list),
+                },
+            },
+        )
+    }
+}
+
+impl<'a> From<&'a ir::CharExpression> for IrExpression<'a> {
+    fn from(expression: &'a ir::CharExpression) -> Self {
+        use IrExpressionKind as Kind;
+
+        IrExpression::new(
+            "char",
+            match expression {
+                ir::CharExpression::Literal(value) => Kind::Literal {
+                    value: LiteralValue::Char(*value),
+                },
+                ir::CharExpression::Var(identifier) => Kind::Var {
+                    variable: identifier.0,
+                },
+                ir::CharExpression::FromInt(code) => Kind::FromInt {
+                    code: ir_boxed(&**code),
+                },
+                ir::CharExpression::Call(call) => Kind::Call { call: call.into() },
+                ir::CharExpression::If(condition, then_value, else_value) => Kind::If {
+                    condition: ir_boxed(&**condition),
+                    then_value: ir_boxed(&**then_value),
+                    else_value: ir_boxed(&**else_value),
+                },
+                ir::CharExpression::Index(list, index) => Kind::Index {
+                    list: ir_boxed(&**list),
+                    index: ir_boxed(&**index),
+                },
+                ir::CharExpression::Pop(list) => Kind::Pop {
+                    list: ir_boxed(&**list),
+                },
+            },
+        )
+    }
+}
+
+impl<'a> From<&'a ir::BooleanExpression> for IrExpression<'a> {
+    fn from(expression: &'a ir::BooleanExpression) -> Self {
+        use IrExpressionKind as Kind;
+
+        IrExpression::new(
+            "bool",
+            match expression {
+                ir::BooleanExpression::Literal(value) => Kind::Literal {
+                    value: LiteralValue::Boolean(*value),
+                },
+                ir::BooleanExpression::Not(operand) => Kind::Not {
+                    operand: ir_boxed(&**operand),
+                },
+                ir::BooleanExpression::Comparison(ir::ComparisonExpression::IntComparison(
+                    left,
+                    chains,
+                )) => Kind::Comparison {
+                    left: ir_boxed(&**left),
+                    comparisons: comparisons(chains),
+                },
+                ir::BooleanExpression::Comparison(ir::ComparisonExpression::CharComparison(
+                    left,
+                    chains,
+                )) => Kind::Comparison {
+                    left: ir_boxed(&**left),
+                    comparisons: comparisons(chains),
+                },
+                ir::BooleanExpression::Operator(identifier, left, op, right) => Kind::Logical {
+                    variable: identifier.0,
+                    left: ir_boxed(&**left),
+                    operator: match op {
+                        ir::BooleanOperator::And => "&&",
+                        ir::BooleanOperator::Or => "||",
+                    },
+                    right: ir_boxed(&**right),
+                },
+                ir::BooleanExpression::Var(identifier) => Kind::Var {
+                    variable: identifier.0,
+                },
+                ir::BooleanExpression::Call(call) => Kind::Call { call: call.into() },
+                ir::BooleanExpression::If(condition, then_value, else_value) => Kind::If {
+                    condition: ir_boxed(&**condition),
+                    then_value: ir_boxed(&**then_value),
+                    else_value: ir_boxed(&**else_value),
+                },
+                ir::BooleanExpression::Index(list, index) => Kind::Index {
+                    list: ir_boxed(&**list),
+                    index: ir_boxed(&**index),
+                },
+                ir::BooleanExpression::Pop(list) => Kind::Pop {
+                    list: ir_boxed(&**list),
+                },
+            },
+        )
+    }
+}
+
+impl<'a> From<&'a ir::ListExpression> for IrExpression<'a> {
+    fn from(expression: &'a ir::ListExpression) -> Self {
+        use IrExpressionKind as Kind;
+
+        IrExpression::new(
+            "list",
+            match expression {
+                ir::ListExpression::Literal(element_type, elements) => Kind::List {
+                    element_type: element_type.to_string(),
+                    elements: elements.iter().map(IrExpression::from).collect(),
+                },
+                ir::ListExpression::Var(identifier) => Kind::Var {
+                    variable: identifier.0,
+                },
+                ir::ListExpression::Call(call) => Kind::Call { call: call.into() },
+                ir::ListExpression::If(condition, then_value, else_value) => Kind::If {
+                    condition: ir_boxed(&**condition),
+                    then_value: ir_boxed(&**then_value),
+                    else_value: ir_boxed(&**else_value),
+                },
+            },
+        )
+    }
+}
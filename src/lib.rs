@@ -1,51 +1,216 @@
 use anyhow::Context;
 
 mod code_gen;
-mod ir;
-mod parsing;
-mod type_analyzer;
+mod compilation;
+pub mod ir;
+pub mod json;
+pub mod parsing;
+pub mod type_analyzer;
+
+pub use compilation::{compile, Compilation, Diagnostic, Severity};
 
 type IntType = i32;
-type FloatType = f64;
 
-const IntWidth: usize = 32;
-const FloatWidth: usize = 64;
+const INT_WIDTH: usize = 32;
 
+#[derive(Default, Clone)]
 pub struct CompilerOptions {
     pub dont_optimize: bool,
-    pub output_tokens: bool,
-    pub output_ast: bool,
-    pub output_ir: bool,
-    pub output_llvm: bool,
+    /// Compile the test blocks into a test harness instead of compiling the main function
+    pub test_mode: bool,
+    /// Libraries passed to the linker as `-l<library>`
+    pub link_libraries: Vec<String>,
+    /// Produce position independent code, needed for shared libraries
+    pub position_independent: bool,
+    /// Validate the ir before codegen, this is always done in debug builds
+    pub verify_ir: bool,
+    /// Lints reported as errors instead of warnings
+    pub denied_lints: Vec<String>,
+    /// Lints that aren't reported at all
+    pub allowed_lints: Vec<String>,
+    /// Report every lint that isn't allowed as an error
+    pub deny_warnings: bool,
+    /// Print the number of lists and strings that are still allocated to stderr when main returns
+    pub leak_check: bool,
+    /// Directories searched for headers when C files passed to the linker are compiled
+    pub include_directories: Vec<String>,
+}
+
+/// Reads and compiles a file, only failing if the file can't be read.
+pub fn compile_file(file_name: &str, options: &CompilerOptions) -> anyhow::Result<Compilation> {
+    let source = std::fs::read_to_string(file_name)
+        .with_context(|| format!("Reading input file {file_name}"))?;
+
+    Ok(compile(file_name, &source, options))
+}
+
+/// Writes the object file of a compilation, or fails with its first error.
+pub fn write_object(compilation: &Compilation, object_file: &str) -> anyhow::Result<()> {
+    if let Some(error) = compilation.first_error() {
+        anyhow::bail!("{error}");
+    }
+
+    std::fs::write(object_file, compilation.object.as_ref().unwrap())
+        .with_context(|| format!("Writing object file {object_file}"))
+}
+
+/// Compiles a file and everything it imports into a single object file.
+pub fn compile_to_object(
+    file_name: &str,
+    object_file: &str,
+    options: &CompilerOptions,
+) -> anyhow::Result<Compilation> {
+    let compilation = compile_file(file_name, options)?;
+    write_object(&compilation, object_file)?;
+
+    Ok(compilation)
+}
+
+/// Links object files produced by `compile_to_object` into an executable.
+///
+/// C source files can be passed along as well, they are compiled by the linker.
+pub fn link(
+    object_files: &[&str],
+    output_file: &str,
+    options: &CompilerOptions,
+) -> anyhow::Result<()> {
+    compile_to_binary(
+        object_files,
+        output_file,
+        &options.link_libraries,
+        &options.include_directories,
+    )
+}
+
+/// Bundles object files into a static library, or a shared library if the output ends with `.so`.
+pub fn link_library(
+    object_files: &[&str],
+    output_file: &str,
+    options: &CompilerOptions,
+) -> anyhow::Result<()> {
+    if output_file.ends_with(".so") {
+        let clang = find_any_on_path(vec!["clang", "gcc"])
+            .ok_or(anyhow::anyhow!("Clang or gcc not found on path"))?;
+        std::process::Command::new(clang)
+            .arg("-shared")
+            .args(object_files)
+            .args(["-o", output_file])
+            .args(
+                options
+                    .link_libraries
+                    .iter()
+                    .map(|library| format!("-l{library}")),
+            )
+            .spawn()?
+            .wait()?
+            .success()
+            .then_some(())
+            .ok_or(anyhow::anyhow!("Clang/gcc failed"))?;
+    } else {
+        let ar = find_on_path("ar").ok_or(anyhow::anyhow!("Ar not found on path"))?;
+        std::process::Command::new(ar)
+            .arg("rcs")
+            .arg(output_file)
+            .args(object_files)
+            .spawn()?
+            .wait()?
+            .success()
+            .then_some(())
+            .ok_or(anyhow::anyhow!("Ar failed"))?;
+    }
+
+    Ok(())
 }
 
-pub fn build(file_name: &str, output_file: &str, options: CompilerOptions) -> anyhow::Result<()> {
-    let code = std::fs::read_to_string(file_name).context("Reading input file")?;
+/// Generates a C header declaring the exported functions of a compiled module,
+/// along with `viv_list_release` for the lists they return.
+pub fn c_header(module_name: &str, ir: &ir::Module) -> String {
+    let c_type = |var_type: &ir::VarType| match var_type {
+        ir::VarType::Int => "int",
+        ir::VarType::Boolean => "bool",
+        ir::VarType::String => "const char*",
+        ir::VarType::Char => "uint32_t",
+        ir::VarType::List(_) => "void*",
+    };
 
-    let ast = parsing::parse(&code, &options).context("Parsing input file")?;
-    let ir = type_analyzer::Analyzer::new()
-        .resolve_module(&ast)
-        .context("Resolving types")?;
+    let guard = module_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+        + "_H";
+
+    let mut header = format!(
+        "#ifndef {guard}\n#define {guard}\n\n#include <stdbool.h>\n#include <stdint.h>\n\n"
+    );
+    header += "// Strings passed to these functions are copied, a returned string stays valid until the function\n";
+    header += "// is called again. Lists passed to them are only borrowed, a returned list belongs to the caller\n";
+    header += "// which releases it with viv_list_release.\n";
+    header += "void viv_list_release(void* list);\n\n";
+    for statement in &ir.0 {
+        let ir::ToplevelStatement::Function {
+            name,
+            parameters,
+            return_type,
+            ..
+        } = statement
+        else {
+            continue;
+        };
+        if name == "main" {
+            continue;
+        }
 
-    if options.output_ir {
-        println!("{ir:#?}");
+        let parameters = if parameters.is_empty() {
+            String::from("void")
+        } else {
+            parameters
+                .iter()
+                .map(|parameter| format!("{} {}", c_type(&parameter.var_type), parameter.name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let return_type = return_type.as_ref().map(c_type).unwrap_or("void");
+        header += &format!("{return_type} {name}({parameters});\n");
     }
+    header += &format!("\n#endif // {guard}\n");
 
-    let inkwell_context = inkwell::context::Context::create();
-    let mut code_gen = code_gen::CodeGen::new(&inkwell_context);
-    code_gen.compile_module(&ir);
+    header
+}
 
-    let llvm_ir_output_file = temp_file::empty();
-    code_gen.output_to_file(llvm_ir_output_file.path(), &options);
+/// Writes the C header of a compiled file into `directory`, named after the file with a `.h` extension.
+pub fn write_c_header(
+    file_name: &str,
+    compilation: &Compilation,
+    directory: &std::path::Path,
+) -> anyhow::Result<()> {
+    let header_name = std::path::Path::new(file_name).with_extension("h");
+    let module_name = header_name.file_stem().unwrap().to_string_lossy();
+    let header = c_header(&module_name, compilation.ir.as_ref().unwrap());
+    let header_file = directory.join(header_name.file_name().unwrap());
+    std::fs::write(&header_file, header)
+        .with_context(|| format!("Writing {}", header_file.display()))
+}
 
+pub fn build(
+    file_name: &str,
+    output_file: &str,
+    options: CompilerOptions,
+) -> anyhow::Result<Compilation> {
     let object_file = temp_file::empty();
-    compile_to_objectfile(
-        llvm_ir_output_file.path().to_str().unwrap(),
-        object_file.path().to_str().unwrap(),
+    let compilation = compile_to_object(file_name, object_file.path().to_str().unwrap(), &options)?;
+    link(
+        &[object_file.path().to_str().unwrap()],
+        output_file,
+        &options,
     )?;
-    compile_to_binary(object_file.path().to_str().unwrap(), output_file)?;
 
-    Ok(())
+    Ok(compilation)
 }
 
 fn find_on_path(program: &str) -> Option<std::path::PathBuf> {
@@ -72,24 +237,23 @@ fn find_any_on_path(programs: Vec<&str>) -> Option<std::path::PathBuf> {
     None
 }
 
-fn compile_to_objectfile(from: &str, to: &str) -> anyhow::Result<()> {
-    let clang = find_on_path("llc").ok_or(anyhow::anyhow!("Llc not found on path"))?;
-    std::process::Command::new(clang)
-        .args([from, "-filetype=obj", "-o", to])
-        .spawn()?
-        .wait()?
-        .success()
-        .then_some(())
-        .ok_or(anyhow::anyhow!("Llc failed"))?;
-
-    Ok(())
-}
-
-fn compile_to_binary(from: &str, to: &str) -> anyhow::Result<()> {
+fn compile_to_binary(
+    from: &[&str],
+    to: &str,
+    libraries: &[String],
+    include_directories: &[String],
+) -> anyhow::Result<()> {
     let clang = find_any_on_path(vec!["clang", "gcc"])
         .ok_or(anyhow::anyhow!("Clang or gcc not found on path"))?;
     std::process::Command::new(clang)
-        .args([from, "-no-pie", "-o", to])
+        .args(
+            include_directories
+                .iter()
+                .map(|directory| format!("-I{directory}")),
+        )
+        .args(from)
+        .args(["-no-pie", "-o", to])
+        .args(libraries.iter().map(|library| format!("-l{library}")))
         .spawn()?
         .wait()?
         .success()
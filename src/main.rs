@@ -1,19 +1,87 @@
+use std::path::{Path, PathBuf};
+
 use anyhow::Context;
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use thiserror::Error;
+
+use viv_script::{
+    compile_file, json::JsonOutput, link, link_library, write_c_header, write_object, Compilation,
+    CompilerOptions, Diagnostic, Severity,
+};
+
+mod test_runner;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Emit {
+    /// Link everything into one executable
+    Exe,
+    /// Write one object file per input file
+    Obj,
+}
 
-use viv_script::{build, CompilerOptions};
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human readable output
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// An error that was already printed as JSON diagnostics
+#[derive(Error, Debug)]
+#[error("Compilation failed")]
+struct ReportedError;
 
 #[derive(Subcommand)]
 enum CompilerCommand {
-    /// Compile and run file
-    Run { input_file: String },
-    /// Compile file
-    Build {
+    /// Compile and run file, extra arguments are passed on to the program
+    Run {
         input_file: String,
-        output_file: String,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        arguments: Vec<String>,
+    },
+    /// Compile files, each .viv file is compiled on its own and .o and .c files are passed to the linker
+    Build {
+        #[arg(required = true)]
+        input_files: Vec<String>,
+
+        /// The executable to produce, or the directory to put object files in with `--emit obj`,
+        /// object files are named after the path of their source file, `src/x.viv` becomes `src_x.o`
+        #[arg(short = 'o', long)]
+        output: Option<String>,
+
+        #[arg(long, value_enum, default_value_t = Emit::Exe)]
+        emit: Emit,
+
+        /// Build a static library, or a shared library if the output ends with .so, and a C header for every .viv file
+        #[arg(long, conflicts_with = "emit")]
+        lib: bool,
+    },
+    /// Compile and run all test files in a directory, or the test blocks of a single file
+    Test {
+        #[arg(default_value = "integration_tests")]
+        path: PathBuf,
+
+        /// Only run tests whose file name contains this pattern
+        #[arg(long)]
+        filter: Option<String>,
+
+        /// Number of tests to run at the same time, defaults to the number of cpus
+        #[arg(short = 'j', long)]
+        jobs: Option<usize>,
+
+        /// Overwrite the expected output files with the actual output
+        #[arg(long)]
+        bless: bool,
+
+        /// Write a JUnit XML report to this file
+        #[arg(long)]
+        junit: Option<PathBuf>,
+
+        /// Write a JSON report to this file
+        #[arg(long)]
+        json: Option<PathBuf>,
     },
-    /// Compile and run all files in integration_tests/
-    Test,
 }
 
 #[derive(Args)]
@@ -34,9 +102,17 @@ struct DebugArguments {
     #[arg(short = 'i', long, global = true)]
     output_ir: bool,
 
-    /// Print the produced LLVM ir to stdout
-    #[arg(short = 'l', long, global = true)]
+    /// Print the produced LLVM ir to stdout, `-l` is taken by --link-lib like for a C compiler
+    #[arg(short = 'm', long, global = true)]
     output_llvm: bool,
+
+    /// Check the ir for inconsistencies before generating code, always done in debug builds
+    #[arg(long, global = true)]
+    verify_ir: bool,
+
+    /// Format of the tokens, ast, ir and errors, the LLVM ir is always text
+    #[arg(long, value_enum, global = true, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
 }
 
 #[derive(Parser)]
@@ -46,43 +122,217 @@ struct CompilerCli {
 
     #[command(flatten)]
     debug: DebugArguments,
+
+    /// Link with a library, like -l for a C compiler
+    #[arg(short = 'l', long = "link-lib", global = true)]
+    link_libraries: Vec<String>,
+
+    /// Report a lint as an error instead of a warning
+    #[arg(short = 'W', long = "deny", global = true, value_name = "LINT")]
+    denied_lints: Vec<String>,
+
+    /// Don't report a lint at all
+    #[arg(short = 'A', long = "allow", global = true, value_name = "LINT")]
+    allowed_lints: Vec<String>,
+
+    /// Report all warnings as errors, lints passed to --allow stay allowed
+    #[arg(long, global = true)]
+    deny_warnings: bool,
+
+    /// Report the lists and strings that are still allocated when main returns, tests fail if any are
+    #[arg(long, global = true)]
+    leak_check: bool,
 }
 
-fn run_test(file: &str) -> anyhow::Result<()> {
-    print!("Running test: {file} ... ");
-
-    let output_file = temp_file::empty();
-    build(
-        file,
-        output_file.path().to_str().unwrap(),
-        CompilerOptions {
-            dont_optimize: false,
-            output_tokens: false,
-            output_ast: false,
-            output_ir: false,
-            output_llvm: false,
-        },
-    )?;
-    let output = std::process::Command::new(output_file.path())
-        .spawn()?
-        .wait()?;
-
-    if !output.success() {
-        println!("ERROR");
-        Err(anyhow::anyhow!("Test failed: {}", file))?;
-    } else {
-        println!("OK");
+impl DebugArguments {
+    fn print(&self, compilation: &Compilation) {
+        if self.format == OutputFormat::Json {
+            self.print_json(compilation);
+        } else {
+            self.print_text(compilation);
+        }
+
+        if let (true, Some(llvm_ir)) = (self.output_llvm, &compilation.llvm_ir) {
+            print!("{llvm_ir}");
+        }
+    }
+
+    fn print_json(&self, compilation: &Compilation) {
+        if self.output_tokens {
+            for module in &compilation.tokens {
+                println!(
+                    "{}",
+                    JsonOutput::Tokens {
+                        file: &module.file,
+                        tokens: &module.tokens,
+                    }
+                    .to_line()
+                );
+            }
+        }
+
+        if let (true, Some(program)) = (self.output_ast, &compilation.ast) {
+            println!("{}", JsonOutput::Ast { program }.to_line());
+        }
+
+        if let (true, Some(module)) = (self.output_ir, &compilation.ir) {
+            println!("{}", JsonOutput::Ir { module }.to_line());
+        }
+
+        if !compilation.diagnostics.is_empty() {
+            println!(
+                "{}",
+                JsonOutput::Diagnostics {
+                    diagnostics: &compilation.diagnostics,
+                }
+                .to_line()
+            );
+        }
+    }
+
+    fn print_text(&self, compilation: &Compilation) {
+        for diagnostic in &compilation.diagnostics {
+            if diagnostic.severity == Severity::Warning {
+                eprintln!("warning: {diagnostic}");
+            }
+        }
+
+        if self.output_tokens {
+            for module in &compilation.tokens {
+                // we dont care about position info in this output
+                let token_types = module
+                    .tokens
+                    .iter()
+                    .map(|t| format!("{:?}", t._type))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+
+                println!("TOKENS: {token_types}");
+            }
+        }
+
+        if let (true, Some(ast)) = (self.output_ast, &compilation.ast) {
+            for module in &ast.0 {
+                println!("AST: {:#?}", module.module);
+            }
+        }
+
+        if let (true, Some(ir)) = (self.output_ir, &compilation.ir) {
+            print!("{ir}");
+        }
+    }
+}
+
+/// Compiles a file to an object file, printing the requested debug output along the way
+fn compile_to_object(
+    file: &str,
+    object_file: &str,
+    options: &CompilerOptions,
+    debug: &DebugArguments,
+) -> anyhow::Result<Compilation> {
+    let compilation = compile_file(file, options)?;
+    debug.print(&compilation);
+    if debug.format == OutputFormat::Json && compilation.first_error().is_some() {
+        Err(ReportedError)?;
+    }
+    write_object(&compilation, object_file).with_context(|| format!("Building {file}"))?;
+
+    Ok(compilation)
+}
+
+/// Compiles every .viv file to a temporary object file, .o and .c files are passed along as is.
+///
+/// The temporary object files are deleted when dropped, so they have to be kept around until linking is done.
+fn compile_objects(
+    input_files: &[String],
+    options: &CompilerOptions,
+    debug: &DebugArguments,
+) -> anyhow::Result<(Vec<temp_file::TempFile>, Vec<String>, Vec<Compilation>)> {
+    let mut temporary_objects = Vec::new();
+    let mut object_files = Vec::new();
+    let mut compilations = Vec::new();
+
+    for file in input_files {
+        if file.ends_with(".o") || file.ends_with(".c") {
+            object_files.push(file.clone());
+            continue;
+        }
+
+        let object_file = temp_file::empty();
+        let compilation =
+            compile_to_object(file, object_file.path().to_str().unwrap(), options, debug)?;
+        object_files.push(object_file.path().to_str().unwrap().to_string());
+        temporary_objects.push(object_file);
+        compilations.push(compilation);
+    }
+
+    Ok((temporary_objects, object_files, compilations))
+}
+
+fn build_library(
+    input_files: &[String],
+    output: Option<String>,
+    options: &CompilerOptions,
+    debug: &DebugArguments,
+) -> anyhow::Result<()> {
+    let output = output.unwrap_or_else(|| {
+        let stem = Path::new(&input_files[0]).file_stem().unwrap();
+        format!("lib{}.a", stem.to_string_lossy())
+    });
+    let directory = Path::new(&output).parent().unwrap_or(Path::new(""));
+
+    let (_temporary_objects, object_files, compilations) =
+        compile_objects(input_files, options, debug)?;
+    let object_files = object_files.iter().map(String::as_str).collect::<Vec<_>>();
+    link_library(&object_files, &output, options).context("Linking")?;
+
+    let viv_files = input_files.iter().filter(|file| file.ends_with(".viv"));
+    for (file, compilation) in viv_files.zip(&compilations) {
+        write_c_header(file, compilation, directory)?;
     }
 
     Ok(())
 }
 
-fn run_tests() -> anyhow::Result<()> {
-    for file in std::fs::read_dir("integration_tests")? {
-        let file = file?;
-        let file_name = file.file_name().into_string().unwrap();
-        if file_name.ends_with(".viv") {
-            run_test(file.path().to_str().unwrap())?;
+/// The name of the object file for a source file, built from its whole path so `a/x.viv` and `b/x.viv`
+/// become `a_x.o` and `b_x.o` instead of overwriting each other
+fn object_file_name(file: &str) -> String {
+    let path = Path::new(file).with_extension("o");
+    path.components()
+        .filter_map(|component| match component {
+            std::path::Component::Normal(part) => Some(part.to_string_lossy()),
+            std::path::Component::ParentDir => Some("..".into()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+fn build_files(
+    input_files: &[String],
+    output: Option<String>,
+    emit: Emit,
+    options: &CompilerOptions,
+    debug: &DebugArguments,
+) -> anyhow::Result<()> {
+    match emit {
+        Emit::Obj => {
+            let directory = PathBuf::from(output.unwrap_or_else(|| String::from(".")));
+            for file in input_files {
+                let object_file = directory.join(object_file_name(file));
+                compile_to_object(file, object_file.to_str().unwrap(), options, debug)?;
+            }
+        }
+        Emit::Exe => {
+            let (_temporary_objects, object_files, _) =
+                compile_objects(input_files, options, debug)?;
+            let object_files = object_files.iter().map(String::as_str).collect::<Vec<_>>();
+            link(
+                &object_files,
+                &output.unwrap_or_else(|| String::from("a.out")),
+                options,
+            )
+            .context("Linking")?;
         }
     }
 
@@ -91,37 +341,111 @@ fn run_tests() -> anyhow::Result<()> {
 
 fn main() -> anyhow::Result<()> {
     let arguments = CompilerCli::parse();
+    let format = arguments.debug.format;
+
+    match run(arguments) {
+        Err(error) if format == OutputFormat::Json => {
+            if error.downcast_ref::<ReportedError>().is_none() {
+                println!(
+                    "{}",
+                    JsonOutput::Diagnostics {
+                        diagnostics: &[Diagnostic::from_error(&error)],
+                    }
+                    .to_line()
+                );
+            }
+            std::process::exit(1);
+        }
+        result => result,
+    }
+}
+
+fn run(arguments: CompilerCli) -> anyhow::Result<()> {
     let compiler_options = CompilerOptions {
         dont_optimize: arguments.debug.dont_optimize,
-        output_tokens: arguments.debug.output_tokens,
-        output_ast: arguments.debug.output_ast,
-        output_ir: arguments.debug.output_ir,
-        output_llvm: arguments.debug.output_llvm,
+        verify_ir: arguments.debug.verify_ir,
+        test_mode: false,
+        link_libraries: arguments.link_libraries,
+        denied_lints: arguments.denied_lints,
+        allowed_lints: arguments.allowed_lints,
+        deny_warnings: arguments.deny_warnings,
+        leak_check: arguments.leak_check,
+        position_independent: matches!(arguments.command, CompilerCommand::Build { lib: true, .. }),
+        include_directories: Vec::new(),
     };
 
     match arguments.command {
-        CompilerCommand::Run { input_file } => {
+        CompilerCommand::Run {
+            input_file,
+            arguments: program_arguments,
+        } => {
+            let object_file = temp_file::empty();
             let output_file = temp_file::empty();
-            build(
+            let object_path = object_file.path().to_str().unwrap();
+            compile_to_object(
                 &input_file,
+                object_path,
+                &compiler_options,
+                &arguments.debug,
+            )
+            .context("Building input file")?;
+            link(
+                &[object_path],
                 output_file.path().to_str().unwrap(),
-                compiler_options,
+                &compiler_options,
             )
             .context("Building input file")?;
 
             let output = std::process::Command::new(output_file.path())
+                .args(program_arguments)
+                .stdin(std::process::Stdio::inherit())
                 .spawn()?
                 .wait()?;
 
             std::process::exit(output.code().unwrap_or(1));
         }
         CompilerCommand::Build {
-            input_file,
-            output_file,
+            input_files,
+            output,
+            emit,
+            lib,
         } => {
-            build(&input_file, &output_file, compiler_options).context("Building input file")?;
+            if lib {
+                build_library(&input_files, output, &compiler_options, &arguments.debug)?
+            } else {
+                build_files(
+                    &input_files,
+                    output,
+                    emit,
+                    &compiler_options,
+                    &arguments.debug,
+                )?
+            }
+        }
+        CompilerCommand::Test { path, .. } if path.is_file() => {
+            let exit_code = test_runner::run_test_blocks(&path, compiler_options)?;
+            std::process::exit(exit_code);
         }
-        CompilerCommand::Test => run_tests()?,
+        CompilerCommand::Test {
+            path,
+            filter,
+            jobs,
+            bless,
+            junit,
+            json,
+        } => test_runner::run_tests(test_runner::TestOptions {
+            directory: path,
+            filter,
+            jobs: jobs.unwrap_or_else(|| {
+                std::thread::available_parallelism()
+                    .map(|jobs| jobs.get())
+                    .unwrap_or(1)
+            }),
+            bless,
+            junit,
+            json,
+            leak_check: compiler_options.leak_check,
+        })?,
     }
 
     Ok(())
@@ -1,4 +1,23 @@
-use crate::IntType;
+use crate::{parsing::tokens::Suppression, IntType};
+
+/// Every module of a program, dependencies come before the modules importing them
+#[derive(Debug)]
+pub struct Program(pub Vec<ProgramModule>);
+
+#[derive(Debug)]
+pub struct ProgramModule {
+    /// Namespace of the module, the file name without extension for the root module and the path
+    /// relative to the root module joined by `::` for imported modules
+    pub name: String,
+    pub file: String,
+    /// Whether this is the file the compiler was invoked on
+    pub is_root: bool,
+    /// Namespaces of the modules imported by this module, their items can be used as `namespace::name`
+    pub imports: Vec<String>,
+    pub module: Module,
+    /// Lints allowed by `# viv:allow(...)` comments in the module
+    pub suppressions: Vec<Suppression>,
+}
 
 #[derive(Debug)]
 pub struct Module(pub Vec<ToplevelStatement>);
@@ -6,24 +25,116 @@ pub struct Module(pub Vec<ToplevelStatement>);
 #[derive(Debug)]
 pub enum ToplevelStatement {
     MainFunction(Vec<Statement>),
+    // test "name" { ... }
+    Test(String, Vec<Statement>),
+    // import "other.viv";
+    Import(String),
+    // extern fn puts(s: string) -> int;
+    ExternFunction(FunctionSignature),
+    // export fn add(a: int, b: int) -> int { ... }
+    ExportFunction(FunctionSignature, Vec<Statement>),
+    // const LIMIT: int = 100;
+    Const(GlobalDefinition),
+    // static mut counter: int = 0;
+    Static(GlobalDefinition),
+}
+
+#[derive(Debug)]
+pub struct GlobalDefinition {
+    pub name: String,
+    pub type_name: String,
+    pub value: Expression,
+    pub line: usize,
+}
+
+#[derive(Debug)]
+pub struct FunctionSignature {
+    pub name: String,
+    /// Parameter names and type names
+    pub parameters: Vec<(String, String)>,
+    pub return_type: Option<String>,
+}
+
+/// Where a statement came from, used for runtime error messages
+#[derive(Debug, Clone)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+    pub text: String,
 }
 
 #[derive(Debug)]
 pub enum Statement {
-    Print(Expression),
-    Assert(Expression, Option<String>),
-    // let x = 1;
-    Declaration(String, Expression),
-    // x = 1;
-    Assignment(String, Expression),
+    // print 1, x, "x = {x}";
+    Print(Vec<PrintArgument>),
+    Assert(Expression, Option<String>, SourceLocation),
+    // let x = 1; or let mut x: int = 1; with the type, whether it is mutable and the line it is on
+    Declaration(String, Option<String>, Expression, bool, usize),
+    // set x = 1; with the line it is on
+    Assignment(String, Expression, usize),
+    // set xs[0] = 1; with the line it is on
+    IndexAssignment(String, Expression, Expression, usize),
+    // set x += 1; with the line it is on
+    CompoundAssignment(String, BinaryOp, Expression, usize),
+    // return 1;
+    Return(Option<Expression>),
+    // exit(1); or any other expression whose value is discarded, with the line it is on
+    Expression(Expression, usize),
+    // for i in 0..10 { ... } or for x in xs { ... }
+    For(ForLoop),
+}
+
+#[derive(Debug)]
+pub struct ForLoop {
+    pub variable: String,
+    pub iterable: Iterable,
+    pub body: Vec<Statement>,
+    pub line: usize,
+}
+
+#[derive(Debug)]
+pub enum Iterable {
+    Range {
+        start: Expression,
+        end: Expression,
+        /// `..=` instead of `..`, the end is part of the range
+        inclusive: bool,
+        /// `step 2`, the loop counts up by one without it
+        step: Option<Expression>,
+    },
+    // the elements of a list, in order
+    List(Expression),
+}
+
+#[derive(Debug)]
+pub enum PrintArgument {
+    Expression(Expression),
+    Format(Vec<FormatPart>),
+}
+
+#[derive(Debug)]
+pub enum FormatPart {
+    Text(String),
+    // the {x} in "x = {x}"
+    Expression(Expression),
 }
 
 #[derive(Debug)]
 pub enum Expression {
     Literal(Literal),
     BinaryOp(Box<Expression>, BinaryOp, Box<Expression>),
-    Prefix(PrefixOp, Box<Expression>),
-    Comparison(Box<Expression>, Vec<(ComparisonOp, Expression)>),
+    // !x, with the line of the operator
+    Prefix(PrefixOp, Box<Expression>, usize),
+    // a < b <= c, with the line of the first operator
+    Comparison(Box<Expression>, Vec<(ComparisonOp, Expression)>, usize),
+    // read_int()
+    Call(String, Vec<Expression>),
+    // if c { a } else { b }, with the line of the if
+    If(Box<Expression>, Box<Expression>, Box<Expression>, usize),
+    // [1, 2, 3]
+    List(Vec<Expression>),
+    // xs[0]
+    Index(Box<Expression>, Box<Expression>),
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -56,5 +167,7 @@ pub enum ComparisonOp {
 pub enum Literal {
     Integer(IntType),
     Boolean(bool),
+    String(String),
+    Char(char),
     Variable(String),
 }
@@ -1,11 +1,16 @@
-use std::collections::VecDeque;
-use thiserror::Error;
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Component, Path, PathBuf},
+};
 
-use crate::CompilerOptions;
+use anyhow::Context;
+use thiserror::Error;
 
 pub mod ast;
 mod parser;
-mod tokens;
+pub mod tokens;
+
+pub use parser::ParsingError;
 
 #[derive(Error, Debug)]
 #[error("Stream ran out of elements")]
@@ -31,29 +36,211 @@ impl<T> StreamConsumer<T> {
     }
 }
 
-pub fn parse(code: &str, compiler_options: &CompilerOptions) -> anyhow::Result<ast::Module> {
+/// A single parsed file
+pub struct ParsedFile {
+    pub tokens: Vec<tokens::Token>,
+    pub suppressions: Vec<tokens::Suppression>,
+    pub module: ast::Module,
+}
+
+/// Parses a single file, returning its tokens and lint suppressions along with the ast
+pub fn parse(file_name: &str, code: &str) -> anyhow::Result<ParsedFile> {
     let tokenizer = tokens::Tokenizer::new(code);
-    let tokens = tokenizer.tokenize()?;
+    let (tokens, suppressions) = tokenizer.tokenize()?;
 
-    if compiler_options.output_tokens {
-        // we dont care about position info in this output
-        let token_types = tokens
-            .iter()
-            .map(|t| format!("{:?}", t._type))
-            // This is inefficient! itertools has a more efficient join method, but I dont want to add a dependency just for that
-            // You however can :D
-            .collect::<Vec<String>>()
-            .join(", ");
+    let parser = parser::Parser::new(tokens.clone(), code, file_name);
+    let module = parser.module()?;
+
+    Ok(ParsedFile {
+        tokens,
+        suppressions,
+        module,
+    })
+}
 
-        println!("TOKENS: {token_types}");
+/// The tokens of a single module
+#[derive(Debug)]
+pub struct ModuleTokens {
+    pub file: String,
+    pub tokens: Vec<tokens::Token>,
+}
+
+/// Context of an error in one file of a program, the diagnostic of the error is reported in that file
+#[derive(Debug)]
+pub struct FileContext {
+    /// What was being done with the file, like `Parsing`
+    pub action: &'static str,
+    pub file: String,
+}
+
+impl std::fmt::Display for FileContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {}", self.action, self.file)
     }
+}
+
+#[derive(Error, Debug)]
+#[error("Import cycle: {}", .0.join(" -> "))]
+struct ImportCycleError(Vec<String>);
 
-    let parser = parser::Parser::new(tokens);
-    let ast = parser.module()?;
+/// Loads a file and everything it imports, each file is only loaded once
+struct ModuleLoader<'a> {
+    /// Source of the root module, if it isn't read from disk
+    root_source: Option<&'a str>,
+    modules: Vec<ast::ProgramModule>,
+    tokens: Vec<ModuleTokens>,
+    /// Canonical path of every loaded module, mapped to its namespace
+    loaded: HashMap<PathBuf, String>,
+    /// Modules currently being loaded, used to detect cycles
+    stack: Vec<(PathBuf, String)>,
+    /// Directory of the root module, namespaces of imported modules are relative to it
+    root_directory: PathBuf,
+}
 
-    if compiler_options.output_ast {
-        println!("AST: {ast:#?}");
+/// The namespace of an imported module is its path relative to the root module without the extension,
+/// `modules/util.viv` becomes `modules::util` and `../util.viv` becomes `super::util`.
+///
+/// Functions, constants and statics of a module are referred to as `namespace::name` from the modules importing it,
+/// and tests are named `namespace::name`.
+fn namespace(root_directory: &Path, file_name: &Path) -> anyhow::Result<String> {
+    let relative = file_name.strip_prefix(root_directory).unwrap_or(file_name);
+    let mut parts: Vec<String> = Vec::new();
+    for component in relative.with_extension("").components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match parts.last() {
+                Some(last) if last != "super" => {
+                    parts.pop();
+                }
+                _ => parts.push("super".to_string()),
+            },
+            Component::Normal(part) => parts.push(
+                part.to_str()
+                    .ok_or(anyhow::anyhow!(
+                        "Invalid module file name {}",
+                        file_name.display()
+                    ))?
+                    .to_string(),
+            ),
+            Component::RootDir | Component::Prefix(_) => {}
+        }
     }
 
-    Ok(ast)
+    Ok(parts.join("::"))
+}
+
+impl<'a> ModuleLoader<'a> {
+    /// Loads a module unless it has already been loaded, returning its namespace
+    fn load(&mut self, file_name: &str) -> anyhow::Result<String> {
+        let is_root = self.stack.is_empty();
+        let canonical_path = match self.root_source {
+            // an in-memory root doesn't have to exist on disk
+            Some(_) if is_root => {
+                std::fs::canonicalize(file_name).unwrap_or_else(|_| PathBuf::from(file_name))
+            }
+            _ => std::fs::canonicalize(file_name)
+                .with_context(|| format!("Reading input file {file_name}"))?,
+        };
+
+        if let Some(start) = self
+            .stack
+            .iter()
+            .position(|(path, _)| path == &canonical_path)
+        {
+            let mut cycle = self.stack[start..]
+                .iter()
+                .map(|(_, file)| file.clone())
+                .collect::<Vec<_>>();
+            cycle.push(file_name.to_string());
+            Err(ImportCycleError(cycle))?;
+        }
+
+        if let Some(name) = self.loaded.get(&canonical_path) {
+            return Ok(name.clone());
+        }
+
+        let name = if is_root {
+            self.root_directory = Path::new(file_name)
+                .parent()
+                .unwrap_or(Path::new(""))
+                .to_path_buf();
+            Path::new(file_name)
+                .file_stem()
+                .and_then(|name| name.to_str())
+                .ok_or(anyhow::anyhow!("Invalid module file name {file_name}"))?
+                .to_string()
+        } else {
+            namespace(&self.root_directory, Path::new(file_name))?
+        };
+        // only possible when the same file is reached through a link
+        if let Some((other_path, _)) = self.loaded.iter().find(|(_, other)| *other == &name) {
+            Err(anyhow::anyhow!(
+                "Two different modules have the namespace {name}: {} and {file_name}",
+                other_path.display()
+            ))?;
+        }
+
+        let code = match self.root_source {
+            Some(source) if is_root => source.to_string(),
+            _ => std::fs::read_to_string(file_name)
+                .with_context(|| format!("Reading input file {file_name}"))?,
+        };
+        let ParsedFile {
+            tokens,
+            suppressions,
+            module,
+        } = parse(file_name, &code).with_context(|| FileContext {
+            action: "Parsing",
+            file: file_name.to_string(),
+        })?;
+
+        self.stack
+            .push((canonical_path.clone(), file_name.to_string()));
+
+        let directory = Path::new(file_name).parent().unwrap_or(Path::new(""));
+        let mut imports = Vec::new();
+        for statement in &module.0 {
+            if let ast::ToplevelStatement::Import(path) = statement {
+                let import_path = directory.join(path);
+                imports.push(self.load(import_path.to_str().unwrap())?);
+            }
+        }
+
+        self.stack.pop();
+        self.loaded.insert(canonical_path, name.clone());
+        self.tokens.push(ModuleTokens {
+            file: file_name.to_string(),
+            tokens,
+        });
+        self.modules.push(ast::ProgramModule {
+            name: name.clone(),
+            file: file_name.to_string(),
+            is_root,
+            imports,
+            module,
+            suppressions,
+        });
+
+        Ok(name)
+    }
+}
+
+/// Parses a file and all the modules it imports, resolving imports relative to the importing file.
+///
+/// When `source` is given it is used as the contents of `file_name` instead of reading it from disk.
+pub fn parse_program(
+    file_name: &str,
+    source: Option<&str>,
+) -> anyhow::Result<(ast::Program, Vec<ModuleTokens>)> {
+    let mut loader = ModuleLoader {
+        root_source: source,
+        modules: Vec::new(),
+        tokens: Vec::new(),
+        loaded: HashMap::new(),
+        stack: Vec::new(),
+        root_directory: PathBuf::new(),
+    };
+    loader.load(file_name)?;
+
+    Ok((ast::Program(loader.modules), loader.tokens))
 }
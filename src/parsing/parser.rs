@@ -4,7 +4,7 @@ use thiserror::Error;
 
 use crate::parsing::{
     ast,
-    tokens::{Token, TokenType},
+    tokens::{Token, TokenType, Tokenizer},
     StreamConsumer,
 };
 
@@ -59,22 +59,31 @@ lazy_static! {
     ];
 }
 
-pub struct Parser(StreamConsumer<Token>);
+pub struct Parser {
+    tokens: StreamConsumer<Token>,
+    source: Vec<char>,
+    file_name: String,
+}
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self(StreamConsumer::new(tokens.into_iter().collect()))
+    pub fn new(tokens: Vec<Token>, source: &str, file_name: &str) -> Self {
+        Self {
+            tokens: StreamConsumer::new(tokens.into_iter().collect()),
+            source: source.chars().collect(),
+            file_name: file_name.to_string(),
+        }
+    }
+
+    fn peek_token(&self) -> anyhow::Result<&Token> {
+        self.tokens.peek().context("Unexpected End of Tokens")
     }
 
     fn peek(&self) -> anyhow::Result<&TokenType> {
-        self.0
-            .peek()
-            .context("Unexpected End of Tokens")
-            .map(|t| &t._type)
+        self.peek_token().map(|t| &t._type)
     }
 
     fn advance(&mut self) -> anyhow::Result<Token> {
-        self.0.advance().context("Unexpected End of Tokens")
+        self.tokens.advance().context("Unexpected End of Tokens")
     }
 
     fn expect(&mut self, expected_token: TokenType) -> anyhow::Result<TokenType> {
@@ -93,46 +102,145 @@ impl Parser {
             TokenType::Integer(i) => Ok(ast::Literal::Integer(i)),
             TokenType::True => Ok(ast::Literal::Boolean(true)),
             TokenType::False => Ok(ast::Literal::Boolean(false)),
-            TokenType::Identifier(name) => Ok(ast::Literal::Variable(name)),
+            TokenType::String(string) => Ok(ast::Literal::String(string)),
+            TokenType::Char(value) => Ok(ast::Literal::Char(value)),
+            TokenType::Identifier(name) => Ok(ast::Literal::Variable(self.path(name)?)),
             _ => Err(error(token, "Literal".to_string()))?,
         }
     }
 
+    // util::LIMIT, the first identifier has already been consumed
+    fn path(&mut self, first: String) -> anyhow::Result<String> {
+        let mut path = first;
+        while self.peek()? == &TokenType::ColonColon {
+            self.tokens.void();
+            path += "::";
+            path += &self.identifier()?;
+        }
+
+        Ok(path)
+    }
+
+    // if c { a } else { b }, the else branch can be another if
+    fn if_expression(&mut self) -> anyhow::Result<ast::Expression> {
+        let line = self.advance()?.line;
+        let condition = self.expression()?;
+
+        self.expect(TokenType::CurlyOpen)?;
+        let then_value = self.expression()?;
+        self.expect(TokenType::CurlyClose)?;
+
+        self.expect(TokenType::Else)?;
+        let else_value = if self.peek()? == &TokenType::If {
+            self.if_expression()?
+        } else {
+            self.expect(TokenType::CurlyOpen)?;
+            let else_value = self.expression()?;
+            self.expect(TokenType::CurlyClose)?;
+            else_value
+        };
+
+        Ok(ast::Expression::If(
+            Box::new(condition),
+            Box::new(then_value),
+            Box::new(else_value),
+            line,
+        ))
+    }
+
+    // [1, 2, 3]
+    fn list(&mut self) -> anyhow::Result<ast::Expression> {
+        self.expect(TokenType::BracketOpen)?;
+
+        let mut elements = Vec::new();
+        while self.peek()? != &TokenType::BracketClose {
+            elements.push(self.expression()?);
+
+            if self.peek()? != &TokenType::Comma {
+                break;
+            }
+            self.tokens.void();
+        }
+        self.expect(TokenType::BracketClose)?;
+
+        Ok(ast::Expression::List(elements))
+    }
+
+    // a group followed by any number of indexes, like xs[0]
+    fn postfix(&mut self) -> anyhow::Result<ast::Expression> {
+        let mut expression = self.group()?;
+        while self.peek()? == &TokenType::BracketOpen {
+            self.tokens.void();
+            let index = self.expression()?;
+            self.expect(TokenType::BracketClose)?;
+            expression = ast::Expression::Index(Box::new(expression), Box::new(index));
+        }
+
+        Ok(expression)
+    }
+
     fn group(&mut self) -> anyhow::Result<ast::Expression> {
-        if self.peek()? == &TokenType::ParenOpen {
-            self.0.void();
+        if self.peek()? == &TokenType::If {
+            self.if_expression()
+        } else if self.peek()? == &TokenType::BracketOpen {
+            self.list()
+        } else if self.peek()? == &TokenType::ParenOpen {
+            self.tokens.void();
             let expression = self.expression()?;
             self.expect(TokenType::ParenClose)?;
             Ok(expression)
         } else {
-            Ok(ast::Expression::Literal(self.literal()?))
+            match self.literal()? {
+                ast::Literal::Variable(name) if self.peek()? == &TokenType::ParenOpen => {
+                    self.call(name)
+                }
+                literal => Ok(ast::Expression::Literal(literal)),
+            }
         }
     }
 
+    fn call(&mut self, name: String) -> anyhow::Result<ast::Expression> {
+        self.expect(TokenType::ParenOpen)?;
+
+        let mut arguments = Vec::new();
+        while self.peek()? != &TokenType::ParenClose {
+            arguments.push(self.expression()?);
+
+            if self.peek()? != &TokenType::Comma {
+                break;
+            }
+            self.tokens.void();
+        }
+        self.expect(TokenType::ParenClose)?;
+
+        Ok(ast::Expression::Call(name, arguments))
+    }
+
     fn expression_precedence(&mut self, precedence: usize) -> anyhow::Result<ast::Expression> {
         let level = match OPERATORS.get(precedence) {
             Some(level) => level,
-            None => return self.group(),
+            None => return self.postfix(),
         };
 
         match level {
             OperatorType::Prefix(token, op) => {
                 if self.peek()? == token {
-                    self.0.void();
+                    let line = self.advance()?.line;
                     let expression = self.expression_precedence(precedence)?;
-                    Ok(ast::Expression::Prefix(*op, Box::new(expression)))
+                    Ok(ast::Expression::Prefix(*op, Box::new(expression), line))
                 } else {
                     self.expression_precedence(precedence + 1)
                 }
             }
             OperatorType::Comparison(comparisons) => {
                 let left_side = self.expression_precedence(precedence + 1)?;
+                let line = self.peek_token()?.line;
                 let mut chains = Vec::new();
 
                 loop {
                     let token = self.peek()?;
                     if let Some((_, op)) = comparisons.iter().find(|(t, _)| t == token) {
-                        self.0.void();
+                        self.tokens.void();
                         chains.push((*op, self.expression_precedence(precedence + 1)?));
                     } else {
                         break;
@@ -142,7 +250,11 @@ impl Parser {
                 if chains.is_empty() {
                     Ok(left_side)
                 } else {
-                    Ok(ast::Expression::Comparison(Box::new(left_side), chains))
+                    Ok(ast::Expression::Comparison(
+                        Box::new(left_side),
+                        chains,
+                        line,
+                    ))
                 }
             }
             OperatorType::Binary(mappings) => {
@@ -150,7 +262,7 @@ impl Parser {
                 loop {
                     let token = self.peek()?;
                     if let Some((_, op)) = mappings.iter().find(|(t, _)| t == token) {
-                        self.0.void();
+                        self.tokens.void();
                         let right_side = self.expression_precedence(precedence + 1)?;
                         left_side = ast::Expression::BinaryOp(
                             Box::new(left_side),
@@ -171,19 +283,116 @@ impl Parser {
         self.expression_precedence(0)
     }
 
+    /// Splits a format string like "x = {x}" into text and the parsed interpolated expressions
+    fn format_string(&self, text: &str, line: usize) -> anyhow::Result<Vec<ast::FormatPart>> {
+        let mut parts = Vec::new();
+        let mut current_text = String::new();
+        let mut chars = text.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    current_text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    current_text.push('}');
+                }
+                '{' => {
+                    let mut inner = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => inner.push(c),
+                            None => Err(anyhow::anyhow!("Unclosed {{ in format string {text:?}"))?,
+                        }
+                    }
+
+                    if !current_text.is_empty() {
+                        parts.push(ast::FormatPart::Text(std::mem::take(&mut current_text)));
+                    }
+
+                    let (mut tokens, _) = Tokenizer::new(&inner)
+                        .tokenize()
+                        .context("Tokenizing format string")?;
+                    // strings can't span lines, so everything in them is on the line of the string
+                    for token in &mut tokens {
+                        token.line = line;
+                    }
+                    let mut parser = Parser::new(tokens, &inner, &self.file_name);
+                    let expression = parser.expression().context("Parsing format string")?;
+                    parser.expect(TokenType::Eof)?;
+
+                    parts.push(ast::FormatPart::Expression(expression));
+                }
+                '}' => Err(anyhow::anyhow!("Unmatched }} in format string {text:?}"))?,
+                c => current_text.push(c),
+            }
+        }
+
+        if !current_text.is_empty() {
+            parts.push(ast::FormatPart::Text(current_text));
+        }
+
+        Ok(parts)
+    }
+
+    fn print_argument(&mut self) -> anyhow::Result<ast::PrintArgument> {
+        if let TokenType::String(text) = self.peek()? {
+            let text = text.clone();
+            let line = self.advance()?.line;
+            Ok(ast::PrintArgument::Format(self.format_string(&text, line)?))
+        } else {
+            Ok(ast::PrintArgument::Expression(self.expression()?))
+        }
+    }
+
     fn statement(&mut self) -> anyhow::Result<ast::Statement> {
+        if !matches!(
+            self.peek()?,
+            TokenType::Print
+                | TokenType::Assert
+                | TokenType::Let
+                | TokenType::Set
+                | TokenType::Return
+                | TokenType::For
+        ) {
+            let line = self.peek_token()?.line;
+            let expression = self.expression()?;
+            self.expect(TokenType::SemiColon)?;
+            return Ok(ast::Statement::Expression(expression, line));
+        }
+
         let token = self.advance()?;
         match token._type {
             TokenType::Print => {
-                let result = ast::Statement::Print(self.expression()?);
+                let mut arguments = vec![self.print_argument()?];
+                while self.peek()? == &TokenType::Comma {
+                    self.tokens.void();
+                    arguments.push(self.print_argument()?);
+                }
+
                 self.expect(TokenType::SemiColon)?;
-                Ok(result)
+                Ok(ast::Statement::Print(arguments))
             }
             TokenType::Assert => {
+                let start = self.peek_token()?.start;
                 let expression = self.expression()?;
+                let end = self.peek_token()?.start;
+                let location = ast::SourceLocation {
+                    file: self.file_name.clone(),
+                    line: token.line,
+                    text: self.source[start..end]
+                        .iter()
+                        .collect::<String>()
+                        .trim_end()
+                        .to_string(),
+                };
+
                 let message = match self.peek()? {
                     TokenType::Comma => {
-                        self.0.void();
+                        self.tokens.void();
                         let should_be_string = self.advance()?;
                         match should_be_string._type {
                             TokenType::String(msg) => Some(msg),
@@ -195,33 +404,145 @@ impl Parser {
 
                 self.expect(TokenType::SemiColon)?;
 
-                Ok(ast::Statement::Assert(expression, message))
+                Ok(ast::Statement::Assert(expression, message, location))
             }
             TokenType::Let | TokenType::Set => {
+                let mutable = token._type == TokenType::Let && self.peek()? == &TokenType::Mut;
+                if mutable {
+                    self.tokens.void();
+                }
+
                 let identifier = self.advance()?;
+                // globals of imported modules can be assigned, but not declared
+                let identifier = match identifier._type {
+                    TokenType::Identifier(name) if token._type == TokenType::Set => Token {
+                        _type: TokenType::Identifier(self.path(name)?),
+                        ..identifier
+                    },
+                    _ => identifier,
+                };
                 match identifier._type {
-                    TokenType::Identifier(name) => {
+                    TokenType::Identifier(name)
+                        if token._type == TokenType::Set
+                            && self.peek()? == &TokenType::BracketOpen =>
+                    {
+                        self.tokens.void();
+                        let index = self.expression()?;
+                        self.expect(TokenType::BracketClose)?;
                         self.expect(TokenType::Eq)?;
                         let expression = self.expression()?;
                         self.expect(TokenType::SemiColon)?;
-                        Ok(match token._type {
-                            TokenType::Let => ast::Statement::Declaration(name, expression),
-                            TokenType::Set => ast::Statement::Assignment(name, expression),
-                            _ => unreachable!(),
+                        Ok(ast::Statement::IndexAssignment(
+                            name, index, expression, token.line,
+                        ))
+                    }
+                    TokenType::Identifier(name) if token._type == TokenType::Set => {
+                        let operator = self.advance()?;
+                        let op = match operator._type {
+                            TokenType::Eq => None,
+                            TokenType::PlusEq => Some(ast::BinaryOp::Plus),
+                            TokenType::MinusEq => Some(ast::BinaryOp::Minus),
+                            TokenType::StarEq => Some(ast::BinaryOp::Multiply),
+                            TokenType::SlashEq => Some(ast::BinaryOp::Divide),
+                            _ => Err(error(
+                                operator,
+                                "Eq, PlusEq, MinusEq, StarEq or SlashEq".to_string(),
+                            ))?,
+                        };
+                        let expression = self.expression()?;
+                        self.expect(TokenType::SemiColon)?;
+                        Ok(match op {
+                            None => ast::Statement::Assignment(name, expression, token.line),
+                            Some(op) => {
+                                ast::Statement::CompoundAssignment(name, op, expression, token.line)
+                            }
                         })
                     }
+                    TokenType::Identifier(name) => {
+                        let type_name = if self.peek()? == &TokenType::Colon {
+                            self.tokens.void();
+                            Some(self.type_name()?)
+                        } else {
+                            None
+                        };
+                        self.expect(TokenType::Eq)?;
+                        let expression = self.expression()?;
+                        self.expect(TokenType::SemiColon)?;
+                        Ok(ast::Statement::Declaration(
+                            name, type_name, expression, mutable, token.line,
+                        ))
+                    }
                     _ => Err(error(identifier, "Identifier".to_string()))?,
                 }
             }
-            _ => Err(error(token, "Statement".to_string()))?,
+            TokenType::Return => {
+                let expression = if self.peek()? == &TokenType::SemiColon {
+                    None
+                } else {
+                    Some(self.expression()?)
+                };
+                self.expect(TokenType::SemiColon)?;
+                Ok(ast::Statement::Return(expression))
+            }
+            TokenType::For => {
+                let variable = self.advance()?;
+                let variable = match variable._type {
+                    TokenType::Identifier(name) => name,
+                    _ => Err(error(variable, "Identifier".to_string()))?,
+                };
+                self.expect(TokenType::In)?;
+
+                let start = self.expression()?;
+                let inclusive = match self.peek()? {
+                    TokenType::DotDot => Some(false),
+                    TokenType::DotDotEq => Some(true),
+                    _ => None,
+                };
+                let iterable = match inclusive {
+                    Some(inclusive) => {
+                        self.tokens.void();
+                        let end = self.expression()?;
+
+                        // step isn't a keyword, variables can still be called step
+                        let step = if self.peek()? == &TokenType::Identifier("step".to_string()) {
+                            self.tokens.void();
+                            Some(self.expression()?)
+                        } else {
+                            None
+                        };
+
+                        ast::Iterable::Range {
+                            start,
+                            end,
+                            inclusive,
+                            step,
+                        }
+                    }
+                    None => ast::Iterable::List(start),
+                };
+
+                Ok(ast::Statement::For(ast::ForLoop {
+                    variable,
+                    iterable,
+                    body: self.block()?,
+                    line: token.line,
+                }))
+            }
+            _ => unreachable!(),
         }
     }
 
     // let x = 123; -> Declaration
+    // let xs: list<int> = []; -> Declaration with a type
+    // let mut x = 123; -> mutable Declaration
     // set x = 12313; -> Assignment
+    // set x += 1; -> CompoundAssignment
+    // set xs[0] = 1; -> IndexAssignment
     // x(); -> Expression
+    // 1 + 2; -> Expression, with a warning since the value isn't used
+    // for i in 0..10 step 2 { ... } -> For
 
-    fn main_function(&mut self) -> anyhow::Result<ast::ToplevelStatement> {
+    fn block(&mut self) -> anyhow::Result<Vec<ast::Statement>> {
         self.expect(TokenType::CurlyOpen)?;
 
         let mut statements = Vec::new();
@@ -230,12 +551,125 @@ impl Parser {
         }
         self.expect(TokenType::CurlyClose)?;
 
-        Ok(ast::ToplevelStatement::MainFunction(statements))
+        Ok(statements)
+    }
+
+    fn main_function(&mut self) -> anyhow::Result<ast::ToplevelStatement> {
+        Ok(ast::ToplevelStatement::MainFunction(self.block()?))
+    }
+
+    fn test(&mut self) -> anyhow::Result<ast::ToplevelStatement> {
+        let name = self.advance()?;
+        let name = match name._type {
+            TokenType::String(name) => name,
+            _ => Err(error(name, "String".to_string()))?,
+        };
+
+        Ok(ast::ToplevelStatement::Test(name, self.block()?))
+    }
+
+    fn identifier(&mut self) -> anyhow::Result<String> {
+        let token = self.advance()?;
+        match token._type {
+            TokenType::Identifier(name) => Ok(name),
+            _ => Err(error(token, "Identifier".to_string()))?,
+        }
+    }
+
+    // int, or list<int> for a list
+    fn type_name(&mut self) -> anyhow::Result<String> {
+        let name = self.identifier()?;
+        if name == "list" && self.peek()? == &TokenType::Lt {
+            self.tokens.void();
+            let element = self.type_name()?;
+            self.expect(TokenType::Gt)?;
+            return Ok(format!("list<{element}>"));
+        }
+
+        Ok(name)
+    }
+
+    fn function_signature(&mut self) -> anyhow::Result<ast::FunctionSignature> {
+        self.expect(TokenType::Fn)?;
+        let name = self.identifier()?;
+
+        self.expect(TokenType::ParenOpen)?;
+        let mut parameters = Vec::new();
+        while self.peek()? != &TokenType::ParenClose {
+            let parameter_name = self.identifier()?;
+            self.expect(TokenType::Colon)?;
+            parameters.push((parameter_name, self.type_name()?));
+
+            if self.peek()? != &TokenType::Comma {
+                break;
+            }
+            self.tokens.void();
+        }
+        self.expect(TokenType::ParenClose)?;
+
+        let return_type = if self.peek()? == &TokenType::Arrow {
+            self.tokens.void();
+            Some(self.type_name()?)
+        } else {
+            None
+        };
+
+        Ok(ast::FunctionSignature {
+            name,
+            parameters,
+            return_type,
+        })
+    }
+
+    // LIMIT: int = 100; after const or static mut
+    fn global_definition(&mut self) -> anyhow::Result<ast::GlobalDefinition> {
+        let line = self.peek_token()?.line;
+        let name = self.identifier()?;
+        self.expect(TokenType::Colon)?;
+        let type_name = self.type_name()?;
+        self.expect(TokenType::Eq)?;
+        let value = self.expression()?;
+        self.expect(TokenType::SemiColon)?;
+
+        Ok(ast::GlobalDefinition {
+            name,
+            type_name,
+            value,
+            line,
+        })
     }
 
     fn top_level_statement(&mut self) -> anyhow::Result<ast::ToplevelStatement> {
         match self.advance()?._type {
             TokenType::Dollar => self.main_function(),
+            TokenType::Test => self.test(),
+            TokenType::Import => {
+                let path = self.advance()?;
+                let path = match path._type {
+                    TokenType::String(path) => path,
+                    _ => Err(error(path, "String".to_string()))?,
+                };
+                self.expect(TokenType::SemiColon)?;
+
+                Ok(ast::ToplevelStatement::Import(path))
+            }
+            TokenType::Extern => {
+                let signature = self.function_signature()?;
+                self.expect(TokenType::SemiColon)?;
+
+                Ok(ast::ToplevelStatement::ExternFunction(signature))
+            }
+            TokenType::Export => {
+                let signature = self.function_signature()?;
+                let body = self.block()?;
+
+                Ok(ast::ToplevelStatement::ExportFunction(signature, body))
+            }
+            TokenType::Const => Ok(ast::ToplevelStatement::Const(self.global_definition()?)),
+            TokenType::Static => {
+                self.expect(TokenType::Mut)?;
+                Ok(ast::ToplevelStatement::Static(self.global_definition()?))
+            }
             _ => Err(error(self.advance()?, "Top Level Statement".to_string()))?,
         }
     }
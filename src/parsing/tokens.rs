@@ -1,14 +1,15 @@
 use crate::{parsing::StreamConsumer, IntType};
-use anyhow::Context;
 use thiserror::Error;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TokenType {
     Integer(IntType),
     Identifier(String),
     String(String),
+    Char(char),
     Print,
     Let,
+    Mut,
     Set,
     SemiColon,
     Dollar,
@@ -16,10 +17,29 @@ pub enum TokenType {
     CurlyClose,
     ParenOpen,
     ParenClose,
+    BracketOpen,
+    BracketClose,
     Comma,
     True,
     False,
     Assert,
+    Test,
+    Return,
+    Import,
+    Extern,
+    Export,
+    Fn,
+    If,
+    Else,
+    For,
+    In,
+    Const,
+    Static,
+    Colon,
+    ColonColon,
+    Arrow,
+    DotDot,
+    DotDotEq,
     Eq,
     Bang,
     EqEq,
@@ -32,6 +52,10 @@ pub enum TokenType {
     Plus,
     Star,
     Slash,
+    MinusEq,
+    PlusEq,
+    StarEq,
+    SlashEq,
     AndAnd,
     OrOr,
     Or,
@@ -39,10 +63,21 @@ pub enum TokenType {
     Eof,
 }
 
+#[derive(Debug, Clone)]
 pub struct Token {
     pub _type: TokenType,
+    /// Line and char of the first char of the token, both start at 1
     pub line: usize,
     pub char: usize,
+    /// Char offset of the start of the token in the source
+    pub start: usize,
+}
+
+/// A `# viv:allow(lint)` comment, allowing the lint on its own line and the line after it
+#[derive(Debug, Clone)]
+pub struct Suppression {
+    pub lint: String,
+    pub line: usize,
 }
 
 #[derive(Error, Debug)]
@@ -57,6 +92,10 @@ pub struct Tokenizer {
     code: StreamConsumer<char>,
     line: usize,
     char: usize,
+    position: usize,
+    /// Offset, line and char where the current token starts
+    token_start: (usize, usize, usize),
+    suppressions: Vec<Suppression>,
 }
 
 impl Tokenizer {
@@ -65,10 +104,13 @@ impl Tokenizer {
             code: StreamConsumer::new(code.chars().collect()),
             line: 1,
             char: 1,
+            position: 0,
+            token_start: (0, 1, 1),
+            suppressions: Vec::new(),
         }
     }
 
-    fn error(&self, msg: String) -> Result<(), TokenizerError> {
+    fn error<T>(&self, msg: String) -> Result<T, TokenizerError> {
         Err(TokenizerError {
             line: self.line,
             char: self.char,
@@ -76,16 +118,23 @@ impl Tokenizer {
         })
     }
 
+    fn start_token(&mut self) {
+        self.token_start = (self.position, self.line, self.char);
+    }
+
     fn token(&self, _type: TokenType) -> Token {
+        let (start, line, char) = self.token_start;
         Token {
             _type,
-            line: self.line,
-            char: self.char,
+            line,
+            char,
+            start,
         }
     }
 
     fn advance(&mut self) -> Option<char> {
         let c = self.code.advance().ok()?;
+        self.position += 1;
         if c == '\n' {
             self.line += 1;
             self.char = 1;
@@ -115,7 +164,7 @@ impl Tokenizer {
     fn consume_identifier(&mut self) -> Token {
         let mut identifier = String::new();
         while let Ok(c) = self.code.peek() {
-            if c.is_ascii_alphanumeric() {
+            if c.is_ascii_alphanumeric() || c == &'_' {
                 identifier.push(*c);
                 self.void();
             } else {
@@ -129,7 +178,20 @@ impl Tokenizer {
             "false" => TokenType::False,
             "assert" => TokenType::Assert,
             "let" => TokenType::Let,
+            "mut" => TokenType::Mut,
             "set" => TokenType::Set,
+            "test" => TokenType::Test,
+            "return" => TokenType::Return,
+            "import" => TokenType::Import,
+            "extern" => TokenType::Extern,
+            "export" => TokenType::Export,
+            "fn" => TokenType::Fn,
+            "if" => TokenType::If,
+            "else" => TokenType::Else,
+            "for" => TokenType::For,
+            "in" => TokenType::In,
+            "const" => TokenType::Const,
+            "static" => TokenType::Static,
             _ => TokenType::Identifier(identifier),
         })
     }
@@ -144,15 +206,37 @@ impl Tokenizer {
         }
     }
 
-    fn consume_comment(&mut self) {
+    fn consume_comment(&mut self) -> Result<(), TokenizerError> {
+        let (line, char) = (self.line, self.char);
+        let mut comment = String::new();
         while let Ok(c) = self.code.peek() {
             if c == &'\n' {
                 self.void();
                 break;
             } else {
+                comment.push(*c);
                 self.void();
             }
         }
+
+        // # viv:allow(constant_condition, redundant_not)
+        if let Some(lints) = comment[1..].trim().strip_prefix("viv:allow(") {
+            let Some(lints) = lints.strip_suffix(')') else {
+                return Err(TokenizerError {
+                    line,
+                    char,
+                    message: "Unclosed viv:allow comment".to_string(),
+                });
+            };
+            for lint in lints.split(',') {
+                self.suppressions.push(Suppression {
+                    lint: lint.trim().to_string(),
+                    line,
+                });
+            }
+        }
+
+        Ok(())
     }
 
     fn consume_string(&mut self) -> Result<Token, TokenizerError> {
@@ -175,6 +259,61 @@ impl Tokenizer {
         Ok(self.token(TokenType::String(string)))
     }
 
+    /// `'a'`, `'\n'` or `'\u{1F600}'`
+    fn consume_char(&mut self) -> Result<Token, TokenizerError> {
+        self.void();
+        let value = match self.advance() {
+            Some('\\') => self.consume_escape()?,
+            Some('\'') => return self.error("Empty char literal".to_string()),
+            Some('\n') => return self.error("Unexpected newline in char literal".to_string()),
+            Some(c) => c,
+            None => return self.error("Unexpected end of file".to_string()),
+        };
+        match self.advance() {
+            Some('\'') => {}
+            Some(_) => {
+                return self.error("Char literal must contain exactly one character".to_string())
+            }
+            None => return self.error("Unexpected end of file".to_string()),
+        }
+        Ok(self.token(TokenType::Char(value)))
+    }
+
+    /// The char an escape in a char literal stands for, the backslash is already consumed
+    fn consume_escape(&mut self) -> Result<char, TokenizerError> {
+        Ok(match self.advance() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('\'') => '\'',
+            Some('"') => '"',
+            Some('u') => {
+                if self.advance() != Some('{') {
+                    return self.error("Expected { after \\u".to_string());
+                }
+                let mut digits = String::new();
+                loop {
+                    match self.advance() {
+                        Some('}') => break,
+                        Some(c) if c.is_ascii_hexdigit() && digits.len() < 6 => digits.push(c),
+                        _ => return self.error("Invalid unicode escape".to_string()),
+                    }
+                }
+                match u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                {
+                    Some(value) => value,
+                    None => return self.error(format!("\\u{{{digits}}} is not a valid char")),
+                }
+            }
+            Some(c) => return self.error(format!("Unknown escape: \\{c}")),
+            None => return self.error("Unexpected end of file".to_string()),
+        })
+    }
+
     fn consume_double_symbol(
         &mut self,
         next_char: char,
@@ -194,14 +333,17 @@ impl Tokenizer {
         }
     }
 
-    pub fn tokenize(mut self) -> Result<Vec<Token>, TokenizerError> {
+    /// Returns the tokens along with the lints allowed by comments
+    pub fn tokenize(mut self) -> Result<(Vec<Token>, Vec<Suppression>), TokenizerError> {
         let mut tokens = Vec::new();
         while let Ok(&c) = self.code.peek() {
+            self.start_token();
             match c {
-                '#' => self.consume_comment(),
+                '#' => self.consume_comment()?,
                 '"' => tokens.push(self.consume_string()?),
+                '\'' => tokens.push(self.consume_char()?),
                 c if c.is_ascii_digit() => tokens.push(self.consume_number()),
-                c if c.is_ascii_alphabetic() => tokens.push(self.consume_identifier()),
+                c if c.is_ascii_alphabetic() || c == '_' => tokens.push(self.consume_identifier()),
                 c if c.is_ascii_whitespace() => self.consume_whitespace(),
                 '=' => tokens.push(self.consume_double_symbol('=', TokenType::Eq, TokenType::EqEq)),
                 '!' => {
@@ -213,6 +355,47 @@ impl Tokenizer {
                     tokens.push(self.consume_double_symbol('&', TokenType::And, TokenType::AndAnd))
                 }
                 '|' => tokens.push(self.consume_double_symbol('|', TokenType::Or, TokenType::OrOr)),
+                ':' => tokens.push(self.consume_double_symbol(
+                    ':',
+                    TokenType::Colon,
+                    TokenType::ColonColon,
+                )),
+                '-' => {
+                    self.void();
+                    tokens.push(match self.code.peek() {
+                        Ok('>') => {
+                            self.void();
+                            self.token(TokenType::Arrow)
+                        }
+                        Ok('=') => {
+                            self.void();
+                            self.token(TokenType::MinusEq)
+                        }
+                        _ => self.token(TokenType::Minus),
+                    })
+                }
+                '.' => {
+                    self.void();
+                    if !matches!(self.code.peek(), Ok('.')) {
+                        return self.error("Unexpected character: .".to_string());
+                    }
+                    tokens.push(self.consume_double_symbol(
+                        '=',
+                        TokenType::DotDot,
+                        TokenType::DotDotEq,
+                    ))
+                }
+                '+' => {
+                    tokens.push(self.consume_double_symbol('=', TokenType::Plus, TokenType::PlusEq))
+                }
+                '*' => {
+                    tokens.push(self.consume_double_symbol('=', TokenType::Star, TokenType::StarEq))
+                }
+                '/' => tokens.push(self.consume_double_symbol(
+                    '=',
+                    TokenType::Slash,
+                    TokenType::SlashEq,
+                )),
                 _ => {
                     self.void();
 
@@ -224,16 +407,15 @@ impl Tokenizer {
                         '}' => tokens.push(self.token(TokenType::CurlyClose)),
                         '(' => tokens.push(self.token(TokenType::ParenOpen)),
                         ')' => tokens.push(self.token(TokenType::ParenClose)),
-                        '-' => tokens.push(self.token(TokenType::Minus)),
-                        '+' => tokens.push(self.token(TokenType::Plus)),
-                        '*' => tokens.push(self.token(TokenType::Star)),
-                        '/' => tokens.push(self.token(TokenType::Slash)),
+                        '[' => tokens.push(self.token(TokenType::BracketOpen)),
+                        ']' => tokens.push(self.token(TokenType::BracketClose)),
                         _ => self.error(format!("Unexpected character: {}", c))?,
                     }
                 }
             }
         }
+        self.start_token();
         tokens.push(self.token(TokenType::Eof));
-        Ok(tokens)
+        Ok((tokens, self.suppressions))
     }
 }
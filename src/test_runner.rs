@@ -0,0 +1,615 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use serde::Serialize;
+use viv_script::{
+    build, compile_to_object, link, link_library, write_c_header, Compilation, CompilerOptions,
+    Severity,
+};
+
+/// What a test file expects to happen when it is compiled and run.
+///
+/// Expectations are read from `# expect-exit: <code>` and `# expect-error: <message>` comments
+/// in the test file, and the expected stdout from a sibling `.out` file.
+/// A `# test-mode` comment compiles the test blocks of the file instead of its main function.
+/// The program is given the arguments of an `# args: <arguments>` comment and a sibling `.in` file as stdin.
+/// Files named in `# link: <file>` comments are compiled separately and linked into the program,
+/// together with the libraries of `# link-lib: <library>` comments.
+/// A `# lib: <library>` comment builds the .viv files into a static or shared library like `build --lib`,
+/// the C files are linked against it and can include the generated headers.
+/// Every `# expect-warning: <message>` comment has to match a warning, lints can be turned into errors
+/// with `# deny: <lint>` and silenced with `# allow: <lint>`.
+/// A `# leak-check` comment fails the test if lists or strings are still allocated when main returns.
+struct Expectations {
+    link: Vec<PathBuf>,
+    link_libraries: Vec<String>,
+    library: Option<String>,
+    denied_lints: Vec<String>,
+    allowed_lints: Vec<String>,
+    deny_warnings: bool,
+    warnings: Vec<String>,
+    test_mode: bool,
+    leak_check: bool,
+    arguments: Vec<String>,
+    stdin: Option<PathBuf>,
+    exit_code: i32,
+    compile_error: Option<String>,
+    stdout: Option<String>,
+}
+
+impl Expectations {
+    fn read(file: &Path) -> anyhow::Result<Self> {
+        let code = std::fs::read_to_string(file).context("Reading test file")?;
+
+        let mut expectations = Self {
+            link: Vec::new(),
+            link_libraries: Vec::new(),
+            library: None,
+            denied_lints: Vec::new(),
+            allowed_lints: Vec::new(),
+            deny_warnings: false,
+            warnings: Vec::new(),
+            test_mode: false,
+            leak_check: false,
+            arguments: Vec::new(),
+            stdin: None,
+            exit_code: 0,
+            compile_error: None,
+            stdout: None,
+        };
+
+        for line in code.lines() {
+            let directive = match line.trim().strip_prefix('#') {
+                Some(directive) => directive.trim(),
+                None => continue,
+            };
+
+            if let Some(exit_code) = directive.strip_prefix("expect-exit:") {
+                expectations.exit_code = exit_code
+                    .trim()
+                    .parse()
+                    .context("Parsing expect-exit directive")?;
+            } else if let Some(message) = directive.strip_prefix("expect-error:") {
+                expectations.compile_error = Some(message.trim().to_string());
+            } else if let Some(message) = directive.strip_prefix("expect-warning:") {
+                expectations.warnings.push(message.trim().to_string());
+            } else if let Some(lint) = directive.strip_prefix("deny:") {
+                expectations.denied_lints.push(lint.trim().to_string());
+            } else if let Some(lint) = directive.strip_prefix("allow:") {
+                expectations.allowed_lints.push(lint.trim().to_string());
+            } else if directive == "deny-warnings" {
+                expectations.deny_warnings = true;
+            } else if let Some(arguments) = directive.strip_prefix("args:") {
+                expectations.arguments = arguments.split_whitespace().map(String::from).collect();
+            } else if let Some(library) = directive.strip_prefix("link-lib:") {
+                expectations.link_libraries.push(library.trim().to_string());
+            } else if let Some(link) = directive.strip_prefix("link:") {
+                let directory = file.parent().unwrap_or(Path::new(""));
+                expectations.link.push(directory.join(link.trim()));
+            } else if let Some(library) = directive.strip_prefix("lib:") {
+                expectations.library = Some(library.trim().to_string());
+            } else if directive == "test-mode" {
+                expectations.test_mode = true;
+            } else if directive == "leak-check" {
+                expectations.leak_check = true;
+            }
+        }
+
+        let in_file = file.with_extension("in");
+        if in_file.is_file() {
+            expectations.stdin = Some(in_file);
+        }
+
+        let out_file = file.with_extension("out");
+        if out_file.is_file() {
+            expectations.stdout =
+                Some(std::fs::read_to_string(out_file).context("Reading expected output")?);
+        }
+
+        Ok(expectations)
+    }
+}
+
+enum TestResult {
+    Passed,
+    Failed(String),
+}
+
+/// Exit code of the program, signals are reported the same way a shell would (128 + signal)
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
+
+fn diff_lines(expected: &str, actual: &str) -> String {
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+
+    let mut diff = String::new();
+    for line in 0..expected.len().max(actual.len()) {
+        match (expected.get(line), actual.get(line)) {
+            (Some(expected), Some(actual)) if expected == actual => {
+                diff.push_str(&format!("   {expected}\n"))
+            }
+            (expected, actual) => {
+                if let Some(expected) = expected {
+                    diff.push_str(&format!(" - {expected}\n"));
+                }
+                if let Some(actual) = actual {
+                    diff.push_str(&format!(" + {actual}\n"));
+                }
+            }
+        }
+    }
+
+    diff
+}
+
+/// The warnings of a compilation, formatted like the compiler prints them
+fn warnings(compilation: &Compilation) -> Vec<String> {
+    compilation
+        .diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Warning)
+        .map(|diagnostic| diagnostic.to_string())
+        .collect()
+}
+
+/// A temporary directory holding the library of a `# lib:` test and its headers, removed when dropped
+struct LibraryDirectory(PathBuf);
+
+impl LibraryDirectory {
+    fn new(file: &Path) -> anyhow::Result<Self> {
+        let name = format!(
+            "viv-test-{}-{}",
+            std::process::id(),
+            file.file_stem().unwrap().to_string_lossy()
+        );
+        let path = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&path).context("Creating library directory")?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for LibraryDirectory {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Builds the test file and the files it links with, returning the warnings of all of them.
+///
+/// Linked files are compiled outside of test mode, only the test file brings a test harness and with it `main`.
+/// When a library is given the .viv files are bundled into it and their headers are written next to it.
+fn build_linked(
+    file: &Path,
+    link_files: &[PathBuf],
+    library: Option<&Path>,
+    output_file: &Path,
+    options: &CompilerOptions,
+) -> anyhow::Result<Vec<String>> {
+    let link_options = CompilerOptions {
+        test_mode: false,
+        ..options.clone()
+    };
+    let mut object_files = Vec::new();
+    let mut c_files = Vec::new();
+    let mut all_warnings = Vec::new();
+    for source in std::iter::once(file).chain(link_files.iter().map(PathBuf::as_path)) {
+        if source.extension().is_some_and(|extension| extension == "c") {
+            c_files.push(source.to_str().unwrap());
+            continue;
+        }
+
+        let object_file = temp_file::empty();
+        let compilation = compile_to_object(
+            source.to_str().unwrap(),
+            object_file.path().to_str().unwrap(),
+            if source == file {
+                options
+            } else {
+                &link_options
+            },
+        )?;
+        all_warnings.extend(warnings(&compilation));
+        if let Some(library) = library {
+            write_c_header(
+                source.to_str().unwrap(),
+                &compilation,
+                library.parent().unwrap(),
+            )?;
+        }
+        object_files.push(object_file);
+    }
+
+    let object_paths = object_files
+        .iter()
+        .map(|object_file| object_file.path().to_str().unwrap())
+        .collect::<Vec<_>>();
+    let linker_inputs = match library {
+        Some(library) => {
+            let library = library.to_str().unwrap();
+            link_library(&object_paths, library, options)?;
+            // the linker only takes what the files before a static library need from it
+            c_files.into_iter().chain([library]).collect()
+        }
+        None => object_paths.into_iter().chain(c_files).collect::<Vec<_>>(),
+    };
+    link(&linker_inputs, output_file.to_str().unwrap(), options)?;
+
+    Ok(all_warnings)
+}
+
+fn run_test(file: &Path, bless: bool, leak_check: bool) -> anyhow::Result<TestResult> {
+    let expectations = Expectations::read(file)?;
+    let leak_check = leak_check || expectations.leak_check;
+
+    let output_file = temp_file::empty();
+    let library_directory = match &expectations.library {
+        Some(_) => Some(LibraryDirectory::new(file)?),
+        None => None,
+    };
+    let library = library_directory
+        .as_ref()
+        .zip(expectations.library.as_ref())
+        .map(|(directory, library)| directory.0.join(library));
+    let options = CompilerOptions {
+        test_mode: expectations.test_mode,
+        position_independent: library.as_ref().is_some_and(|library| {
+            library
+                .extension()
+                .is_some_and(|extension| extension == "so")
+        }),
+        include_directories: library_directory
+            .iter()
+            .map(|directory| directory.0.to_string_lossy().to_string())
+            .collect(),
+        link_libraries: expectations.link_libraries.clone(),
+        verify_ir: true,
+        denied_lints: expectations.denied_lints.clone(),
+        allowed_lints: expectations.allowed_lints.clone(),
+        deny_warnings: expectations.deny_warnings,
+        leak_check,
+        ..Default::default()
+    };
+    let build_result = if expectations.link.is_empty() && library.is_none() {
+        build(
+            file.to_str().unwrap(),
+            output_file.path().to_str().unwrap(),
+            options,
+        )
+        .map(|compilation| warnings(&compilation))
+    } else {
+        build_linked(
+            file,
+            &expectations.link,
+            library.as_deref(),
+            output_file.path(),
+            &options,
+        )
+    };
+
+    let actual_warnings = match (build_result, &expectations.compile_error) {
+        (Ok(warnings), None) => warnings,
+        (Ok(_), Some(expected)) => {
+            return Ok(TestResult::Failed(format!(
+                "expected compile error containing {expected:?}, but it compiled"
+            )))
+        }
+        (Err(err), None) => return Ok(TestResult::Failed(format!("compile error: {err:#}"))),
+        (Err(err), Some(expected)) => {
+            let message = format!("{err:#}");
+            return Ok(if message.contains(expected.as_str()) {
+                TestResult::Passed
+            } else {
+                TestResult::Failed(format!(
+                    "expected compile error containing {expected:?}, got: {message}"
+                ))
+            });
+        }
+    };
+
+    for expected in &expectations.warnings {
+        if !actual_warnings
+            .iter()
+            .any(|warning| warning.contains(expected.as_str()))
+        {
+            return Ok(TestResult::Failed(format!(
+                "expected warning containing {expected:?}, got: {actual_warnings:?}"
+            )));
+        }
+    }
+
+    let stdin = match &expectations.stdin {
+        Some(in_file) => std::fs::File::open(in_file)
+            .context("Opening test input")?
+            .into(),
+        None => std::process::Stdio::null(),
+    };
+    let output = std::process::Command::new(output_file.path())
+        .args(&expectations.arguments)
+        .stdin(stdin)
+        .output()?;
+    let actual_exit_code = exit_code(output.status);
+    let actual_stdout = String::from_utf8_lossy(&output.stdout);
+
+    if bless && (!actual_stdout.is_empty() || expectations.stdout.is_some()) {
+        std::fs::write(file.with_extension("out"), actual_stdout.as_bytes())
+            .context("Writing expected output")?;
+    } else if let Some(expected_stdout) = &expectations.stdout {
+        if expected_stdout != &actual_stdout {
+            return Ok(TestResult::Failed(format!(
+                "stdout differs from expected:\n{}",
+                diff_lines(expected_stdout, &actual_stdout)
+            )));
+        }
+    }
+
+    if actual_exit_code != expectations.exit_code {
+        return Ok(TestResult::Failed(format!(
+            "expected exit code {}, got {actual_exit_code}",
+            expectations.exit_code
+        )));
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if let Some(leak) = stderr.lines().find(|line| line.starts_with("Leak check:")) {
+        return Ok(TestResult::Failed(leak.to_string()));
+    }
+
+    Ok(TestResult::Passed)
+}
+
+/// Compile the `test` blocks of a single file into a test harness and run it, returning its exit code.
+pub fn run_test_blocks(file: &Path, options: CompilerOptions) -> anyhow::Result<i32> {
+    let output_file = temp_file::empty();
+    build(
+        file.to_str().unwrap(),
+        output_file.path().to_str().unwrap(),
+        CompilerOptions {
+            test_mode: true,
+            ..options
+        },
+    )
+    .context("Building test file")?;
+
+    let status = std::process::Command::new(output_file.path())
+        .spawn()?
+        .wait()?;
+
+    Ok(exit_code(status))
+}
+
+/// How `viv test` should find, run and report tests
+pub struct TestOptions {
+    pub directory: PathBuf,
+    pub filter: Option<String>,
+    pub jobs: usize,
+    pub bless: bool,
+    pub junit: Option<PathBuf>,
+    pub json: Option<PathBuf>,
+    /// Fail every test that leaves lists allocated when main returns
+    pub leak_check: bool,
+}
+
+struct TestReport {
+    path: PathBuf,
+    result: TestResult,
+    duration: Duration,
+}
+
+fn collect_tests(options: &TestOptions) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for file in std::fs::read_dir(&options.directory).context("Reading test directory")? {
+        let file = file?;
+        let file_name = file.file_name().into_string().unwrap();
+        if !file_name.ends_with(".viv") && !file_name.ends_with(".vir") {
+            continue;
+        }
+
+        if let Some(filter) = &options.filter {
+            if !file_name.contains(filter.as_str()) {
+                continue;
+            }
+        }
+
+        files.push(file.path());
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Escapes text for XML, replacing the control characters XML 1.0 doesn't allow even as
+/// character references with a visible `\x..` escape
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c < ' ' || c == '\u{FFFE}' || c == '\u{FFFF}' => {
+                escaped.push_str(&format!("\\x{:02x}", c as u32))
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn junit_report(reports: &[TestReport], total_time: Duration) -> String {
+    let failures = reports
+        .iter()
+        .filter(|report| matches!(report.result, TestResult::Failed(_)))
+        .count();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuite name=\"viv_script\" tests=\"{}\" failures=\"{failures}\" time=\"{:.3}\">\n",
+        reports.len(),
+        total_time.as_secs_f64()
+    ));
+
+    for report in reports {
+        xml.push_str(&format!(
+            "  <testcase name=\"{}\" time=\"{:.3}\"",
+            escape_xml(&report.path.display().to_string()),
+            report.duration.as_secs_f64()
+        ));
+        match &report.result {
+            TestResult::Passed => xml.push_str("/>\n"),
+            TestResult::Failed(reason) => {
+                let first_line = reason.lines().next().unwrap_or_default();
+                xml.push_str(&format!(
+                    ">\n    <failure message=\"{}\">{}</failure>\n  </testcase>\n",
+                    escape_xml(first_line),
+                    escape_xml(reason)
+                ));
+            }
+        }
+    }
+
+    xml.push_str("</testsuite>\n");
+    xml
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    passed: usize,
+    failed: usize,
+    duration: f64,
+    tests: Vec<JsonTest<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonTest<'a> {
+    name: String,
+    passed: bool,
+    duration: f64,
+    message: Option<&'a str>,
+}
+
+/// Seconds rounded to milliseconds, like the other reports
+fn seconds(duration: Duration) -> f64 {
+    (duration.as_secs_f64() * 1000.0).round() / 1000.0
+}
+
+fn json_report(reports: &[TestReport], total_time: Duration) -> String {
+    let tests = reports
+        .iter()
+        .map(|report| JsonTest {
+            name: report.path.display().to_string(),
+            passed: matches!(report.result, TestResult::Passed),
+            duration: seconds(report.duration),
+            message: match &report.result {
+                TestResult::Passed => None,
+                TestResult::Failed(reason) => Some(reason),
+            },
+        })
+        .collect::<Vec<_>>();
+
+    let failed = tests.iter().filter(|test| !test.passed).count();
+    let report = JsonReport {
+        passed: tests.len() - failed,
+        failed,
+        duration: seconds(total_time),
+        tests,
+    };
+
+    serde_json::to_string(&report).unwrap() + "\n"
+}
+
+/// Compile and run every `.viv` file in the test directory on `jobs` threads, comparing against the expectations.
+///
+/// With `bless` the `.out` files are overwritten with the actual output instead of being compared.
+pub fn run_tests(options: TestOptions) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let queue = Mutex::new(
+        collect_tests(&options)?
+            .into_iter()
+            .collect::<VecDeque<_>>(),
+    );
+    let reports = Mutex::new(Vec::new());
+
+    std::thread::scope(|scope| {
+        for _ in 0..options.jobs.max(1) {
+            scope.spawn(|| loop {
+                let path = match queue.lock().unwrap().pop_front() {
+                    Some(path) => path,
+                    None => break,
+                };
+
+                let test_start = Instant::now();
+                let result = run_test(&path, options.bless, options.leak_check)
+                    .unwrap_or_else(|err| TestResult::Failed(format!("{err:#}")));
+                let duration = test_start.elapsed();
+
+                let status = match result {
+                    TestResult::Passed => "OK",
+                    TestResult::Failed(_) => "ERROR",
+                };
+                println!(
+                    "Running test: {} ... {status} ({:.2}s)",
+                    path.display(),
+                    duration.as_secs_f64()
+                );
+
+                reports.lock().unwrap().push(TestReport {
+                    path,
+                    result,
+                    duration,
+                });
+            });
+        }
+    });
+
+    let total_time = start.elapsed();
+    let mut reports = reports.into_inner().unwrap();
+    reports.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let failed = reports
+        .iter()
+        .filter_map(|report| match &report.result {
+            TestResult::Failed(reason) => Some((&report.path, reason)),
+            TestResult::Passed => None,
+        })
+        .collect::<Vec<_>>();
+
+    for (path, reason) in &failed {
+        println!("\n---- {} ----\n{reason}", path.display());
+    }
+
+    println!(
+        "\n{} passed, {} failed in {:.2}s",
+        reports.len() - failed.len(),
+        failed.len(),
+        total_time.as_secs_f64()
+    );
+
+    if let Some(junit) = &options.junit {
+        std::fs::write(junit, junit_report(&reports, total_time))
+            .context("Writing JUnit report")?;
+    }
+    if let Some(json) = &options.json {
+        std::fs::write(json, json_report(&reports, total_time)).context("Writing JSON report")?;
+    }
+
+    if !failed.is_empty() {
+        Err(anyhow::anyhow!("{} test(s) failed", failed.len()))?;
+    }
+
+    Ok(())
+}
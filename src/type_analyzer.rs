@@ -1,32 +1,137 @@
-use std::{collections::HashMap, process::id};
+use std::collections::HashMap;
 
-use crate::{ir, parsing::ast};
+use anyhow::Context;
+
+use crate::{
+    code_gen, ir,
+    parsing::{ast, FileContext},
+    IntType,
+};
 use thiserror::Error;
 
 enum TypedExpression {
     Int(ir::IntExpression),
     Boolean(ir::BooleanExpression),
+    String(ir::StringExpression),
+    Char(ir::CharExpression),
+    List(ir::ElementType, ir::ListExpression),
 }
 
 impl TypedExpression {
-    fn is_int(self) -> anyhow::Result<ir::IntExpression> {
+    fn into_int(self) -> anyhow::Result<ir::IntExpression> {
         match self {
             TypedExpression::Int(exp) => Ok(exp),
             _ => Err(TypeError("Expected int".to_string()))?,
         }
     }
 
-    fn is_boolean(self) -> anyhow::Result<ir::BooleanExpression> {
+    fn into_boolean(self) -> anyhow::Result<ir::BooleanExpression> {
         match self {
             TypedExpression::Boolean(exp) => Ok(exp),
             _ => Err(TypeError("Expected bool".to_string()))?,
         }
     }
 
+    fn into_string(self) -> anyhow::Result<ir::StringExpression> {
+        match self {
+            TypedExpression::String(exp) => Ok(exp),
+            _ => Err(TypeError("Expected string".to_string()))?,
+        }
+    }
+
+    fn into_char(self) -> anyhow::Result<ir::CharExpression> {
+        match self {
+            TypedExpression::Char(exp) => Ok(exp),
+            _ => Err(TypeError("Expected char".to_string()))?,
+        }
+    }
+
+    fn into_list(self) -> anyhow::Result<(ir::ElementType, ir::ListExpression)> {
+        match self {
+            TypedExpression::List(element_type, exp) => Ok((element_type, exp)),
+            _ => Err(TypeError("Expected list".to_string()))?,
+        }
+    }
+
+    /// The element type of a list holding this value, lists of lists aren't supported
+    fn element_type(&self) -> anyhow::Result<ir::ElementType> {
+        match self {
+            TypedExpression::Int(_) => Ok(ir::ElementType::Int),
+            TypedExpression::Boolean(_) => Ok(ir::ElementType::Boolean),
+            TypedExpression::String(_) => Ok(ir::ElementType::String),
+            TypedExpression::Char(_) => Ok(ir::ElementType::Char),
+            TypedExpression::List(..) => {
+                Err(TypeError("lists of lists aren't supported".to_string()))?
+            }
+        }
+    }
+
+    /// An element of a list, the last one is popped without an index
+    fn element(
+        element_type: ir::ElementType,
+        list: ir::ListExpression,
+        index: Option<ir::IntExpression>,
+    ) -> Self {
+        let list = Box::new(list);
+        match (element_type, index) {
+            (ir::ElementType::Int, Some(index)) => {
+                TypedExpression::Int(ir::IntExpression::Index(list, Box::new(index)))
+            }
+            (ir::ElementType::Int, None) => TypedExpression::Int(ir::IntExpression::Pop(list)),
+            (ir::ElementType::Boolean, Some(index)) => {
+                TypedExpression::Boolean(ir::BooleanExpression::Index(list, Box::new(index)))
+            }
+            (ir::ElementType::Boolean, None) => {
+                TypedExpression::Boolean(ir::BooleanExpression::Pop(list))
+            }
+            (ir::ElementType::String, Some(index)) => {
+                TypedExpression::String(ir::StringExpression::Index(list, Box::new(index)))
+            }
+            (ir::ElementType::String, None) => {
+                TypedExpression::String(ir::StringExpression::Pop(list))
+            }
+            (ir::ElementType::Char, Some(index)) => {
+                TypedExpression::Char(ir::CharExpression::Index(list, Box::new(index)))
+            }
+            (ir::ElementType::Char, None) => TypedExpression::Char(ir::CharExpression::Pop(list)),
+        }
+    }
+
     fn to_var_type(&self) -> ir::VarType {
         match self {
             TypedExpression::Int(_) => ir::VarType::Int,
             TypedExpression::Boolean(_) => ir::VarType::Boolean,
+            TypedExpression::String(_) => ir::VarType::String,
+            TypedExpression::Char(_) => ir::VarType::Char,
+            TypedExpression::List(element_type, _) => ir::VarType::List(*element_type),
+        }
+    }
+
+    fn into_value(self) -> ir::Value {
+        match self {
+            TypedExpression::Int(int_expression) => ir::Value::Int(int_expression),
+            TypedExpression::Boolean(boolean_expression) => ir::Value::Boolean(boolean_expression),
+            TypedExpression::String(string_expression) => ir::Value::String(string_expression),
+            TypedExpression::Char(char_expression) => ir::Value::Char(char_expression),
+            TypedExpression::List(_, list_expression) => ir::Value::List(list_expression),
+        }
+    }
+
+    fn into_assignment(self) -> ir::AssignmentStatement {
+        match self {
+            TypedExpression::Int(int_expression) => ir::AssignmentStatement::Int(int_expression),
+            TypedExpression::Boolean(boolean_expression) => {
+                ir::AssignmentStatement::Boolean(boolean_expression)
+            }
+            TypedExpression::String(string_expression) => {
+                ir::AssignmentStatement::String(string_expression)
+            }
+            TypedExpression::Char(char_expression) => {
+                ir::AssignmentStatement::Char(char_expression)
+            }
+            TypedExpression::List(_, list_expression) => {
+                ir::AssignmentStatement::List(list_expression)
+            }
         }
     }
 }
@@ -34,28 +139,203 @@ impl TypedExpression {
 struct VarInfo {
     identifier: ir::VariableIdentifier,
     var_type: ir::VarType,
+    /// Declared with `let mut`
+    mutable: bool,
+    /// Line of the declaration, `None` for parameters
+    line: Option<usize>,
 }
 
 struct VarScope {
-    parent: Option<Box<VarScope>>,
     variables: HashMap<String, VarInfo>,
 }
 
+#[derive(PartialEq, Eq)]
+struct FunctionSignature {
+    parameters: Vec<ir::VarType>,
+    return_type: Option<ir::VarType>,
+}
+
 struct FunctionMetadata {
-    locals: Vec<(ir::VariableIdentifier, ir::VarType)>,
+    locals: ir::Locals,
+    can_return: bool,
+    return_type: Option<ir::VarType>,
 }
 
 #[derive(Debug, Error)]
 #[error("Type error: {0}")]
-struct TypeError(String);
+pub struct TypeError(pub String);
 
 pub struct Analyzer {
     scopes: Vec<VarScope>,
     function_metadata: Option<FunctionMetadata>,
     current_identifier: usize,
+    test_mode: bool,
+    test_names: Vec<String>,
+    /// Namespace of the module being resolved, `None` for the root module
+    current_module: Option<String>,
+    /// Namespaces of the modules imported by the module being resolved
+    current_imports: Vec<String>,
+    /// File of the module that defined the main function
+    main_file: Option<String>,
+    /// File of the module being resolved
+    current_file: String,
+    /// Signatures of the declared functions, by their symbol
+    functions: HashMap<String, FunctionSignature>,
+    /// Symbols of the declared functions, by their qualified name
+    function_symbols: HashMap<String, String>,
+    variables: HashMap<ir::VariableIdentifier, ir::VariableOrigin>,
+    warnings: Vec<ir::lints::Warning>,
+    /// Values of the `const` definitions by their qualified name, they are replaced by their value wherever they
+    /// are used
+    constants: HashMap<String, ir::Constant>,
+    /// `static mut` variables by their qualified name, usable in every function
+    globals: HashMap<String, VarInfo>,
 }
 
 impl Analyzer {
+    fn warning(&mut self, lint: &'static str, line: usize, message: String) {
+        self.warnings.push(ir::lints::Warning {
+            lint,
+            message,
+            file: Some(self.current_file.clone()),
+            line: Some(line),
+        });
+    }
+
+    /// Whether an expression doesn't depend on any variable or call
+    fn is_constant(expression: &ast::Expression) -> bool {
+        match expression {
+            ast::Expression::Literal(ast::Literal::Variable(_))
+            | ast::Expression::Call(..)
+            | ast::Expression::List(_)
+            | ast::Expression::Index(..) => false,
+            ast::Expression::Literal(_) => true,
+            ast::Expression::BinaryOp(left, _, right) => {
+                Self::is_constant(left) && Self::is_constant(right)
+            }
+            ast::Expression::Prefix(_, expression, _) => Self::is_constant(expression),
+            ast::Expression::Comparison(left_side, chains, _) => {
+                Self::is_constant(left_side)
+                    && chains
+                        .iter()
+                        .all(|(_, expression)| Self::is_constant(expression))
+            }
+            ast::Expression::If(condition, then_value, else_value, _) => {
+                Self::is_constant(condition)
+                    && Self::is_constant(then_value)
+                    && Self::is_constant(else_value)
+            }
+        }
+    }
+
+    /// Whether evaluating an expression has no effect besides producing its value
+    fn is_pure(expression: &ast::Expression) -> bool {
+        match expression {
+            ast::Expression::Call(..) => false,
+            ast::Expression::Literal(_) => true,
+            ast::Expression::BinaryOp(left, _, right) => {
+                Self::is_pure(left) && Self::is_pure(right)
+            }
+            ast::Expression::Prefix(_, expression, _) => Self::is_pure(expression),
+            ast::Expression::Comparison(left_side, chains, _) => {
+                Self::is_pure(left_side)
+                    && chains
+                        .iter()
+                        .all(|(_, expression)| Self::is_pure(expression))
+            }
+            ast::Expression::If(condition, then_value, else_value, _) => {
+                Self::is_pure(condition) && Self::is_pure(then_value) && Self::is_pure(else_value)
+            }
+            ast::Expression::List(elements) => elements.iter().all(Self::is_pure),
+            ast::Expression::Index(list, index) => Self::is_pure(list) && Self::is_pure(index),
+        }
+    }
+
+    /// Looks up a variable in the innermost block declaring it
+    fn local(&self, name: &str) -> Option<&VarInfo> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.variables.get(name))
+    }
+
+    /// The name a global or function is stored under, `name` in the root module and `namespace::name` in imported
+    /// modules. A name that already has a namespace must come from the current module or a module it imports.
+    fn qualified_name(&self, name: &str) -> anyhow::Result<String> {
+        if let Some((namespace, _)) = name.rsplit_once("::") {
+            if self.current_module.as_deref() != Some(namespace)
+                && !self
+                    .current_imports
+                    .iter()
+                    .any(|import| import == namespace)
+            {
+                Err(TypeError(format!(
+                    "cannot use {name}, module {namespace} is not imported by {}",
+                    self.current_file
+                )))?;
+            }
+            return Ok(name.to_string());
+        }
+
+        Ok(match &self.current_module {
+            Some(module) => format!("{module}::{name}"),
+            None => name.to_string(),
+        })
+    }
+
+    /// The name of a global or function in the ir and the object file, `util::LIMIT` becomes `util__LIMIT` so it is
+    /// a valid C identifier
+    fn symbol(qualified_name: &str) -> String {
+        qualified_name.replace("::", "__")
+    }
+
+    /// Looks up a local, or a global if there is no local with the name
+    fn variable(&self, name: &str) -> anyhow::Result<Option<&VarInfo>> {
+        if let Some(local) = self.local(name) {
+            return Ok(Some(local));
+        }
+        Ok(self.globals.get(&self.qualified_name(name)?))
+    }
+
+    /// Looks up a constant, unless a local with the name shadows it
+    fn constant(&self, name: &str) -> anyhow::Result<Option<&ir::Constant>> {
+        if self.local(name).is_some() {
+            return Ok(None);
+        }
+        Ok(self.constants.get(&self.qualified_name(name)?))
+    }
+
+    /// Whether `name` is a declared function that the current module can call
+    fn is_function(&self, name: &str) -> bool {
+        self.qualified_name(name)
+            .is_ok_and(|name| self.function_symbols.contains_key(&name))
+    }
+
+    /// Looks up a variable that is assigned on `line`, making sure it is mutable
+    fn assignment_target(&self, name: &str, line: usize) -> anyhow::Result<&VarInfo> {
+        if self.constant(name)?.is_some() {
+            Err(TypeError(format!("cannot assign to constant {name}")))?;
+        }
+        let var_info = self
+            .variable(name)?
+            .ok_or(TypeError(format!("variable {name} not found")))?;
+
+        if !var_info.mutable {
+            let file = &self.current_file;
+            Err(TypeError(match var_info.line {
+                Some(declaration_line) => format!(
+                    "cannot assign twice to immutable variable {name}: set at {file}:{line}, \
+                     declared without mut at {file}:{declaration_line}"
+                ),
+                None => format!(
+                    "cannot assign to parameter {name} at {file}:{line}, parameters are immutable"
+                ),
+            }))?;
+        }
+
+        Ok(var_info)
+    }
+
     fn get_free_identifier(&mut self) -> ir::VariableIdentifier {
         self.current_identifier += 1;
         ir::VariableIdentifier(self.current_identifier)
@@ -69,13 +349,32 @@ impl Analyzer {
             ast::Literal::Boolean(boolean) => Ok(TypedExpression::Boolean(
                 ir::BooleanExpression::Literal(*boolean),
             )),
+            ast::Literal::String(string) => Ok(TypedExpression::String(
+                ir::StringExpression::Literal(string.clone()),
+            )),
+            ast::Literal::Char(value) => {
+                Ok(TypedExpression::Char(ir::CharExpression::Literal(*value)))
+            }
             ast::Literal::Variable(name) => {
+                if let Some(constant) = self.constant(name)? {
+                    return Ok(match constant.clone() {
+                        ir::Constant::Int(int) => {
+                            TypedExpression::Int(ir::IntExpression::Literal(int))
+                        }
+                        ir::Constant::Boolean(boolean) => {
+                            TypedExpression::Boolean(ir::BooleanExpression::Literal(boolean))
+                        }
+                        ir::Constant::String(string) => {
+                            TypedExpression::String(ir::StringExpression::Literal(string))
+                        }
+                        ir::Constant::Char(value) => {
+                            TypedExpression::Char(ir::CharExpression::Literal(value))
+                        }
+                    });
+                }
+
                 let var_info = self
-                    .scopes
-                    .last()
-                    .unwrap()
-                    .variables
-                    .get(name)
+                    .variable(name)?
                     .ok_or(TypeError(format!("variable {name} not found")))?;
 
                 Ok(match var_info.var_type {
@@ -85,6 +384,16 @@ impl Analyzer {
                     ir::VarType::Boolean => {
                         TypedExpression::Boolean(ir::BooleanExpression::Var(var_info.identifier))
                     }
+                    ir::VarType::String => {
+                        TypedExpression::String(ir::StringExpression::Var(var_info.identifier))
+                    }
+                    ir::VarType::Char => {
+                        TypedExpression::Char(ir::CharExpression::Var(var_info.identifier))
+                    }
+                    ir::VarType::List(element_type) => TypedExpression::List(
+                        element_type,
+                        ir::ListExpression::Var(var_info.identifier),
+                    ),
                 })
             }
         }
@@ -92,21 +401,32 @@ impl Analyzer {
 
     fn resolve_prefix(
         &mut self,
-        expression: &Box<ast::Expression>,
+        expression: &ast::Expression,
         op: &ast::PrefixOp,
+        line: usize,
     ) -> anyhow::Result<TypedExpression> {
+        if let (ast::PrefixOp::Not, ast::Expression::Prefix(ast::PrefixOp::Not, ..)) =
+            (op, expression)
+        {
+            self.warning(
+                ir::lints::REDUNDANT_NOT,
+                line,
+                String::from("double negation has no effect"),
+            );
+        }
+
         let expression = self.resolve_expression(expression)?;
 
         match op {
             ast::PrefixOp::Negate => {
-                let expression = expression.is_int()?;
+                let expression = expression.into_int()?;
 
                 Ok(TypedExpression::Int(ir::IntExpression::Negate(Box::new(
                     expression,
                 ))))
             }
             ast::PrefixOp::Not => {
-                let expression = expression.is_boolean()?;
+                let expression = expression.into_boolean()?;
 
                 Ok(TypedExpression::Boolean(ir::BooleanExpression::Not(
                     Box::new(expression),
@@ -117,16 +437,16 @@ impl Analyzer {
 
     fn resolve_binary(
         &mut self,
-        left: &Box<ast::Expression>,
+        left: &ast::Expression,
         op: &ast::BinaryOp,
-        right: &Box<ast::Expression>,
+        right: &ast::Expression,
     ) -> anyhow::Result<TypedExpression> {
         let left = self.resolve_expression(left)?;
         let right = self.resolve_expression(right)?;
 
         match left {
             TypedExpression::Int(left) => {
-                let right = right.is_int()?;
+                let right = right.into_int()?;
                 let op = match op {
                     ast::BinaryOp::Plus => ir::IntBinaryOp::Plus,
                     ast::BinaryOp::Minus => ir::IntBinaryOp::Minus,
@@ -141,7 +461,7 @@ impl Analyzer {
                 )))
             }
             TypedExpression::Boolean(left) => {
-                let right = right.is_boolean()?;
+                let right = right.into_boolean()?;
                 let op = match op {
                     ast::BinaryOp::And => ir::BooleanOperator::And,
                     ast::BinaryOp::Or => ir::BooleanOperator::Or,
@@ -168,16 +488,61 @@ impl Analyzer {
 
     fn resolve_comparison(
         &mut self,
-        left_side: &Box<ast::Expression>,
+        left_side: &ast::Expression,
         chains: &Vec<(ast::ComparisonOp, ast::Expression)>,
+        line: usize,
     ) -> anyhow::Result<TypedExpression> {
-        let left_side = self.resolve_expression(left_side)?;
-        let left_side = left_side.is_int()?;
-        let chains = chains
+        let mut left = left_side;
+        for (op, right) in chains {
+            if let (
+                ast::Expression::Literal(ast::Literal::Variable(left_name)),
+                ast::Expression::Literal(ast::Literal::Variable(right_name)),
+            ) = (left, right)
+            {
+                if left_name == right_name {
+                    let result = matches!(
+                        op,
+                        ast::ComparisonOp::Equals
+                            | ast::ComparisonOp::LessThanEquals
+                            | ast::ComparisonOp::GreaterThanEquals
+                    );
+                    self.warning(
+                        ir::lints::SELF_COMPARISON,
+                        line,
+                        format!("comparing `{left_name}` with itself is always {result}"),
+                    );
+                }
+            }
+            left = right;
+        }
+
+        // a chain starting with a char compares chars, anything else compares ints
+        let comparison = match self.resolve_expression(left_side)? {
+            TypedExpression::Char(left_side) => ir::ComparisonExpression::CharComparison(
+                Box::new(left_side),
+                self.resolve_comparison_chains(chains, TypedExpression::into_char)?,
+            ),
+            left_side => ir::ComparisonExpression::IntComparison(
+                Box::new(left_side.into_int()?),
+                self.resolve_comparison_chains(chains, TypedExpression::into_int)?,
+            ),
+        };
+
+        Ok(TypedExpression::Boolean(ir::BooleanExpression::Comparison(
+            comparison,
+        )))
+    }
+
+    fn resolve_comparison_chains<T>(
+        &mut self,
+        chains: &[(ast::ComparisonOp, ast::Expression)],
+        expect: impl Fn(TypedExpression) -> anyhow::Result<T>,
+    ) -> anyhow::Result<Vec<(ir::IntComparisonOp, T)>> {
+        chains
             .iter()
             .map(|(op, expression)| {
                 let expression = self.resolve_expression(expression)?;
-                let expression = expression.is_int()?;
+                let expression = expect(expression)?;
 
                 let op = match op {
                     ast::ComparisonOp::Equals => ir::IntComparisonOp::Equal,
@@ -190,11 +555,211 @@ impl Analyzer {
 
                 Ok((op, expression))
             })
-            .collect::<anyhow::Result<Vec<_>>>()?;
+            .collect()
+    }
 
-        Ok(TypedExpression::Boolean(ir::BooleanExpression::Comparison(
-            ir::ComparisonExpression::IntComparison(Box::new(left_side), chains),
-        )))
+    fn resolve_type(name: &str) -> anyhow::Result<ir::VarType> {
+        match name {
+            "int" => Ok(ir::VarType::Int),
+            "bool" => Ok(ir::VarType::Boolean),
+            "string" => Ok(ir::VarType::String),
+            "char" => Ok(ir::VarType::Char),
+            _ => {
+                let element_type = name
+                    .strip_prefix("list<")
+                    .and_then(|name| name.strip_suffix('>'))
+                    .ok_or(TypeError(format!("unknown type {name}")))?;
+                Ok(ir::VarType::List(match Self::resolve_type(element_type)? {
+                    ir::VarType::Int => ir::ElementType::Int,
+                    ir::VarType::Boolean => ir::ElementType::Boolean,
+                    ir::VarType::String => ir::ElementType::String,
+                    ir::VarType::Char => ir::ElementType::Char,
+                    ir::VarType::List(_) => {
+                        Err(TypeError("lists of lists aren't supported".to_string()))?
+                    }
+                }))
+            }
+        }
+    }
+
+    fn resolve_function_call(
+        &mut self,
+        name: &str,
+        arguments: &[ast::Expression],
+    ) -> anyhow::Result<(ir::Call, Option<ir::VarType>)> {
+        let symbol = self.function_symbols[&self.qualified_name(name)?].clone();
+        let signature = &self.functions[&symbol];
+        let parameters = signature.parameters.clone();
+        let return_type = signature.return_type;
+
+        if arguments.len() != parameters.len() {
+            Err(TypeError(format!(
+                "function {name} takes {} argument(s), but {} were given",
+                parameters.len(),
+                arguments.len()
+            )))?;
+        }
+
+        let mut values = Vec::with_capacity(arguments.len());
+        for (index, (argument, parameter)) in arguments.iter().zip(parameters).enumerate() {
+            let argument = self.resolve_expected(argument, Some(parameter))?;
+            let argument_type = argument.to_var_type();
+            if argument_type != parameter {
+                Err(TypeError(format!(
+                    "argument {} of {name} should be {parameter:?}, but is {argument_type:?}",
+                    index + 1
+                )))?;
+            }
+            values.push(argument.into_value());
+        }
+
+        Ok((
+            ir::Call {
+                function: symbol,
+                arguments: values,
+            },
+            return_type,
+        ))
+    }
+
+    fn resolve_call(
+        &mut self,
+        name: &str,
+        arguments: &[ast::Expression],
+    ) -> anyhow::Result<TypedExpression> {
+        if self.is_function(name) {
+            let (call, return_type) = self.resolve_function_call(name, arguments)?;
+            return Ok(match return_type {
+                Some(ir::VarType::Int) => TypedExpression::Int(ir::IntExpression::Call(call)),
+                Some(ir::VarType::Boolean) => {
+                    TypedExpression::Boolean(ir::BooleanExpression::Call(call))
+                }
+                Some(ir::VarType::String) => {
+                    TypedExpression::String(ir::StringExpression::Call(call))
+                }
+                Some(ir::VarType::Char) => TypedExpression::Char(ir::CharExpression::Call(call)),
+                Some(ir::VarType::List(element_type)) => {
+                    TypedExpression::List(element_type, ir::ListExpression::Call(call))
+                }
+                None => Err(TypeError(format!(
+                    "function {name} does not return a value"
+                )))?,
+            });
+        }
+
+        let expected_arguments = match name {
+            "read_int" | "read_line" | "arg_count" => 0,
+            "arg" | "parse_int" | "pop" | "len" | "to_int" | "to_char" => 1,
+            "exit" | "push" => Err(TypeError(format!(
+                "{name} does not return a value, it can only be used as a statement"
+            )))?,
+            _ => {
+                // reports a namespace that isn't imported
+                self.qualified_name(name)?;
+                Err(TypeError(format!("function {name} not found")))?
+            }
+        };
+        if arguments.len() != expected_arguments {
+            Err(TypeError(format!(
+                "function {name} takes {expected_arguments} argument(s), but {} were given",
+                arguments.len()
+            )))?;
+        }
+
+        let mut arguments = arguments
+            .iter()
+            .map(|argument| self.resolve_expression(argument))
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .into_iter();
+
+        Ok(match name {
+            "read_int" => TypedExpression::Int(ir::IntExpression::ReadInt),
+            "arg_count" => TypedExpression::Int(ir::IntExpression::ArgCount),
+            "parse_int" => TypedExpression::Int(ir::IntExpression::ParseInt(Box::new(
+                arguments.next().unwrap().into_string()?,
+            ))),
+            "read_line" => TypedExpression::String(ir::StringExpression::ReadLine),
+            "arg" => TypedExpression::String(ir::StringExpression::Arg(Box::new(
+                arguments.next().unwrap().into_int()?,
+            ))),
+            "pop" => {
+                let (element_type, list) = arguments.next().unwrap().into_list()?;
+                TypedExpression::element(element_type, list, None)
+            }
+            "len" => TypedExpression::Int(ir::IntExpression::Length(Box::new(
+                arguments.next().unwrap().into_list()?.1,
+            ))),
+            "to_int" => TypedExpression::Int(ir::IntExpression::FromChar(Box::new(
+                arguments.next().unwrap().into_char()?,
+            ))),
+            "to_char" => TypedExpression::Char(ir::CharExpression::FromInt(Box::new(
+                arguments.next().unwrap().into_int()?,
+            ))),
+            _ => unreachable!(),
+        })
+    }
+
+    fn resolve_if(
+        &mut self,
+        condition: &ast::Expression,
+        then_value: &ast::Expression,
+        else_value: &ast::Expression,
+        line: usize,
+    ) -> anyhow::Result<TypedExpression> {
+        if Self::is_constant(condition) {
+            self.warning(
+                ir::lints::CONSTANT_CONDITION,
+                line,
+                String::from("condition of if is constant"),
+            );
+        }
+
+        let condition = Box::new(self.resolve_expression(condition)?.into_boolean()?);
+        let then_value = self.resolve_expression(then_value)?;
+        let else_value = self.resolve_expression(else_value)?;
+
+        Ok(match (then_value, else_value) {
+            (TypedExpression::Int(then_value), TypedExpression::Int(else_value)) => {
+                TypedExpression::Int(ir::IntExpression::If(
+                    condition,
+                    Box::new(then_value),
+                    Box::new(else_value),
+                ))
+            }
+            (TypedExpression::Boolean(then_value), TypedExpression::Boolean(else_value)) => {
+                TypedExpression::Boolean(ir::BooleanExpression::If(
+                    condition,
+                    Box::new(then_value),
+                    Box::new(else_value),
+                ))
+            }
+            (TypedExpression::String(then_value), TypedExpression::String(else_value)) => {
+                TypedExpression::String(ir::StringExpression::If(
+                    condition,
+                    Box::new(then_value),
+                    Box::new(else_value),
+                ))
+            }
+            (TypedExpression::Char(then_value), TypedExpression::Char(else_value)) => {
+                TypedExpression::Char(ir::CharExpression::If(
+                    condition,
+                    Box::new(then_value),
+                    Box::new(else_value),
+                ))
+            }
+            (
+                TypedExpression::List(element_type, then_value),
+                TypedExpression::List(else_type, else_value),
+            ) if element_type == else_type => TypedExpression::List(
+                element_type,
+                ir::ListExpression::If(condition, Box::new(then_value), Box::new(else_value)),
+            ),
+            (then_value, else_value) => Err(TypeError(format!(
+                "branches of if have different types: {:?} and {:?}",
+                then_value.to_var_type(),
+                else_value.to_var_type()
+            )))?,
+        })
     }
 
     fn resolve_expression(
@@ -203,80 +768,223 @@ impl Analyzer {
     ) -> anyhow::Result<TypedExpression> {
         match expression {
             ast::Expression::Literal(literal) => self.resolve_literal(literal),
-            ast::Expression::Prefix(op, expression) => self.resolve_prefix(expression, op),
+            ast::Expression::Prefix(op, expression, line) => {
+                self.resolve_prefix(expression, op, *line)
+            }
             ast::Expression::BinaryOp(left, op, right) => self.resolve_binary(left, op, right),
-            ast::Expression::Comparison(left_side, chains) => {
-                self.resolve_comparison(left_side, chains)
+            ast::Expression::Comparison(left_side, chains, line) => {
+                self.resolve_comparison(left_side, chains, *line)
+            }
+            ast::Expression::Call(name, arguments) => self.resolve_call(name, arguments),
+            ast::Expression::If(condition, then_value, else_value, line) => {
+                self.resolve_if(condition, then_value, else_value, *line)
+            }
+            ast::Expression::List(elements) => self.resolve_list(elements, None),
+            ast::Expression::Index(list, index) => {
+                let (element_type, list) = self.resolve_expression(list)?.into_list()?;
+                let index = self.resolve_expression(index)?.into_int()?;
+                Ok(TypedExpression::element(element_type, list, Some(index)))
             }
         }
     }
 
-    fn resolve_print_statement(
+    /// Resolves an expression whose type is known from where it is used, which gives `[]` its element type
+    fn resolve_expected(
+        &mut self,
+        expression: &ast::Expression,
+        expected: Option<ir::VarType>,
+    ) -> anyhow::Result<TypedExpression> {
+        match (expression, expected) {
+            (ast::Expression::List(elements), Some(ir::VarType::List(element_type))) => {
+                self.resolve_list(elements, Some(element_type))
+            }
+            _ => self.resolve_expression(expression),
+        }
+    }
+
+    fn resolve_list(
+        &mut self,
+        elements: &[ast::Expression],
+        expected: Option<ir::ElementType>,
+    ) -> anyhow::Result<TypedExpression> {
+        let elements = elements
+            .iter()
+            .map(|element| self.resolve_expression(element))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let element_type = match elements.first() {
+            Some(first) => first.element_type()?,
+            None => expected.ok_or(TypeError(
+                "the element type of an empty list can't be inferred, declare the variable with \
+                 a type like `let xs: list<int> = [];`"
+                    .to_string(),
+            ))?,
+        };
+        for element in &elements {
+            if element.element_type()? != element_type {
+                Err(TypeError(format!(
+                    "list elements have different types: {:?} and {:?}",
+                    element_type.var_type(),
+                    element.to_var_type()
+                )))?;
+            }
+        }
+
+        Ok(TypedExpression::List(
+            element_type,
+            ir::ListExpression::Literal(
+                element_type,
+                elements
+                    .into_iter()
+                    .map(TypedExpression::into_value)
+                    .collect(),
+            ),
+        ))
+    }
+
+    /// Makes sure a value can be stored in a list of `element_type`
+    fn element_value(
+        element_type: ir::ElementType,
+        value: TypedExpression,
+    ) -> anyhow::Result<ir::Value> {
+        if value.to_var_type() != element_type.var_type() {
+            Err(TypeError(format!(
+                "{:?} can't be stored in a list of {:?}",
+                value.to_var_type(),
+                element_type.var_type()
+            )))?;
+        }
+        Ok(value.into_value())
+    }
+
+    fn resolve_print_value(
         &mut self,
         expression: &ast::Expression,
-    ) -> anyhow::Result<ir::PrintStatement> {
+    ) -> anyhow::Result<ir::PrintValue> {
         let typed_expression = self.resolve_expression(expression)?;
 
         match typed_expression {
-            TypedExpression::Int(int_expression) => Ok(ir::PrintStatement::Int(int_expression)),
+            TypedExpression::Int(int_expression) => Ok(ir::PrintValue::Int(int_expression)),
             TypedExpression::Boolean(boolean_expression) => {
-                Ok(ir::PrintStatement::Boolean(boolean_expression))
+                Ok(ir::PrintValue::Boolean(boolean_expression))
+            }
+            TypedExpression::String(string_expression) => {
+                Ok(ir::PrintValue::String(string_expression))
             }
+            TypedExpression::Char(char_expression) => Ok(ir::PrintValue::Char(char_expression)),
+            TypedExpression::List(..) => Err(TypeError(
+                "lists can't be printed, print their elements instead".to_string(),
+            ))?,
         }
     }
 
+    fn resolve_print_statement(
+        &mut self,
+        arguments: &[ast::PrintArgument],
+    ) -> anyhow::Result<Vec<ir::PrintValue>> {
+        let mut values = Vec::new();
+
+        for (index, argument) in arguments.iter().enumerate() {
+            if index > 0 {
+                values.push(ir::PrintValue::Text(String::from(" ")));
+            }
+
+            match argument {
+                ast::PrintArgument::Expression(expression) => {
+                    values.push(self.resolve_print_value(expression)?);
+                }
+                ast::PrintArgument::Format(parts) => {
+                    for part in parts {
+                        values.push(match part {
+                            ast::FormatPart::Text(text) => ir::PrintValue::Text(text.clone()),
+                            ast::FormatPart::Expression(expression) => {
+                                self.resolve_print_value(expression)?
+                            }
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(values)
+    }
+
     fn resolve_statement(&mut self, statement: &ast::Statement) -> anyhow::Result<ir::Statement> {
         match statement {
-            ast::Statement::Print(expression) => {
-                let print_statement = self.resolve_print_statement(expression)?;
+            ast::Statement::Print(arguments) => {
+                let values = self.resolve_print_statement(arguments)?;
 
-                Ok(ir::Statement::Print(print_statement))
+                Ok(ir::Statement::Print(values))
             }
-            ast::Statement::Assert(expression, message) => {
+            ast::Statement::Assert(expression, message, location) => {
+                // `assert false` is the way to fail unconditionally
+                if Self::is_constant(expression)
+                    && !matches!(
+                        expression,
+                        ast::Expression::Literal(ast::Literal::Boolean(false))
+                    )
+                {
+                    self.warning(
+                        ir::lints::CONSTANT_CONDITION,
+                        location.line,
+                        String::from("condition of assert is constant"),
+                    );
+                }
+
                 let expression = self.resolve_expression(expression)?;
-                let expression = expression.is_boolean()?;
+                let expression = expression.into_boolean()?;
 
-                Ok(ir::Statement::Assert(expression, message.clone()))
+                Ok(ir::Statement::Assert(
+                    expression,
+                    message.clone(),
+                    location.clone(),
+                ))
             }
-            ast::Statement::Declaration(name, expression) => {
+            ast::Statement::Declaration(name, type_name, expression, mutable, line) => {
                 let identifier = self.get_free_identifier();
-                let typed_expression = self.resolve_expression(expression)?;
+                self.variables.insert(
+                    identifier,
+                    ir::VariableOrigin {
+                        name: name.clone(),
+                        file: self.current_file.clone(),
+                        line: *line,
+                    },
+                );
+                let declared_type = type_name.as_deref().map(Self::resolve_type).transpose()?;
+                let typed_expression = self.resolve_expected(expression, declared_type)?;
 
                 let var_type = typed_expression.to_var_type();
+                if let Some(declared_type) = declared_type {
+                    if declared_type != var_type {
+                        Err(TypeError(format!(
+                            "{name} is declared as {declared_type:?}, but its value is {var_type:?}"
+                        )))?;
+                    }
+                }
                 self.function_metadata
                     .as_mut()
                     .unwrap()
                     .locals
                     .push((identifier, var_type));
 
-                let assignment = match typed_expression {
-                    TypedExpression::Int(int_expression) => {
-                        ir::AssignmentStatement::Int(int_expression)
-                    }
-                    TypedExpression::Boolean(boolean_expression) => {
-                        ir::AssignmentStatement::Boolean(boolean_expression)
-                    }
-                };
+                let assignment = typed_expression.into_assignment();
 
                 self.scopes.last_mut().unwrap().variables.insert(
                     name.clone(),
                     VarInfo {
                         identifier,
                         var_type,
+                        mutable: *mutable,
+                        line: Some(*line),
                     },
                 );
 
                 Ok(ir::Statement::Assignment(identifier, assignment))
             }
-            ast::Statement::Assignment(name, expression) => {
-                let typed_expression = self.resolve_expression(expression)?;
-                let var_info = self
-                    .scopes
-                    .last()
-                    .unwrap()
-                    .variables
-                    .get(name)
-                    .ok_or(TypeError(format!("variable {name} not found")))?;
+            ast::Statement::Assignment(name, expression, line) => {
+                let expected = self.variable(name)?.map(|var_info| var_info.var_type);
+                let typed_expression = self.resolve_expected(expression, expected)?;
+                let var_info = self.assignment_target(name, *line)?;
 
                 let var_type = typed_expression.to_var_type();
 
@@ -286,65 +994,945 @@ impl Analyzer {
                     )))?;
                 }
 
-                let assignment = match typed_expression {
-                    TypedExpression::Int(int_expression) => {
-                        ir::AssignmentStatement::Int(int_expression)
+                let assignment = typed_expression.into_assignment();
+                Ok(ir::Statement::Assignment(var_info.identifier, assignment))
+            }
+            ast::Statement::CompoundAssignment(name, op, expression, line) => {
+                let expression = self.resolve_expression(expression)?.into_int()?;
+                let var_info = self.assignment_target(name, *line)?;
+
+                if var_info.var_type != ir::VarType::Int {
+                    Err(TypeError(format!(
+                        "compound assignment is only supported for Int, but variable {name} is {:?}",
+                        var_info.var_type
+                    )))?;
+                }
+
+                let op = match op {
+                    ast::BinaryOp::Plus => ir::IntBinaryOp::Plus,
+                    ast::BinaryOp::Minus => ir::IntBinaryOp::Minus,
+                    ast::BinaryOp::Multiply => ir::IntBinaryOp::Multiply,
+                    ast::BinaryOp::Divide => ir::IntBinaryOp::Divide,
+                    ast::BinaryOp::And | ast::BinaryOp::Or => unreachable!(),
+                };
+                Ok(ir::Statement::CompoundAssignment(
+                    var_info.identifier,
+                    op,
+                    expression,
+                ))
+            }
+            ast::Statement::Return(expression) => {
+                let metadata = self.function_metadata.as_ref().unwrap();
+                if !metadata.can_return {
+                    Err(TypeError(
+                        "return is not allowed in a test block".to_string(),
+                    ))?;
+                }
+                let return_type = metadata.return_type;
+
+                let value = expression
+                    .as_ref()
+                    .map(|expression| self.resolve_expected(expression, return_type))
+                    .transpose()?;
+                let value_type = value.as_ref().map(TypedExpression::to_var_type);
+                match (return_type, value_type) {
+                    (Some(return_type), Some(value_type)) if return_type != value_type => {
+                        Err(TypeError(format!(
+                            "function should return {return_type:?}, but returns {value_type:?}"
+                        )))?
+                    }
+                    (Some(return_type), None) => Err(TypeError(format!(
+                        "function should return {return_type:?}, but returns nothing"
+                    )))?,
+                    (None, Some(value_type)) => Err(TypeError(format!(
+                        "function doesn't return a value, but returns {value_type:?}"
+                    )))?,
+                    _ => {}
+                }
+
+                Ok(ir::Statement::Return(
+                    value.map(TypedExpression::into_value),
+                ))
+            }
+            ast::Statement::Expression(ast::Expression::Call(name, arguments), _)
+                if name == "exit" =>
+            {
+                if arguments.len() != 1 {
+                    Err(TypeError(format!(
+                        "function exit takes 1 argument(s), but {} were given",
+                        arguments.len()
+                    )))?;
+                }
+
+                let code = self.resolve_expression(&arguments[0])?.into_int()?;
+                Ok(ir::Statement::Exit(code))
+            }
+            ast::Statement::Expression(ast::Expression::Call(name, arguments), _)
+                if name == "push" =>
+            {
+                if arguments.len() != 2 {
+                    Err(TypeError(format!(
+                        "function push takes 2 argument(s), but {} were given",
+                        arguments.len()
+                    )))?;
+                }
+
+                let (element_type, list) = self.resolve_expression(&arguments[0])?.into_list()?;
+                let value = self.resolve_expression(&arguments[1])?;
+                let value = Self::element_value(element_type, value)?;
+                Ok(ir::Statement::Push(list, value))
+            }
+            // lists are shared by reference, so their elements can be set through an immutable variable
+            ast::Statement::IndexAssignment(name, index, value, _) => {
+                let list = ast::Expression::Literal(ast::Literal::Variable(name.clone()));
+                let (element_type, list) = self.resolve_expression(&list)?.into_list()?;
+                let index = self.resolve_expression(index)?.into_int()?;
+                let value = self.resolve_expression(value)?;
+                let value = Self::element_value(element_type, value)?;
+                Ok(ir::Statement::SetIndex(list, index, value))
+            }
+            ast::Statement::Expression(ast::Expression::Call(name, arguments), _)
+                if self.is_function(name) =>
+            {
+                let (call, return_type) = self.resolve_function_call(name, arguments)?;
+                let value = match return_type {
+                    Some(ir::VarType::List(element_type)) => {
+                        TypedExpression::List(element_type, ir::ListExpression::Call(call))
                     }
-                    TypedExpression::Boolean(boolean_expression) => {
-                        ir::AssignmentStatement::Boolean(boolean_expression)
+                    Some(ir::VarType::String) => {
+                        TypedExpression::String(ir::StringExpression::Call(call))
                     }
+                    _ => return Ok(ir::Statement::Call(call)),
                 };
-                Ok(ir::Statement::Assignment(var_info.identifier, assignment))
+
+                // the returned list or string is kept in a temporary, so it is released with the other variables
+                let identifier = self.get_free_identifier();
+                self.function_metadata
+                    .as_mut()
+                    .unwrap()
+                    .locals
+                    .push((identifier, value.to_var_type()));
+                Ok(ir::Statement::Assignment(
+                    identifier,
+                    value.into_assignment(),
+                ))
+            }
+            ast::Statement::For(_) => unreachable!("for loops are resolved by resolve_statements"),
+            ast::Statement::Expression(expression, line) => {
+                if Self::is_pure(expression) {
+                    self.warning(
+                        ir::lints::UNUSED_VALUE,
+                        *line,
+                        String::from("value of expression statement is never used"),
+                    );
+                }
+
+                // the value is evaluated for its side effects and stored in an unused temporary
+                let typed_expression = self.resolve_expression(expression)?;
+                let identifier = self.get_free_identifier();
+                self.function_metadata
+                    .as_mut()
+                    .unwrap()
+                    .locals
+                    .push((identifier, typed_expression.to_var_type()));
+                Ok(ir::Statement::Assignment(
+                    identifier,
+                    typed_expression.into_assignment(),
+                ))
+            }
+        }
+    }
+
+    fn resolve_statements(
+        &mut self,
+        statements: &[ast::Statement],
+    ) -> anyhow::Result<Vec<ir::Statement>> {
+        let mut ir_statements = Vec::new();
+        for statement in statements {
+            match statement {
+                ast::Statement::For(for_loop) => {
+                    ir_statements.append(&mut self.resolve_for(for_loop)?)
+                }
+                statement => ir_statements.push(self.resolve_statement(statement)?),
+            }
+        }
+
+        Ok(ir_statements)
+    }
+
+    /// Adds a temporary local holding a value, evaluated once
+    fn temporary(
+        &mut self,
+        value: TypedExpression,
+        statements: &mut Vec<ir::Statement>,
+    ) -> ir::VariableIdentifier {
+        let identifier = self.get_free_identifier();
+        self.function_metadata
+            .as_mut()
+            .unwrap()
+            .locals
+            .push((identifier, value.to_var_type()));
+        statements.push(ir::Statement::Assignment(
+            identifier,
+            value.into_assignment(),
+        ));
+        identifier
+    }
+
+    fn int_temporary(
+        &mut self,
+        expression: ir::IntExpression,
+        statements: &mut Vec<ir::Statement>,
+    ) -> ir::IntExpression {
+        ir::IntExpression::Var(self.temporary(TypedExpression::Int(expression), statements))
+    }
+
+    fn resolve_for(&mut self, for_loop: &ast::ForLoop) -> anyhow::Result<Vec<ir::Statement>> {
+        match &for_loop.iterable {
+            ast::Iterable::Range {
+                start,
+                end,
+                inclusive,
+                step,
+            } => self.resolve_range_for(for_loop, start, end, *inclusive, step.as_ref()),
+            ast::Iterable::List(list) => self.resolve_list_for(for_loop, list),
+        }
+    }
+
+    /// Declares the variable of a for loop, which is only visible in its body
+    fn loop_variable(
+        &mut self,
+        for_loop: &ast::ForLoop,
+        var_type: ir::VarType,
+    ) -> ir::VariableIdentifier {
+        let identifier = self.get_free_identifier();
+        self.variables.insert(
+            identifier,
+            ir::VariableOrigin {
+                name: for_loop.variable.clone(),
+                file: self.current_file.clone(),
+                line: for_loop.line,
+            },
+        );
+        self.function_metadata
+            .as_mut()
+            .unwrap()
+            .locals
+            .push((identifier, var_type));
+        identifier
+    }
+
+    /// Resolves the body of a for loop between `start` and `increment`, where the loop variable
+    /// can't be assigned
+    fn resolve_loop_body(
+        &mut self,
+        for_loop: &ast::ForLoop,
+        identifier: ir::VariableIdentifier,
+        var_type: ir::VarType,
+        mut start: Vec<ir::Statement>,
+        increment: Vec<ir::Statement>,
+    ) -> anyhow::Result<Vec<ir::Statement>> {
+        let mut variables = HashMap::new();
+        variables.insert(
+            for_loop.variable.clone(),
+            VarInfo {
+                identifier,
+                var_type,
+                mutable: false,
+                line: Some(for_loop.line),
+            },
+        );
+        self.scopes.push(VarScope { variables });
+        let body = self.resolve_statements(&for_loop.body);
+        self.scopes.pop();
+        start.append(&mut body?);
+
+        // a body ending in return or exit never gets to the increment
+        if !matches!(
+            start.last(),
+            Some(ir::Statement::Return(_) | ir::Statement::Exit(_))
+        ) {
+            start.extend(increment);
+        }
+
+        Ok(start)
+    }
+
+    /// Lowers `for i in start..end step n { body }` to
+    /// `c = start; more = c < end; while more { i = c; body; more = c + n < end; c += n }`,
+    /// the bounds and step are evaluated once, left to right, and a step that isn't constant is checked when
+    /// the loop starts.
+    ///
+    /// Whether there is a next value is decided before the counter is increased, so the loop ends even if
+    /// `c + n` doesn't fit in an int. Only the hidden counter is read by the loop itself, so the loop variable
+    /// is linted like any other variable.
+    fn resolve_range_for(
+        &mut self,
+        for_loop: &ast::ForLoop,
+        start: &ast::Expression,
+        end: &ast::Expression,
+        inclusive: bool,
+        step: Option<&ast::Expression>,
+    ) -> anyhow::Result<Vec<ir::Statement>> {
+        let mut statements = Vec::new();
+
+        let start = self.resolve_expression(start)?.into_int()?;
+        let counter = self.temporary(TypedExpression::Int(start), &mut statements);
+        let end = self.resolve_expression(end)?.into_int()?;
+        let end = self.temporary(TypedExpression::Int(end), &mut statements);
+        let step = match step {
+            Some(step) => {
+                let constant = self.evaluate_constant(step, "step");
+                if let Ok(ir::Constant::Int(value)) = constant {
+                    if value <= 0 {
+                        Err(TypeError(format!(
+                            "step of for loop must be positive, but is {value}"
+                        )))?;
+                    }
+                }
+                let step = self.resolve_expression(step)?.into_int()?;
+                let step = self.temporary(TypedExpression::Int(step), &mut statements);
+                if constant.is_err() {
+                    statements.push(ir::Statement::Assert(
+                        Self::int_comparison(
+                            ir::IntExpression::Var(step),
+                            ir::IntComparisonOp::GreaterThan,
+                            ir::IntExpression::Literal(0),
+                        ),
+                        Some(String::from("step of for loop must be positive")),
+                        ir::SourceLocation {
+                            file: self.current_file.clone(),
+                            line: for_loop.line,
+                            text: String::from("step > 0"),
+                        },
+                    ));
+                }
+                Some(step)
+            }
+            None => None,
+        };
+        let step = || match step {
+            Some(step) => ir::IntExpression::Var(step),
+            None => ir::IntExpression::Literal(1),
+        };
+        let op = || {
+            if inclusive {
+                ir::IntComparisonOp::LessThanEquals
+            } else {
+                ir::IntComparisonOp::LessThan
             }
+        };
+
+        let more = self.temporary(
+            TypedExpression::Boolean(Self::int_comparison(
+                ir::IntExpression::Var(counter),
+                op(),
+                ir::IntExpression::Var(end),
+            )),
+            &mut statements,
+        );
+        // `c + n < end` is `c < end - n`, which only works if `end - n` fits in an int
+        let fits = self.temporary(
+            TypedExpression::Boolean(Self::int_comparison(
+                ir::IntExpression::BinaryOperation(
+                    Box::new(ir::IntExpression::Literal(IntType::MIN)),
+                    ir::IntBinaryOp::Plus,
+                    Box::new(step()),
+                ),
+                ir::IntComparisonOp::LessThanEquals,
+                ir::IntExpression::Var(end),
+            )),
+            &mut statements,
+        );
+        let last = self.int_temporary(
+            ir::IntExpression::BinaryOperation(
+                Box::new(ir::IntExpression::Var(end)),
+                ir::IntBinaryOp::Minus,
+                Box::new(step()),
+            ),
+            &mut statements,
+        );
+
+        let identifier = self.loop_variable(for_loop, ir::VarType::Int);
+        let start = vec![ir::Statement::Assignment(
+            identifier,
+            ir::AssignmentStatement::Int(ir::IntExpression::Var(counter)),
+        )];
+
+        let has_next = ir::BooleanExpression::If(
+            Box::new(ir::BooleanExpression::Var(fits)),
+            Box::new(Self::int_comparison(
+                ir::IntExpression::Var(counter),
+                op(),
+                last,
+            )),
+            Box::new(ir::BooleanExpression::Literal(false)),
+        );
+        let increment = vec![
+            ir::Statement::Assignment(more, ir::AssignmentStatement::Boolean(has_next)),
+            ir::Statement::CompoundAssignment(counter, ir::IntBinaryOp::Plus, step()),
+        ];
+        let body =
+            self.resolve_loop_body(for_loop, identifier, ir::VarType::Int, start, increment)?;
+
+        statements.push(ir::Statement::While(ir::BooleanExpression::Var(more), body));
+
+        Ok(statements)
+    }
+
+    fn int_comparison(
+        left: ir::IntExpression,
+        op: ir::IntComparisonOp,
+        right: ir::IntExpression,
+    ) -> ir::BooleanExpression {
+        ir::BooleanExpression::Comparison(ir::ComparisonExpression::IntComparison(
+            Box::new(left),
+            vec![(op, right)],
+        ))
+    }
+
+    /// Lowers `for x in xs { body }` to
+    /// `l = xs; n = 0; while n < len(l) { x = l[n]; body; n += 1 }`, the list is evaluated once
+    fn resolve_list_for(
+        &mut self,
+        for_loop: &ast::ForLoop,
+        list: &ast::Expression,
+    ) -> anyhow::Result<Vec<ir::Statement>> {
+        let mut statements = Vec::new();
+
+        let (element_type, list) = self.resolve_expression(list)?.into_list()?;
+        let list = self.temporary(TypedExpression::List(element_type, list), &mut statements);
+        let index = self.temporary(
+            TypedExpression::Int(ir::IntExpression::Literal(0)),
+            &mut statements,
+        );
+
+        let identifier = self.loop_variable(for_loop, element_type.var_type());
+        let element = TypedExpression::element(
+            element_type,
+            ir::ListExpression::Var(list),
+            Some(ir::IntExpression::Var(index)),
+        );
+        let start = vec![ir::Statement::Assignment(
+            identifier,
+            element.into_assignment(),
+        )];
+
+        let increment = vec![ir::Statement::CompoundAssignment(
+            index,
+            ir::IntBinaryOp::Plus,
+            ir::IntExpression::Literal(1),
+        )];
+        let body = self.resolve_loop_body(
+            for_loop,
+            identifier,
+            element_type.var_type(),
+            start,
+            increment,
+        )?;
+
+        let condition = ir::BooleanExpression::Comparison(ir::ComparisonExpression::IntComparison(
+            Box::new(ir::IntExpression::Var(index)),
+            vec![(
+                ir::IntComparisonOp::LessThan,
+                ir::IntExpression::Length(Box::new(ir::ListExpression::Var(list))),
+            )],
+        ));
+        statements.push(ir::Statement::While(condition, body));
+
+        Ok(statements)
+    }
+
+    fn resolve_function_body(
+        &mut self,
+        statements: &[ast::Statement],
+        parameters: &[(String, ir::VarType)],
+        can_return: bool,
+        return_type: Option<ir::VarType>,
+    ) -> anyhow::Result<(Vec<ir::Statement>, Vec<ir::Parameter>, ir::Locals)> {
+        self.function_metadata = Some(FunctionMetadata {
+            locals: Vec::new(),
+            can_return,
+            return_type,
+        });
+        self.scopes.push(VarScope {
+            variables: HashMap::new(),
+        });
+
+        let mut ir_parameters = Vec::new();
+        for (name, var_type) in parameters {
+            let identifier = self.get_free_identifier();
+            self.function_metadata
+                .as_mut()
+                .unwrap()
+                .locals
+                .push((identifier, *var_type));
+            self.scopes.last_mut().unwrap().variables.insert(
+                name.clone(),
+                VarInfo {
+                    identifier,
+                    var_type: *var_type,
+                    mutable: false,
+                    line: None,
+                },
+            );
+
+            ir_parameters.push(ir::Parameter {
+                name: name.clone(),
+                identifier,
+                var_type: *var_type,
+            });
         }
+
+        let ir_statements = self.resolve_statements(statements)?;
+
+        self.scopes.pop();
+        let locals = self.function_metadata.take().unwrap().locals;
+
+        Ok((ir_statements, ir_parameters, locals))
     }
 
+    /// Resolves a top level statement, returns `None` for statements that are stripped in the current mode.
     pub fn resolve_top_level_statement(
         &mut self,
         statement: &ast::ToplevelStatement,
-    ) -> anyhow::Result<ir::ToplevelStatement> {
+    ) -> anyhow::Result<Option<ir::ToplevelStatement>> {
         match statement {
             ast::ToplevelStatement::MainFunction(statements) => {
-                self.function_metadata = Some(FunctionMetadata { locals: Vec::new() });
-                self.scopes.push(VarScope {
-                    parent: None,
-                    variables: HashMap::new(),
-                });
+                let (body, parameters, locals) =
+                    self.resolve_function_body(statements, &[], true, Some(ir::VarType::Int))?;
+
+                if self.test_mode {
+                    return Ok(None);
+                }
+
+                Ok(Some(ir::ToplevelStatement::Function {
+                    name: String::from("main"),
+                    parameters,
+                    return_type: Some(ir::VarType::Int),
+                    body,
+                    locals,
+                }))
+            }
+            ast::ToplevelStatement::Test(name, statements) => {
+                let name = match &self.current_module {
+                    Some(module) => format!("{module}::{name}"),
+                    None => name.clone(),
+                };
+                let name = &name;
 
-                let mut ir_statements = Vec::new();
+                if self.test_names.contains(name) {
+                    Err(TypeError(format!(
+                        "test {name:?} is defined more than once"
+                    )))?;
+                }
+                self.test_names.push(name.clone());
 
-                for statement in statements {
-                    let ir_statement = self.resolve_statement(statement)?;
+                let (body, _, locals) = self.resolve_function_body(statements, &[], false, None)?;
 
-                    ir_statements.push(ir_statement);
+                if !self.test_mode {
+                    return Ok(None);
                 }
 
-                Ok(ir::ToplevelStatement::Function {
-                    name: String::from("main"),
-                    body: ir_statements,
-                    locals: self.function_metadata.as_ref().unwrap().locals.clone(),
+                Ok(Some(ir::ToplevelStatement::Test {
+                    name: name.clone(),
+                    body,
+                    locals,
+                }))
+            }
+            // the module loader has already brought the imported module into the program
+            ast::ToplevelStatement::Import(_) => Ok(None),
+            // extern functions are declared up front by `resolve_function_signatures`
+            ast::ToplevelStatement::ExternFunction(_) => Ok(None),
+            // constants and globals are resolved up front by `resolve_globals`
+            ast::ToplevelStatement::Const(_) | ast::ToplevelStatement::Static(_) => Ok(None),
+            ast::ToplevelStatement::ExportFunction(signature, statements) => {
+                let symbol = Self::symbol(&self.qualified_name(&signature.name)?);
+                let function_signature = &self.functions[&symbol];
+                let return_type = function_signature.return_type;
+                let parameters = signature
+                    .parameters
+                    .iter()
+                    .map(|(name, _)| name.clone())
+                    .zip(function_signature.parameters.iter().copied())
+                    .collect::<Vec<_>>();
+
+                // without control flow a return at the end is the only way to return a value
+                if return_type.is_some()
+                    && !matches!(statements.last(), Some(ast::Statement::Return(_)))
+                {
+                    Err(TypeError(format!(
+                        "function {} must end with a return statement",
+                        signature.name
+                    )))?;
+                }
+
+                let (body, parameters, locals) =
+                    self.resolve_function_body(statements, &parameters, true, return_type)?;
+
+                Ok(Some(ir::ToplevelStatement::Function {
+                    name: symbol,
+                    parameters,
+                    return_type,
+                    body,
+                    locals,
+                }))
+            }
+        }
+    }
+
+    /// Makes `module` the module whose names are resolved
+    fn enter_module(&mut self, module: &ast::ProgramModule) {
+        self.current_module = (!module.is_root).then(|| module.name.clone());
+        self.current_imports = module.imports.clone();
+        self.current_file = module.file.clone();
+    }
+
+    /// Declares the extern and exported functions of every module, so they can be called regardless of declaration order.
+    ///
+    /// Extern functions keep the name of the C function, exported functions of imported modules are exported under
+    /// their symbol, like `util__helper` for `util::helper`.
+    fn resolve_function_signatures(
+        &mut self,
+        program: &ast::Program,
+    ) -> anyhow::Result<Vec<ir::ToplevelStatement>> {
+        let mut declarations = Vec::new();
+        // qualified names of the exported functions by their symbol
+        let mut defined: HashMap<String, String> = HashMap::new();
+
+        for module in &program.0 {
+            self.enter_module(module);
+
+            for statement in &module.module.0 {
+                let name = match statement {
+                    ast::ToplevelStatement::ExternFunction(signature)
+                    | ast::ToplevelStatement::ExportFunction(signature, _) => {
+                        self.qualified_name(&signature.name)?
+                    }
+                    _ => continue,
+                };
+                let (signature, symbol) = match statement {
+                    ast::ToplevelStatement::ExportFunction(signature, _) => {
+                        let symbol = Self::symbol(&name);
+                        if code_gen::is_reserved_name(&symbol) {
+                            Err(TypeError(format!(
+                                "function {name} can't be exported, the name is reserved for the runtime"
+                            )))?;
+                        }
+                        match defined.get(&symbol) {
+                            Some(other) if other == &name => Err(TypeError(format!(
+                                "function {name} is defined more than once"
+                            )))?,
+                            Some(other) => Err(TypeError(format!(
+                                "functions {other} and {name} are both exported as {symbol}"
+                            )))?,
+                            None => {}
+                        }
+                        defined.insert(symbol.clone(), name.clone());
+                        (signature, symbol)
+                    }
+                    ast::ToplevelStatement::ExternFunction(signature) => {
+                        (signature, signature.name.clone())
+                    }
+                    _ => unreachable!(),
+                };
+
+                let resolved = FunctionSignature {
+                    parameters: signature
+                        .parameters
+                        .iter()
+                        .map(|(_, type_name)| Self::resolve_type(type_name))
+                        .collect::<anyhow::Result<_>>()?,
+                    return_type: signature
+                        .return_type
+                        .as_deref()
+                        .map(Self::resolve_type)
+                        .transpose()?,
+                };
+
+                if let Some(other) = self.function_symbols.insert(name.clone(), symbol.clone()) {
+                    if other != symbol {
+                        Err(TypeError(format!(
+                            "function {name} is declared more than once"
+                        )))?;
+                    }
+                }
+
+                match self.functions.get(&symbol) {
+                    // the same function can be declared by multiple modules
+                    Some(existing) if existing == &resolved => continue,
+                    Some(_) => Err(TypeError(format!(
+                        "function {symbol} is declared with different signatures"
+                    )))?,
+                    None => {}
+                }
+
+                if let ast::ToplevelStatement::ExternFunction(_) = statement {
+                    declarations.push(ir::ToplevelStatement::ExternFunction {
+                        name: symbol.clone(),
+                        parameters: resolved.parameters.clone(),
+                        return_type: resolved.return_type,
+                    });
+                }
+                self.functions.insert(symbol, resolved);
+            }
+        }
+
+        Ok(declarations)
+    }
+
+    /// Evaluates the value of a constant or global at compile time
+    fn evaluate_constant(
+        &self,
+        expression: &ast::Expression,
+        name: &str,
+    ) -> anyhow::Result<ir::Constant> {
+        let int = |expression| -> anyhow::Result<IntType> {
+            match self.evaluate_constant(expression, name)? {
+                ir::Constant::Int(int) => Ok(int),
+                other => Err(TypeError(format!(
+                    "the value of {name} uses {:?} where Int is expected",
+                    other.var_type()
+                )))?,
+            }
+        };
+        let boolean = |expression| -> anyhow::Result<bool> {
+            match self.evaluate_constant(expression, name)? {
+                ir::Constant::Boolean(boolean) => Ok(boolean),
+                other => Err(TypeError(format!(
+                    "the value of {name} uses {:?} where Boolean is expected",
+                    other.var_type()
+                )))?,
+            }
+        };
+        let overflow = || TypeError(format!("the value of {name} overflows"));
+
+        Ok(match expression {
+            ast::Expression::Literal(ast::Literal::Integer(int)) => ir::Constant::Int(*int),
+            ast::Expression::Literal(ast::Literal::Boolean(boolean)) => {
+                ir::Constant::Boolean(*boolean)
+            }
+            ast::Expression::Literal(ast::Literal::String(string)) => {
+                ir::Constant::String(string.clone())
+            }
+            ast::Expression::Literal(ast::Literal::Char(value)) => ir::Constant::Char(*value),
+            ast::Expression::Literal(ast::Literal::Variable(variable)) => {
+                self.constant(variable)?.cloned().ok_or(TypeError(format!(
+                    "the value of {name} must be a constant expression, but uses {variable}"
+                )))?
+            }
+            ast::Expression::Call(function, _) => Err(TypeError(format!(
+                "the value of {name} must be a constant expression, but calls {function}"
+            )))?,
+            ast::Expression::List(_) | ast::Expression::Index(..) => Err(TypeError(format!(
+                "the value of {name} must be a constant expression, but uses a list"
+            )))?,
+            ast::Expression::Prefix(ast::PrefixOp::Negate, expression, _) => {
+                ir::Constant::Int(int(expression)?.checked_neg().ok_or_else(overflow)?)
+            }
+            ast::Expression::Prefix(ast::PrefixOp::Not, expression, _) => {
+                ir::Constant::Boolean(!boolean(expression)?)
+            }
+            ast::Expression::BinaryOp(
+                left,
+                op @ (ast::BinaryOp::And | ast::BinaryOp::Or),
+                right,
+            ) => {
+                let (left, right) = (boolean(left)?, boolean(right)?);
+                ir::Constant::Boolean(match op {
+                    ast::BinaryOp::And => left && right,
+                    _ => left || right,
                 })
             }
+            ast::Expression::BinaryOp(left, op, right) => {
+                let (left, right) = (int(left)?, int(right)?);
+                let result = match op {
+                    ast::BinaryOp::Plus => left.checked_add(right),
+                    ast::BinaryOp::Minus => left.checked_sub(right),
+                    ast::BinaryOp::Multiply => left.checked_mul(right),
+                    ast::BinaryOp::Divide if right == 0 => {
+                        Err(TypeError(format!("the value of {name} divides by zero")))?
+                    }
+                    ast::BinaryOp::Divide => left.checked_div(right),
+                    ast::BinaryOp::And | ast::BinaryOp::Or => unreachable!(),
+                };
+                ir::Constant::Int(result.ok_or_else(overflow)?)
+            }
+            ast::Expression::If(condition, then_value, else_value, _) => {
+                let then_value = self.evaluate_constant(then_value, name)?;
+                let else_value = self.evaluate_constant(else_value, name)?;
+                if then_value.var_type() != else_value.var_type() {
+                    Err(TypeError(format!(
+                        "branches of if have different types: {:?} and {:?}",
+                        then_value.var_type(),
+                        else_value.var_type()
+                    )))?;
+                }
+                if boolean(condition)? {
+                    then_value
+                } else {
+                    else_value
+                }
+            }
+            ast::Expression::Comparison(left_side, chains, _) => {
+                // chars are compared like at runtime, by their code points
+                let mut left = self.evaluate_constant(left_side, name)?;
+                let mut result = true;
+                for (op, expression) in chains {
+                    let right = self.evaluate_constant(expression, name)?;
+                    let ordering = match (&left, &right) {
+                        (ir::Constant::Int(left), ir::Constant::Int(right)) => left.cmp(right),
+                        (ir::Constant::Char(left), ir::Constant::Char(right)) => left.cmp(right),
+                        (ir::Constant::Char(_), other) => Err(TypeError(format!(
+                            "the value of {name} uses {:?} where Char is expected",
+                            other.var_type()
+                        )))?,
+                        (ir::Constant::Int(_), other) | (other, _) => Err(TypeError(format!(
+                            "the value of {name} uses {:?} where Int is expected",
+                            other.var_type()
+                        )))?,
+                    };
+                    result &= match op {
+                        ast::ComparisonOp::Equals => ordering.is_eq(),
+                        ast::ComparisonOp::NotEquals => ordering.is_ne(),
+                        ast::ComparisonOp::LessThan => ordering.is_lt(),
+                        ast::ComparisonOp::LessThanEquals => ordering.is_le(),
+                        ast::ComparisonOp::GreaterThan => ordering.is_gt(),
+                        ast::ComparisonOp::GreaterThanEquals => ordering.is_ge(),
+                    };
+                    left = right;
+                }
+                ir::Constant::Boolean(result)
+            }
+        })
+    }
+
+    /// Evaluates every `const` and declares every `static mut`, in the order they are defined in
+    fn resolve_globals(
+        &mut self,
+        program: &ast::Program,
+    ) -> anyhow::Result<Vec<ir::ToplevelStatement>> {
+        let mut globals = Vec::new();
+
+        for module in &program.0 {
+            self.enter_module(module);
+
+            for statement in &module.module.0 {
+                let (definition, mutable) = match statement {
+                    ast::ToplevelStatement::Const(definition) => (definition, false),
+                    ast::ToplevelStatement::Static(definition) => (definition, true),
+                    _ => continue,
+                };
+                let name = &self.qualified_name(&definition.name)?;
+
+                if self.constants.contains_key(name) || self.globals.contains_key(name) {
+                    Err(TypeError(format!(
+                        "global {name} is defined more than once"
+                    )))?;
+                }
+
+                let var_type = Self::resolve_type(&definition.type_name)?;
+                let value = self.evaluate_constant(&definition.value, name)?;
+                if value.var_type() != var_type {
+                    Err(TypeError(format!(
+                        "{name} is declared as {var_type:?}, but its value is {:?}",
+                        value.var_type()
+                    )))?;
+                }
+
+                if !mutable {
+                    self.constants.insert(name.clone(), value);
+                    continue;
+                }
+
+                let identifier = self.get_free_identifier();
+                self.globals.insert(
+                    name.clone(),
+                    VarInfo {
+                        identifier,
+                        var_type,
+                        mutable: true,
+                        line: Some(definition.line),
+                    },
+                );
+                globals.push(ir::ToplevelStatement::Global {
+                    name: Self::symbol(name),
+                    identifier,
+                    value,
+                });
+            }
         }
+
+        Ok(globals)
     }
 
-    pub fn resolve_module(&mut self, module: &ast::Module) -> anyhow::Result<ir::Module> {
-        let mut ir_statements = Vec::new();
+    /// Resolves every module of the program into a single ir module, errors name the module they are in
+    pub fn resolve_program(&mut self, program: &ast::Program) -> anyhow::Result<ir::Module> {
+        self.resolve_modules(program).with_context(|| FileContext {
+            action: "Resolving",
+            file: self.current_file.clone(),
+        })
+    }
 
-        for statement in &module.0 {
-            let ir_statement = self.resolve_top_level_statement(statement)?;
+    fn resolve_modules(&mut self, program: &ast::Program) -> anyhow::Result<ir::Module> {
+        let mut ir_statements = self.resolve_function_signatures(program)?;
+        ir_statements.extend(self.resolve_globals(program)?);
 
-            ir_statements.push(ir_statement);
+        for module in &program.0 {
+            self.enter_module(module);
+
+            for statement in &module.module.0 {
+                if let ast::ToplevelStatement::MainFunction(_) = statement {
+                    if let Some(main_file) = &self.main_file {
+                        Err(TypeError(format!(
+                            "only one main function ($) is allowed, found one in {main_file} and {}",
+                            module.file
+                        )))?;
+                    }
+                    self.main_file = Some(module.file.clone());
+                }
+
+                if let Some(ir_statement) = self.resolve_top_level_statement(statement)? {
+                    ir_statements.push(ir_statement);
+                }
+            }
+        }
+
+        if self.test_mode {
+            ir_statements.push(ir::ToplevelStatement::TestHarness(self.test_names.clone()));
         }
 
         Ok(ir::Module(ir_statements))
     }
 
-    pub fn new() -> Self {
+    /// In `test_mode` the main function is stripped and test blocks are compiled into a test harness instead.
+    pub fn new(test_mode: bool) -> Self {
         Self {
             scopes: Vec::new(),
             function_metadata: None,
             current_identifier: 0,
+            test_mode,
+            test_names: Vec::new(),
+            current_module: None,
+            current_imports: Vec::new(),
+            main_file: None,
+            current_file: String::new(),
+            functions: HashMap::new(),
+            function_symbols: HashMap::new(),
+            variables: HashMap::new(),
+            warnings: Vec::new(),
+            constants: HashMap::new(),
+            globals: HashMap::new(),
         }
     }
+
+    /// Where every variable declared with `let` comes from, filled in by `resolve_program`
+    pub fn variables(&self) -> &HashMap<ir::VariableIdentifier, ir::VariableOrigin> {
+        &self.variables
+    }
+
+    /// The warnings found while resolving the program, the lints on the ir come on top of these
+    pub fn take_warnings(&mut self) -> Vec<ir::lints::Warning> {
+        std::mem::take(&mut self.warnings)
+    }
 }
@@ -0,0 +1,93 @@
+use viv_script::{compile, parsing::tokens::TokenType, CompilerOptions, Severity};
+
+const FILE_NAME: &str = "in_memory.viv";
+
+#[test]
+fn compiles_source_without_a_file() {
+    let source = "${\n    let x = 20;\n    print x + 1;\n}\n";
+    let compilation = compile(FILE_NAME, source, &CompilerOptions::default());
+
+    assert!(
+        compilation.diagnostics.is_empty(),
+        "{:?}",
+        compilation.diagnostics
+    );
+    assert_eq!(compilation.tokens.len(), 1);
+    assert_eq!(compilation.tokens[0].file, FILE_NAME);
+    assert_eq!(compilation.tokens[0].tokens[0]._type, TokenType::Dollar);
+    assert_eq!(
+        compilation.tokens[0].tokens.last().unwrap()._type,
+        TokenType::Eof
+    );
+
+    let ast = compilation.ast.as_ref().unwrap();
+    assert_eq!(ast.0.len(), 1);
+    assert!(ast.0[0].is_root);
+
+    let ir = compilation.ir.as_ref().unwrap().to_string();
+    assert!(ir.contains("print"), "{ir}");
+    assert!(compilation.llvm_ir.is_some());
+    assert!(!compilation.object.unwrap().is_empty());
+}
+
+#[test]
+fn reports_type_errors_as_diagnostics() {
+    let source = "const LIMIT: int = 100;\n\n${\n    set LIMIT = 5;\n}\n";
+    let compilation = compile(FILE_NAME, source, &CompilerOptions::default());
+
+    let error = compilation.first_error().unwrap();
+    assert!(
+        error.message.contains("cannot assign to constant LIMIT"),
+        "{error}"
+    );
+    // the stages before the failing one are still filled in
+    assert!(compilation.ast.is_some());
+    assert!(compilation.ir.is_none());
+    assert!(compilation.object.is_none());
+}
+
+#[test]
+fn reports_tokenizer_errors_with_their_position() {
+    let compilation = compile(FILE_NAME, "${\n    print 1 @ 2;\n}\n", &Default::default());
+
+    let error = compilation.first_error().unwrap();
+    assert_eq!(error.file.as_deref(), Some(FILE_NAME));
+    assert_eq!(error.line, Some(2));
+    assert!(error.message.contains("Unexpected character: @"), "{error}");
+    assert!(compilation.tokens.is_empty());
+}
+
+#[test]
+fn reports_errors_in_the_module_they_are_in() {
+    let module = "integration_tests/modules/not_imported.viv";
+    let source = format!("import \"{module}\";\n\n${{\n    print 1;\n}}\n");
+    let compilation = compile(FILE_NAME, &source, &CompilerOptions::default());
+
+    let error = compilation.first_error().unwrap();
+    assert_eq!(error.file.as_deref(), Some(module));
+    assert!(error.message.contains("is not imported by"), "{error}");
+}
+
+#[test]
+fn reports_lints_as_warnings_or_errors() {
+    let source = "${\n    let unused = 1;\n}\n";
+
+    let compilation = compile(FILE_NAME, source, &CompilerOptions::default());
+    assert_eq!(compilation.diagnostics.len(), 1);
+    let warning = &compilation.diagnostics[0];
+    assert_eq!(warning.severity, Severity::Warning);
+    assert_eq!(warning.lint.as_deref(), Some("unused_variable"));
+    assert_eq!(warning.line, Some(2));
+    assert!(compilation.object.is_some());
+
+    let options = CompilerOptions {
+        deny_warnings: true,
+        ..Default::default()
+    };
+    let compilation = compile(FILE_NAME, source, &options);
+    assert_eq!(
+        compilation.first_error().unwrap().message,
+        "unused variable `unused`"
+    );
+    assert!(compilation.object.is_none());
+}
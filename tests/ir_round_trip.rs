@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use viv_script::{ir, parsing, type_analyzer};
+
+/// Prints the ir, parses it back and checks that printing the result gives the same text
+fn assert_round_trip(file: &str, module: &ir::Module) {
+    let printed = module.to_string();
+    let parsed = ir::parse(&printed).unwrap_or_else(|error| panic!("{file}: {error:#}\n{printed}"));
+    assert_eq!(
+        printed,
+        parsed.to_string(),
+        "ir of {file} prints differently after parsing it"
+    );
+}
+
+/// The ir of every program in the integration tests, in and out of test mode, and every hand written ir file
+#[test]
+fn printed_ir_parses_back_to_the_same_ir() {
+    let mut files = std::fs::read_dir("integration_tests")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let mut checked = 0;
+    for path in &files {
+        let file = path.to_str().unwrap();
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("viv") => {
+                // tests of compile errors don't get as far as the ir
+                let Ok((program, _)) = parsing::parse_program(file, None) else {
+                    continue;
+                };
+                for test_mode in [false, true] {
+                    let mut analyzer = type_analyzer::Analyzer::new(test_mode);
+                    if let Ok(module) = analyzer.resolve_program(&program) {
+                        assert_round_trip(file, &module);
+                        checked += 1;
+                    }
+                }
+            }
+            Some("vir") => {
+                let source = std::fs::read_to_string(path).unwrap();
+                if let Ok(module) = ir::parse(&source) {
+                    assert_round_trip(file, &module);
+                    checked += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    assert!(
+        checked > 0,
+        "no ir found in {}",
+        Path::new("integration_tests").display()
+    );
+}
@@ -0,0 +1,54 @@
+use viv_script::{compile, json::JsonOutput, CompilerOptions};
+
+const PROGRAM: &str = "tests/json/program.viv";
+const EXPECTED: &str = "tests/json/program.jsonl";
+
+/// Every kind of `--format json` output for a small program, compared against a golden file.
+///
+/// When the output changes on purpose, increase `SCHEMA_VERSION` and rerun with `VIV_BLESS=1` to update the file.
+#[test]
+fn json_output_matches_golden_file() {
+    let source = std::fs::read_to_string(PROGRAM).unwrap();
+    let compilation = compile(PROGRAM, &source, &CompilerOptions::default());
+
+    let mut lines = compilation
+        .tokens
+        .iter()
+        .map(|module| {
+            JsonOutput::Tokens {
+                file: &module.file,
+                tokens: &module.tokens,
+            }
+            .to_line()
+        })
+        .collect::<Vec<_>>();
+    lines.push(
+        JsonOutput::Ast {
+            program: compilation.ast.as_ref().unwrap(),
+        }
+        .to_line(),
+    );
+    lines.push(
+        JsonOutput::Ir {
+            module: compilation.ir.as_ref().unwrap(),
+        }
+        .to_line(),
+    );
+    lines.push(
+        JsonOutput::Diagnostics {
+            diagnostics: &compilation.diagnostics,
+        }
+        .to_line(),
+    );
+
+    if std::env::var_os("VIV_BLESS").is_some() {
+        std::fs::write(EXPECTED, lines.join("\n") + "\n").unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(EXPECTED).unwrap();
+    for (index, (expected, actual)) in expected.lines().zip(&lines).enumerate() {
+        assert_eq!(expected, actual, "line {} of {EXPECTED} differs", index + 1);
+    }
+    assert_eq!(expected.lines().count(), lines.len());
+}